@@ -9,4 +9,4 @@ mod util;
 pub mod load_file;
 
 // Re-exports
-pub use load_file::Warrior;
+pub use load_file::{ResolvedWarrior, Warrior};
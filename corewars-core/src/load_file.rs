@@ -3,19 +3,30 @@ use std::{convert::TryInto, fmt};
 use lazy_static::lazy_static;
 use maplit::hashmap;
 
+mod encoding;
 mod metadata;
 mod offset;
 mod program;
+mod resolved;
+#[cfg(feature = "serde")]
+mod serialize;
 mod types;
 
+pub use encoding::DecodeError;
 pub use metadata::Metadata;
 pub use offset::Offset;
 pub use program::{Instructions, LabelMap, Program};
-pub use types::{AddressMode, Modifier, Opcode, PseudoOpcode, Value};
+pub use resolved::{ResolvedWarrior, UnresolvedLabel};
+pub use types::{AddressMode, Modifier, Opcode, PseudoOpcode, Standard, Value};
+
+/// The pMARS version whose predefined labels this implementation follows,
+/// in the same format as pMARS' `VERSION` label (i.e. 0.9.4 is 94).
+pub const PMARS_VERSION: u32 = 94;
 
 lazy_static! {
-    // TODO: handle command-line constant redefinition and things like
-    // CURLINE, VERSION, WARRIORS, PSPACESIZE
+    /// The default values of predefined constants. These can be overridden
+    /// when parsing, to assemble a warrior for a different environment.
+    /// `CURLINE` is also predefined, but depends on where it is used.
     pub static ref DEFAULT_CONSTANTS: LabelMap = hashmap! {
         "CORESIZE".into() => 8000,
         "MAXPROCESSES".into() => 8000,
@@ -23,11 +34,28 @@ lazy_static! {
         "MAXLENGTH".into() => 100,
         "MINDISTANCE".into() => 100,
         "ROUNDS".into() => 1,
+        "PSPACESIZE".into() => 500,
+        "READLIMIT".into() => 8000,
+        "WRITELIMIT".into() => 8000,
+        "VERSION".into() => PMARS_VERSION,
+        "WARRIORS".into() => 1,
     };
 }
 
+/// The values of constants which are derived from the core size, unless they
+/// are given explicitly. These match the defaults used by pMARS.
+#[must_use]
+pub fn derived_constants(core_size: u32) -> LabelMap {
+    hashmap! {
+        "PSPACESIZE".into() => (core_size / 16).max(1),
+        "READLIMIT".into() => core_size,
+        "WRITELIMIT".into() => core_size,
+    }
+}
+
 /// The main public struct used to represent a Redcode warrior
 #[derive(Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Warrior {
     pub program: Program,
     pub metadata: Metadata,
@@ -59,6 +87,7 @@ impl Warrior {
 //
 // See docs/icws94.txt:891
 #[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Field {
     pub address_mode: AddressMode,
     pub value: Value,
@@ -115,6 +144,7 @@ impl Field {
 }
 
 #[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Instruction {
     pub opcode: Opcode,
     pub modifier: Modifier,
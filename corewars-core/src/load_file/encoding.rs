@@ -0,0 +1,309 @@
+//! A compact binary encoding of a warrior's code, and a fingerprint derived
+//! from it. Metadata is not encoded, so warriors which assemble to the same
+//! code are encoded the same way, however their source was written.
+//!
+//! The encoding is made of little-endian integers: the origin as a `u32`,
+//! the number of instructions as a `u32`, then 10 bytes for each instruction.
+//! An instruction is a `u16` with its opcode in the top 5 bits, then 3 bits
+//! each for its modifier, A-field address mode and B-field address mode,
+//! followed by the `u32` values of its A-field and B-field.
+
+use std::convert::{TryFrom, TryInto};
+use std::error::Error;
+use std::fmt;
+
+use super::{
+    AddressMode, Field, Instruction, Modifier, Offset, Opcode, Program, ResolvedWarrior, Value,
+    Warrior,
+};
+
+// The position of each value in these tables is its code in the encoding,
+// so new values must only be added at the end.
+
+const OPCODES: [Opcode; 19] = {
+    use Opcode::{
+        Add, Cmp, Dat, Div, Djn, Jmn, Jmp, Jmz, Ldp, Mod, Mov, Mul, Nop, Seq, Slt, Sne, Spl, Stp,
+        Sub,
+    };
+    [
+        Dat, Mov, Add, Sub, Mul, Div, Mod, Jmp, Jmz, Jmn, Djn, Cmp, Slt, Spl, Seq, Sne, Nop, Ldp,
+        Stp,
+    ]
+};
+
+const MODIFIERS: [Modifier; 7] = {
+    use Modifier::{A, AB, B, BA, F, I, X};
+    [A, B, AB, BA, F, X, I]
+};
+
+const ADDRESS_MODES: [AddressMode; 8] = {
+    use AddressMode::{
+        Direct, Immediate, IndirectA, IndirectB, PostIncIndirectA, PostIncIndirectB,
+        PreDecIndirectA, PreDecIndirectB,
+    };
+    [
+        Immediate,
+        Direct,
+        IndirectA,
+        IndirectB,
+        PreDecIndirectA,
+        PreDecIndirectB,
+        PostIncIndirectA,
+        PostIncIndirectB,
+    ]
+};
+
+const INSTRUCTION_LEN: usize = 10;
+
+/// The error when bytes are not a valid encoding of a warrior
+#[derive(Debug, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The bytes ended before the end of the encoding
+    TooShort,
+
+    /// There were bytes after the end of the encoding
+    TooLong,
+
+    /// The instruction at this index has an unknown opcode, modifier or
+    /// address mode, or a field value larger than `i32::MAX`
+    InvalidInstruction(usize),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::TooShort => write!(formatter, "encoded warrior ended unexpectedly"),
+            Self::TooLong => write!(formatter, "unexpected bytes after encoded warrior"),
+            Self::InvalidInstruction(index) => {
+                write!(formatter, "encoded instruction {} is invalid", index)
+            }
+        }
+    }
+}
+
+impl Error for DecodeError {}
+
+impl ResolvedWarrior {
+    /// Encode this warrior's program, for a core of the given size. Field
+    /// values are adjusted to be within the core, so e.g. `JMP -1` and
+    /// `JMP 7999` are the same in a core of size 8000. The PIN and metadata
+    /// are not encoded.
+    ///
+    /// # Panics
+    /// If `core_size` is 0 or larger than `i32::MAX`.
+    #[must_use]
+    pub fn encode(&self, core_size: u32) -> Vec<u8> {
+        let program = &self.program;
+        let mut bytes = Vec::with_capacity(8 + program.instructions.len() * INSTRUCTION_LEN);
+
+        bytes.extend(program.origin.unwrap_or_default().to_le_bytes());
+        bytes.extend(program.len().to_le_bytes());
+
+        for instruction in &program.instructions {
+            let kind = code(&OPCODES, instruction.opcode) << 9
+                | code(&MODIFIERS, instruction.modifier) << 6
+                | code(&ADDRESS_MODES, instruction.a_field.address_mode) << 3
+                | code(&ADDRESS_MODES, instruction.b_field.address_mode);
+
+            bytes.extend(kind.to_le_bytes());
+            for field in [&instruction.a_field, &instruction.b_field] {
+                let value = Offset::new(field.value.unwrap(), core_size).value();
+                bytes.extend(value.to_le_bytes());
+            }
+        }
+
+        bytes
+    }
+
+    /// A fingerprint of this warrior's code, for a core of the given size.
+    /// Warriors with the same [encoding](Self::encode) have the same
+    /// fingerprint, which is stable between versions of this crate. It is a
+    /// 64-bit FNV-1a hash, so it is not suitable for cryptographic use.
+    ///
+    /// # Panics
+    /// If `core_size` is 0 or larger than `i32::MAX`.
+    #[must_use]
+    pub fn fingerprint(&self, core_size: u32) -> u64 {
+        const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+        const PRIME: u64 = 0x0100_0000_01b3;
+
+        self.encode(core_size)
+            .into_iter()
+            .fold(OFFSET_BASIS, |hash, byte| {
+                (hash ^ u64::from(byte)).wrapping_mul(PRIME)
+            })
+    }
+
+    /// Decode a warrior which was [encoded](Self::encode). The warrior has
+    /// an explicit origin, but no PIN or metadata.
+    pub fn decode(mut bytes: &[u8]) -> Result<Self, DecodeError> {
+        let origin = u32::from_le_bytes(take(&mut bytes)?);
+        let len = u32::from_le_bytes(take(&mut bytes)?);
+
+        let mut instructions = Vec::new();
+        for index in 0..len as usize {
+            let instruction = decode_instruction(take(&mut bytes)?)
+                .ok_or(DecodeError::InvalidInstruction(index))?;
+            instructions.push(instruction);
+        }
+
+        if !bytes.is_empty() {
+            return Err(DecodeError::TooLong);
+        }
+
+        Ok(Self(Warrior {
+            program: Program {
+                instructions,
+                origin: Some(origin),
+                pin: None,
+            },
+            ..Warrior::default()
+        }))
+    }
+}
+
+fn decode_instruction(bytes: [u8; INSTRUCTION_LEN]) -> Option<Instruction> {
+    let mut bytes = &bytes[..];
+    let kind = u16::from_le_bytes(take(&mut bytes).ok()?);
+
+    let mut field = |address_mode: u16| {
+        let value = u32::from_le_bytes(take(&mut bytes).ok()?);
+        Some(Field {
+            address_mode: lookup(&ADDRESS_MODES, address_mode & 0b111)?,
+            value: Value::Literal(i32::try_from(value).ok()?),
+        })
+    };
+    let a_field = field(kind >> 3)?;
+    let b_field = field(kind)?;
+
+    Some(Instruction {
+        opcode: lookup(&OPCODES, kind >> 9)?,
+        modifier: lookup(&MODIFIERS, kind >> 6 & 0b111)?,
+        a_field,
+        b_field,
+    })
+}
+
+/// The code of `value` in the encoding
+fn code<T: PartialEq>(table: &[T], value: T) -> u16 {
+    let position = table
+        .iter()
+        .position(|item| *item == value)
+        .expect("every value should have a code");
+
+    position.try_into().expect("codes should fit in a u16")
+}
+
+/// The value with a given code in the encoding
+fn lookup<T: Copy>(table: &[T], code: u16) -> Option<T> {
+    table.get(usize::from(code)).copied()
+}
+
+/// Read `N` bytes from the start of `bytes`.
+fn take<const N: usize>(bytes: &mut &[u8]) -> Result<[u8; N], DecodeError> {
+    if bytes.len() < N {
+        return Err(DecodeError::TooShort);
+    }
+
+    let (taken, rest) = bytes.split_at(N);
+    *bytes = rest;
+    Ok(taken.try_into().expect("slice should have N bytes"))
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn warrior(a_value: i32) -> ResolvedWarrior {
+        ResolvedWarrior(Warrior {
+            program: Program {
+                instructions: vec![
+                    Instruction::new(Opcode::Add, Field::immediate(4), Field::direct(3)),
+                    Instruction {
+                        opcode: Opcode::Jmp,
+                        modifier: Modifier::I,
+                        a_field: Field {
+                            address_mode: AddressMode::PostIncIndirectB,
+                            value: Value::Literal(a_value),
+                        },
+                        b_field: Field::immediate(0),
+                    },
+                ],
+                origin: Some(1),
+                pin: Some(5),
+            },
+            ..Warrior::default()
+        })
+    }
+
+    #[test]
+    fn every_value_has_a_code() {
+        for opcode in Opcode::iter_values() {
+            assert!(OPCODES.contains(opcode), "{:?}", opcode);
+        }
+        for modifier in Modifier::iter_values() {
+            assert!(MODIFIERS.contains(modifier), "{:?}", modifier);
+        }
+        for address_mode in AddressMode::iter_values() {
+            assert!(ADDRESS_MODES.contains(address_mode), "{:?}", address_mode);
+        }
+    }
+
+    #[test]
+    fn encodes_warrior() {
+        #[rustfmt::skip]
+        let expected = vec![
+            1, 0, 0, 0,
+            2, 0, 0, 0,
+            0x81, 0x04, 4, 0, 0, 0, 3, 0, 0, 0,
+            0xb8, 0x0f, 0x3f, 0x1f, 0, 0, 0, 0, 0, 0,
+        ];
+
+        assert_eq!(warrior(-1).encode(8000), expected);
+    }
+
+    #[test]
+    fn decodes_warrior() {
+        let decoded = ResolvedWarrior::decode(&warrior(-1).encode(8000)).unwrap();
+
+        assert_eq!(
+            decoded.program.instructions,
+            warrior(7999).program.instructions
+        );
+        assert_eq!(decoded.program.origin, Some(1));
+        assert_eq!(decoded.program.pin, None);
+    }
+
+    #[test]
+    fn rejects_invalid_encoding() {
+        let bytes = warrior(0).encode(8000);
+
+        assert_eq!(
+            ResolvedWarrior::decode(&bytes[..bytes.len() - 1]).unwrap_err(),
+            DecodeError::TooShort
+        );
+        assert_eq!(
+            ResolvedWarrior::decode(&[&bytes[..], &[0]].concat()).unwrap_err(),
+            DecodeError::TooLong
+        );
+
+        let mut invalid = bytes;
+        invalid[19] = 0xff;
+        assert_eq!(
+            ResolvedWarrior::decode(&invalid).unwrap_err(),
+            DecodeError::InvalidInstruction(1)
+        );
+    }
+
+    #[test]
+    fn fingerprints_code() {
+        let fingerprint = warrior(-1).fingerprint(8000);
+
+        assert_eq!(warrior(7999).fingerprint(8000), fingerprint);
+        assert_ne!(warrior(0).fingerprint(8000), fingerprint);
+        assert_ne!(warrior(-1).fingerprint(8001), fingerprint);
+        assert_eq!(fingerprint, 0x1667_1b74_8e92_17c7);
+    }
+}
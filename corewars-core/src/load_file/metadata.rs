@@ -3,11 +3,14 @@
 
 use std::fmt;
 
+use super::Standard;
+
 /// Metadata about a Redcode program that is stored in the comments.
 #[derive(Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Metadata {
-    /// The Redcode standard for this warrior (e.g. "94").
-    // TODO #38 handle directives like `redcode-94` etc.
+    /// The Redcode standard for this warrior, e.g. "94" for `;redcode-94`.
+    /// This is empty if the warrior only has a plain `;redcode` comment.
     pub redcode: Option<String>,
 
     /// The name of this warrior.
@@ -46,25 +49,41 @@ impl Metadata {
 
             let directive = split_comment[0].to_lowercase();
             match directive.as_ref() {
-                "redcode" => self.redcode = value,
+                "redcode" => self.redcode = Some(String::new()),
                 "name" => self.name = value,
                 "author" => self.author = value,
                 "date" => self.date = value,
                 "version" => self.version = value,
                 "strategy" => self.strategy = value,
                 "assert" => self.assertion = value,
-                _ => (),
+                other => {
+                    if let Some(standard) = other.strip_prefix("redcode-") {
+                        self.redcode = Some(standard.to_owned());
+                    }
+                }
             }
         }
 
         split_line[0].trim().to_string()
     }
+
+    /// The Redcode standard declared by a `;redcode-XX` comment, if it is a
+    /// known one. Variants such as `;redcode-94m` are treated as their base standard.
+    #[must_use]
+    pub fn standard(&self) -> Option<Standard> {
+        self.redcode.as_deref()?.get(..2)?.parse().ok()
+    }
 }
 
 impl fmt::Display for Metadata {
     fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self.redcode.as_deref() {
+            Some("") => writeln!(formatter, ";redcode")?,
+            Some(standard) => writeln!(formatter, ";redcode-{standard}")?,
+            None => {}
+        }
+
         for (field, name) in &[
-            (&self.redcode, "redcode"),
             (&self.name, "name"),
             (&self.author, "author"),
             (&self.version, "version"),
@@ -84,4 +103,29 @@ impl fmt::Display for Metadata {
     }
 }
 
-// TODO as part of #38 test parse_line
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn parses_redcode_standard() {
+        let mut metadata = Metadata::default();
+        assert_eq!(metadata.parse_line(";redcode"), "");
+        assert_eq!(metadata.redcode.as_deref(), Some(""));
+        assert_eq!(metadata.standard(), None);
+
+        assert_eq!(metadata.parse_line("; redcode-88 verbose"), "");
+        assert_eq!(metadata.redcode.as_deref(), Some("88"));
+        assert_eq!(metadata.standard(), Some(Standard::Icws88));
+
+        metadata.parse_line(";REDCODE-94m");
+        assert_eq!(metadata.redcode.as_deref(), Some("94m"));
+        assert_eq!(metadata.standard(), Some(Standard::Icws94));
+
+        metadata.parse_line(";redcode-x");
+        assert_eq!(metadata.standard(), None);
+        assert_eq!(metadata.to_string(), ";redcode-x\n");
+    }
+}
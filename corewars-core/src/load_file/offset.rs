@@ -39,6 +39,21 @@ impl Offset {
         self.value
     }
 
+    /// Get the value of the offset as the closest distance from the start of
+    /// the core, in either direction, e.g. `-1` instead of `CORESIZE - 1`.
+    /// A value exactly half way around the core is positive.
+    #[must_use]
+    pub fn signed_value(&self) -> i32 {
+        // The core size is checked to fit in an i32 when the offset is created
+        let value = self.value as i32;
+
+        if self.value > self.core_size / 2 {
+            value - self.core_size as i32
+        } else {
+            value
+        }
+    }
+
     /// Set the value of the offset. The value will be adjusted to be within
     /// bounds of the core size.
     pub fn set_value(&mut self, value: i32) {
@@ -156,6 +171,16 @@ mod tests {
         assert_eq!(offset.value(), 8);
     }
 
+    #[test]
+    fn signed_offset_value() {
+        assert_eq!(Offset::new(-1, 12).signed_value(), -1);
+        assert_eq!(Offset::new(5, 12).signed_value(), 5);
+        assert_eq!(Offset::new(6, 12).signed_value(), 6);
+        assert_eq!(Offset::new(7, 12).signed_value(), -5);
+        assert_eq!(Offset::new(2, 5).signed_value(), 2);
+        assert_eq!(Offset::new(3, 5).signed_value(), -2);
+    }
+
     #[test]
     fn add_offset() {
         let mut offset = Offset::new(0, 12);
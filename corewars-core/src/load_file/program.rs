@@ -12,6 +12,7 @@ pub type LabelMap = HashMap<String, u32>;
 
 /// A parsed Redcode program, which can be loaded into a core for execution
 #[derive(Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Program {
     /// The list of instructions in the program. These are one-to-one copied into
     /// the core when loaded for execution
@@ -19,6 +20,10 @@ pub struct Program {
 
     /// The program's entry point as an instruction index
     pub origin: Option<u32>,
+
+    /// The program's P-space identifier, given with `PIN`. Programs with the
+    /// same PIN share their P-space, otherwise each has its own.
+    pub pin: Option<i32>,
 }
 
 impl Program {
@@ -56,6 +61,7 @@ impl fmt::Debug for Program {
     fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
         writeln!(formatter, "{{")?;
         writeln!(formatter, "origin: {:?},", self.origin)?;
+        writeln!(formatter, "pin: {:?},", self.pin)?;
 
         let lines = self
             .instructions
@@ -79,6 +85,10 @@ impl fmt::Display for Program {
             self.origin.unwrap_or_default()
         ));
 
+        if let Some(pin) = self.pin {
+            lines.push(format!("{:<8}{}", PseudoOpcode::Pin, pin));
+        }
+
         for instruction in &self.instructions {
             lines.push(instruction.to_string());
         }
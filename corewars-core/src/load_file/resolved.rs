@@ -0,0 +1,119 @@
+//! A warrior which is known to be fully resolved, and so can be loaded into
+//! a core for execution.
+
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::ops::Deref;
+
+use super::{Value, Warrior};
+
+/// A [`Warrior`](Warrior) whose every field is a literal value. The only way
+/// to create one is to check a `Warrior` with [`TryFrom`](TryFrom), so a
+/// warrior with unresolved labels can't be loaded into a core.
+#[derive(Debug, Default)]
+pub struct ResolvedWarrior(pub(super) Warrior);
+
+impl ResolvedWarrior {
+    /// Get back the [`Warrior`](Warrior), e.g. to modify it
+    #[must_use]
+    pub fn into_inner(self) -> Warrior {
+        self.0
+    }
+}
+
+impl TryFrom<Warrior> for ResolvedWarrior {
+    type Error = UnresolvedLabel;
+
+    fn try_from(warrior: Warrior) -> Result<Self, Self::Error> {
+        for (index, instruction) in warrior.program.instructions.iter().enumerate() {
+            for field in [&instruction.a_field, &instruction.b_field] {
+                if let Value::Label(label) = &field.value {
+                    return Err(UnresolvedLabel {
+                        label: label.clone(),
+                        index,
+                    });
+                }
+            }
+        }
+
+        Ok(Self(warrior))
+    }
+}
+
+impl Deref for ResolvedWarrior {
+    type Target = Warrior;
+
+    fn deref(&self) -> &Warrior {
+        &self.0
+    }
+}
+
+impl fmt::Display for ResolvedWarrior {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(formatter)
+    }
+}
+
+/// The error when a [`Warrior`](Warrior) has a field which is still a label
+#[derive(Debug, PartialEq, Eq)]
+pub struct UnresolvedLabel {
+    /// The label which was not resolved
+    pub label: String,
+
+    /// The index of the instruction using the label
+    pub index: usize,
+}
+
+impl fmt::Display for UnresolvedLabel {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            formatter,
+            "instruction {} uses unresolved label {:?}",
+            self.index, self.label
+        )
+    }
+}
+
+impl Error for UnresolvedLabel {}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+
+    use super::super::{Field, Instruction, Opcode, Program};
+    use super::*;
+
+    fn warrior(b_field: Field) -> Warrior {
+        Warrior {
+            program: Program {
+                instructions: vec![
+                    Instruction::new(Opcode::Mov, Field::direct(0), Field::direct(1)),
+                    Instruction::new(Opcode::Jmp, Field::direct(-1), b_field),
+                ],
+                origin: None,
+                pin: None,
+            },
+            ..Warrior::default()
+        }
+    }
+
+    #[test]
+    fn resolves_literals() {
+        let resolved = ResolvedWarrior::try_from(warrior(Field::direct(0))).unwrap();
+        assert_eq!(resolved.len(), 2);
+    }
+
+    #[test]
+    fn rejects_labels() {
+        let result = ResolvedWarrior::try_from(warrior(Field::direct_label(&"start")));
+
+        assert_eq!(
+            result.unwrap_err(),
+            UnresolvedLabel {
+                label: "start".into(),
+                index: 1,
+            }
+        );
+    }
+}
@@ -0,0 +1,139 @@
+//! Serialization of warriors with [`serde`](serde), enabled by the `serde`
+//! feature. Opcodes, modifiers and address modes are written the same way as
+//! in Redcode, e.g. `"MOV"`, `"AB"` and `"#"`, and a field's value is either
+//! a number or the name of a label.
+
+use std::convert::TryFrom;
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use super::{AddressMode, Modifier, Opcode, ResolvedWarrior, Warrior};
+
+/// Serialize enums created with `enum_string!` as their Redcode string
+macro_rules! serialize_as_string {
+    ($($name:ident),* $(,)?) => {
+        $(
+            impl Serialize for $name {
+                fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                    serializer.collect_str(self)
+                }
+            }
+
+            impl<'de> Deserialize<'de> for $name {
+                fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                    String::deserialize(deserializer)?
+                        .parse()
+                        .map_err(de::Error::custom)
+                }
+            }
+        )*
+    };
+}
+
+serialize_as_string!(Opcode, Modifier, AddressMode);
+
+impl Serialize for ResolvedWarrior {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        Warrior::serialize(self, serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for ResolvedWarrior {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let warrior = Warrior::deserialize(deserializer)?;
+        Self::try_from(warrior).map_err(de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    use super::super::{Field, Instruction, Metadata, Program};
+    use super::*;
+
+    fn warrior() -> Warrior {
+        Warrior {
+            program: Program {
+                instructions: vec![
+                    Instruction::new(Opcode::Add, Field::immediate(4), Field::direct(3)),
+                    Instruction::new(Opcode::Jmp, Field::direct_label(&"start"), Field::direct(0)),
+                ],
+                origin: Some(1),
+                pin: None,
+            },
+            metadata: Metadata {
+                name: Some("Dwarf".into()),
+                ..Metadata::default()
+            },
+        }
+    }
+
+    #[test]
+    fn serializes_warrior() {
+        let expected = json!({
+            "program": {
+                "instructions": [
+                    {
+                        "opcode": "ADD",
+                        "modifier": "AB",
+                        "a_field": { "address_mode": "#", "value": 4 },
+                        "b_field": { "address_mode": "$", "value": 3 },
+                    },
+                    {
+                        "opcode": "JMP",
+                        "modifier": "B",
+                        "a_field": { "address_mode": "$", "value": "start" },
+                        "b_field": { "address_mode": "$", "value": 0 },
+                    },
+                ],
+                "origin": 1,
+                "pin": null,
+            },
+            "metadata": {
+                "redcode": null,
+                "name": "Dwarf",
+                "author": null,
+                "date": null,
+                "version": null,
+                "strategy": null,
+                "assertion": null,
+            },
+        });
+
+        assert_eq!(serde_json::to_value(warrior()).unwrap(), expected);
+    }
+
+    #[test]
+    fn round_trips_warrior() {
+        let json = serde_json::to_string(&warrior()).unwrap();
+        let warrior: Warrior = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(warrior.program, self::warrior().program);
+        assert_eq!(warrior.metadata, self::warrior().metadata);
+    }
+
+    #[test]
+    fn rejects_unknown_opcode() {
+        let field = json!({ "address_mode": "$", "value": 0 });
+        let json = json!({ "opcode": "FOO", "modifier": "I", "a_field": field, "b_field": field });
+
+        let error = serde_json::from_value::<Instruction>(json).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "No variant 'FOO' found for enum 'Opcode'"
+        );
+    }
+
+    #[test]
+    fn rejects_unresolved_warrior() {
+        let json = serde_json::to_string(&warrior()).unwrap();
+        let error = serde_json::from_str::<ResolvedWarrior>(&json).unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            "instruction 1 uses unresolved label \"start\""
+        );
+    }
+}
@@ -1,10 +1,11 @@
 use std::fmt;
 
 enum_string! {
-    #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+    #[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
     pub enum Opcode {
         Add => "ADD",
         Cmp => "CMP",
+        #[default]
         Dat => "DAT",
         Div => "DIV",
         Djn => "DJN",
@@ -25,12 +26,6 @@ enum_string! {
     }
 }
 
-impl Default for Opcode {
-    fn default() -> Self {
-        Self::Dat
-    }
-}
-
 enum_string! {
     #[derive(Copy, Clone, Debug, PartialEq, Eq)]
     pub enum PseudoOpcode {
@@ -38,36 +33,34 @@ enum_string! {
         End => "END",
         Equ => "EQU",
         For => "FOR",
+        Rof => "ROF",
+        Pin => "PIN",
     }
 }
 
 enum_string! {
     #[allow(clippy::upper_case_acronyms)]
-    #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+    #[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
     pub enum Modifier {
         A   => "A",
         B   => "B",
         AB  => "AB",
         BA  => "BA",
+        #[default]
         F   => "F",
         X   => "X",
         I   => "I",
     }
 }
 
-impl Default for Modifier {
-    fn default() -> Self {
-        Self::F
-    }
-}
-
 impl Modifier {
     #[must_use]
     pub fn default_88_to_94(opcode: Opcode, a_mode: AddressMode, b_mode: AddressMode) -> Self {
         /// Implemented based on the ICWS '94 document,
         /// section A.2.1.2: ICWS'88 to ICWS'94 Conversion
         use Opcode::{
-            Add, Cmp, Dat, Div, Djn, Jmn, Jmp, Jmz, Mod, Mov, Mul, Nop, Seq, Slt, Sne, Spl, Sub,
+            Add, Cmp, Dat, Div, Djn, Jmn, Jmp, Jmz, Ldp, Mod, Mov, Mul, Nop, Seq, Slt, Sne, Spl,
+            Stp, Sub,
         };
 
         match opcode {
@@ -81,7 +74,7 @@ impl Modifier {
                 } else {
                     match opcode {
                         Mov | Cmp | Seq | Sne => Modifier::I,
-                        Slt => Modifier::B,
+                        Slt | Ldp | Stp => Modifier::B,
                         Add | Sub | Mul | Div | Mod => Modifier::F,
                         _ => unreachable!(),
                     }
@@ -92,9 +85,10 @@ impl Modifier {
 }
 
 enum_string! {
-    #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+    #[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
     pub enum AddressMode {
         Immediate           => "#",
+        #[default]
         Direct              => "$",
         IndirectA           => "*",
         IndirectB           => "@",
@@ -105,13 +99,57 @@ enum_string! {
     }
 }
 
-impl Default for AddressMode {
-    fn default() -> Self {
-        Self::Direct
+enum_string! {
+    /// A version of the Redcode standard, as given by a `;redcode-XX` comment.
+    #[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+    pub enum Standard {
+        Icws88 => "88",
+        #[default]
+        Icws94 => "94",
     }
 }
 
+impl Standard {
+    /// Whether the opcode is part of this standard.
+    #[must_use]
+    pub fn supports_opcode(self, opcode: Opcode) -> bool {
+        use Opcode::{Add, Cmp, Dat, Djn, Jmn, Jmp, Jmz, Mov, Slt, Spl, Sub};
+
+        match self {
+            Self::Icws88 => matches!(
+                opcode,
+                Dat | Mov | Add | Sub | Jmp | Jmz | Jmn | Djn | Cmp | Slt | Spl
+            ),
+            Self::Icws94 => true,
+        }
+    }
+
+    /// Whether the address mode is part of this standard.
+    #[must_use]
+    pub fn supports_address_mode(self, address_mode: AddressMode) -> bool {
+        use AddressMode::{Direct, Immediate, IndirectB, PreDecIndirectB};
+
+        match self {
+            Self::Icws88 => matches!(
+                address_mode,
+                Immediate | Direct | IndirectB | PreDecIndirectB
+            ),
+            Self::Icws94 => true,
+        }
+    }
+
+    /// Whether instructions may be given an explicit modifier, like `MOV.AB`.
+    /// Otherwise, the modifier is always derived from the opcode and address modes.
+    #[must_use]
+    pub fn supports_modifiers(self) -> bool {
+        self == Self::Icws94
+    }
+}
+
+/// A field's value, which is serialized as either a number or a label
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(untagged))]
 pub enum Value {
     Label(String),
     Literal(i32),
@@ -182,11 +220,16 @@ mod test {
 
     #[test]
     fn modifier_b_slt() {
-        for (&a_mode, &b_mode) in iproduct!(AddressMode::iter_values(), AddressMode::iter_values())
-        {
+        let codes = [Slt, Opcode::Ldp, Opcode::Stp];
+
+        for (&opcode, &a_mode, &b_mode) in iproduct!(
+            codes.iter(),
+            AddressMode::iter_values(),
+            AddressMode::iter_values()
+        ) {
             if a_mode != AddressMode::Immediate {
                 assert_eq!(
-                    Modifier::default_88_to_94(Opcode::Slt, a_mode, b_mode),
+                    Modifier::default_88_to_94(opcode, a_mode, b_mode),
                     Modifier::B
                 );
             }
@@ -266,4 +309,27 @@ mod test {
 
         assert_eq!(String::from("123"), Value::Literal(123).to_string());
     }
+
+    #[test]
+    fn standard_88_subset() {
+        let opcodes_94 = [Div, Mod, Mul, Nop, Seq, Sne, Opcode::Ldp, Opcode::Stp];
+
+        for &opcode in Opcode::iter_values() {
+            assert!(Standard::Icws94.supports_opcode(opcode));
+            assert_eq!(
+                Standard::Icws88.supports_opcode(opcode),
+                !opcodes_94.contains(&opcode),
+                "{opcode}"
+            );
+        }
+
+        for &address_mode in AddressMode::iter_values() {
+            assert!(Standard::Icws94.supports_address_mode(address_mode));
+            assert_eq!(
+                Standard::Icws88.supports_address_mode(address_mode),
+                "#$@<".contains(&address_mode.to_string()),
+                "{address_mode}"
+            );
+        }
+    }
 }
@@ -31,12 +31,12 @@ macro_rules! enum_string {
     (
         $(#[$attr:meta])*
         $vis:vis enum $name:ident {
-            $($variant:ident => $value:expr),* $(,)?
+            $($(#[$variant_attr:meta])* $variant:ident => $value:expr),* $(,)?
         }
     ) => {
         $(#[$attr])*
         $vis enum $name {
-            $($variant,)*
+            $($(#[$variant_attr])* $variant,)*
         }
 
         impl ::std::fmt::Display for $name {
@@ -0,0 +1,366 @@
+//! Analysis of a single Redcode document, which answers the language server's
+//! queries. Positions are converted between the parser's byte columns and the
+//! UTF-16 columns used by the language server protocol.
+
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+
+use lsp_types::{
+    DiagnosticRelatedInformation, DiagnosticSeverity, DocumentSymbol, Hover, HoverContents,
+    Location, MarkupContent, MarkupKind, Position, Range, SymbolKind, Url,
+};
+
+use corewars_core::load_file::PseudoOpcode;
+use corewars_parser as parser;
+use parser::{LabelDefinition, LabelOccurrence, Span, Statement, SyntaxTree};
+
+/// An open document, and everything known about it.
+#[derive(Debug)]
+pub struct Document {
+    text: String,
+    tree: SyntaxTree,
+    labels: BTreeMap<String, LabelDefinition>,
+    occurrences: Vec<LabelOccurrence>,
+}
+
+impl Document {
+    pub fn new(text: String) -> Self {
+        let tree = parser::parse_tree(&text);
+        let labels = parser::parse_labels(&text, parser::Settings::default());
+        let occurrences = tree.label_occurrences();
+
+        Self {
+            text,
+            tree,
+            labels,
+            occurrences,
+        }
+    }
+
+    /// The errors and warnings from assembling and linting the document.
+    pub fn diagnostics(&self, uri: &Url) -> Vec<lsp_types::Diagnostic> {
+        let result = parser::lint(&self.text, parser::Settings::default());
+
+        result
+            .errors()
+            .iter()
+            .map(parser::Diagnostic::from)
+            .chain(result.warnings().iter().map(parser::Diagnostic::from))
+            .map(|diagnostic| {
+                let mut message = diagnostic.message;
+                if let Some(help) = diagnostic.help {
+                    message = format!("{message}\nhelp: {help}");
+                }
+
+                let related_information = diagnostic.expansion.map(|expansion| {
+                    vec![DiagnosticRelatedInformation {
+                        location: Location::new(uri.clone(), self.range(expansion.span)),
+                        message: format!("expanded from `{}`", expansion.label),
+                    }]
+                });
+
+                lsp_types::Diagnostic {
+                    range: diagnostic
+                        .span
+                        .map(|span| self.range(span))
+                        .unwrap_or_default(),
+                    severity: Some(match diagnostic.severity {
+                        parser::Severity::Error => DiagnosticSeverity::ERROR,
+                        parser::Severity::Warning => DiagnosticSeverity::WARNING,
+                    }),
+                    source: Some(String::from("corewars")),
+                    message,
+                    related_information,
+                    ..lsp_types::Diagnostic::default()
+                }
+            })
+            .collect()
+    }
+
+    /// Where the label at `position` is declared.
+    pub fn definition(&self, position: Position) -> Vec<Range> {
+        let Some(occurrence) = self.occurrence_at(position) else {
+            return Vec::new();
+        };
+
+        self.occurrences_of(&occurrence.label)
+            .filter(|other| other.is_declaration)
+            .map(|other| self.range(other.span))
+            .collect()
+    }
+
+    /// Everywhere the label at `position` is used, and optionally declared.
+    pub fn references(&self, position: Position, include_declaration: bool) -> Vec<Range> {
+        let Some(occurrence) = self.occurrence_at(position) else {
+            return Vec::new();
+        };
+
+        self.occurrences_of(&occurrence.label)
+            .filter(|other| include_declaration || !other.is_declaration)
+            .map(|other| self.range(other.span))
+            .collect()
+    }
+
+    /// What the label at `position` resolves to.
+    pub fn hover(&self, position: Position) -> Option<Hover> {
+        let occurrence = self.occurrence_at(position)?;
+        let label = &occurrence.label;
+
+        let value = match self.labels.get(label) {
+            Some(LabelDefinition::Offset(offset)) => {
+                format!("```redcode\n{label}\n```\nOffset {offset} from the start of the warrior")
+            }
+            Some(LabelDefinition::Substitution { text, value }) => {
+                let mut value = value.map_or_else(String::new, |value| format!("\nValue: {value}"));
+                value.insert_str(0, &format!("```redcode\n{label} EQU {text}\n```"));
+                value
+            }
+            None => {
+                let value = parser::Settings::default().constant(label)?;
+                format!("```redcode\n{label}\n```\nPredefined constant: {value}")
+            }
+        };
+
+        Some(Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value,
+            }),
+            range: Some(self.range(occurrence.span)),
+        })
+    }
+
+    /// The labels declared in the document.
+    pub fn symbols(&self) -> Vec<DocumentSymbol> {
+        self.occurrences
+            .iter()
+            .filter(|occurrence| occurrence.is_declaration)
+            .map(|occurrence| {
+                let line = &self.tree.lines[occurrence.span.line];
+                let statement = line.code.as_ref().and_then(|code| code.statement.as_ref());
+
+                let kind = match statement {
+                    Some(Statement::Pseudo {
+                        opcode: PseudoOpcode::Equ,
+                        ..
+                    }) => SymbolKind::CONSTANT,
+                    Some(Statement::Pseudo {
+                        opcode: PseudoOpcode::For,
+                        ..
+                    }) => SymbolKind::VARIABLE,
+                    _ => SymbolKind::FUNCTION,
+                };
+
+                let detail = match self.labels.get(&occurrence.label) {
+                    Some(LabelDefinition::Offset(offset)) => Some(format!("offset {offset}")),
+                    Some(LabelDefinition::Substitution { text, .. }) => Some(text.clone()),
+                    None => None,
+                };
+
+                #[allow(deprecated)]
+                DocumentSymbol {
+                    name: occurrence.label.clone(),
+                    detail,
+                    kind,
+                    tags: None,
+                    deprecated: None,
+                    range: self.range(line.span),
+                    selection_range: self.range(occurrence.span),
+                    children: None,
+                }
+            })
+            .collect()
+    }
+
+    fn occurrence_at(&self, position: Position) -> Option<&LabelOccurrence> {
+        let line = usize::try_from(position.line).ok()?;
+        let column = self.column(line, position.character);
+
+        self.occurrences.iter().find(|occurrence| {
+            occurrence.span.line == line
+                && occurrence.span.start <= column
+                && column <= occurrence.span.end
+        })
+    }
+
+    fn occurrences_of<'a>(&'a self, label: &'a str) -> impl Iterator<Item = &'a LabelOccurrence> {
+        self.occurrences
+            .iter()
+            .filter(move |occurrence| occurrence.label == label)
+    }
+
+    /// The text of a line, without its line ending.
+    fn line(&self, line: usize) -> &str {
+        self.text.lines().nth(line).unwrap_or_default()
+    }
+
+    /// Convert a UTF-16 column in a line to a byte column.
+    fn column(&self, line: usize, character: u32) -> usize {
+        let text = self.line(line);
+        let mut utf16_column = 0;
+
+        for (i, c) in text.char_indices() {
+            if utf16_column >= character as usize {
+                return i;
+            }
+            utf16_column += c.len_utf16();
+        }
+        text.len()
+    }
+
+    fn position(&self, line: usize, column: usize) -> Position {
+        let text = self.line(line);
+        let character = text
+            .get(..column)
+            .map_or(column, |prefix| prefix.encode_utf16().count());
+
+        Position::new(
+            u32::try_from(line).unwrap_or(u32::MAX),
+            u32::try_from(character).unwrap_or(u32::MAX),
+        )
+    }
+
+    fn range(&self, span: Span) -> Range {
+        Range::new(
+            self.position(span.line, span.start),
+            self.position(span.line, span.end),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    const SOURCE: &str = "\
+step    equ     CORESIZE / 4 ; how far
+ptr     equ     bomb + step
+start:  add.ab  #step, bomb
+        mov     bomb, @bomb
+        jmp     start
+bomb    dat     0
+";
+
+    fn range(line: u32, start: u32, end: u32) -> Range {
+        Range::new(Position::new(line, start), Position::new(line, end))
+    }
+
+    #[test]
+    fn finds_definition() {
+        let document = Document::new(SOURCE.into());
+
+        assert_eq!(
+            document.definition(Position::new(3, 18)),
+            vec![range(5, 0, 4)]
+        );
+        assert_eq!(
+            document.definition(Position::new(1, 26)),
+            vec![range(0, 0, 4)]
+        );
+        assert_eq!(document.definition(Position::new(3, 9)), vec![]);
+    }
+
+    #[test]
+    fn finds_references() {
+        let document = Document::new(SOURCE.into());
+
+        assert_eq!(
+            document.references(Position::new(5, 2), false),
+            vec![
+                range(1, 16, 20),
+                range(2, 23, 27),
+                range(3, 16, 20),
+                range(3, 23, 27)
+            ]
+        );
+        assert_eq!(
+            document.references(Position::new(4, 16), true),
+            vec![range(2, 0, 5), range(4, 16, 21)]
+        );
+    }
+
+    #[test]
+    fn hovers_label_values() {
+        let document = Document::new(SOURCE.into());
+        let hover = |line, character| match document.hover(Position::new(line, character)) {
+            Some(Hover {
+                contents: HoverContents::Markup(content),
+                ..
+            }) => Some(content.value),
+            _ => None,
+        };
+
+        assert_eq!(
+            hover(2, 17).as_deref(),
+            Some("```redcode\nstep EQU CORESIZE / 4\n```\nValue: 2000")
+        );
+        assert_eq!(
+            hover(4, 17).as_deref(),
+            Some("```redcode\nstart\n```\nOffset 0 from the start of the warrior")
+        );
+        assert_eq!(
+            hover(0, 18).as_deref(),
+            Some("```redcode\nCORESIZE\n```\nPredefined constant: 8000")
+        );
+        assert_eq!(hover(2, 9), None);
+    }
+
+    #[test]
+    fn lists_symbols() {
+        let document = Document::new(SOURCE.into());
+
+        let symbols: Vec<(String, SymbolKind, Option<String>)> = document
+            .symbols()
+            .into_iter()
+            .map(|symbol| (symbol.name, symbol.kind, symbol.detail))
+            .collect();
+
+        assert_eq!(
+            symbols,
+            vec![
+                (
+                    "step".into(),
+                    SymbolKind::CONSTANT,
+                    Some("CORESIZE / 4".into())
+                ),
+                (
+                    "ptr".into(),
+                    SymbolKind::CONSTANT,
+                    Some("bomb + step".into())
+                ),
+                (
+                    "start".into(),
+                    SymbolKind::FUNCTION,
+                    Some("offset 0".into())
+                ),
+                ("bomb".into(), SymbolKind::FUNCTION, Some("offset 3".into())),
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_diagnostics() {
+        let uri = Url::parse("file:///imp.red").unwrap();
+        let document = Document::new("mov 0, 1\njmp nowhere\n".into());
+
+        let diagnostics = document.diagnostics(&uri);
+
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].range, range(1, 4, 11));
+        assert_eq!(diagnostics[0].severity, Some(DiagnosticSeverity::ERROR));
+        assert!(diagnostics[0]
+            .message
+            .starts_with("no such label \"nowhere\""));
+    }
+
+    #[test]
+    fn converts_utf16_columns() {
+        let document = Document::new("; ünïcode ✓\nx dat x\n".into());
+
+        assert_eq!(document.column(0, 3), 4);
+        assert_eq!(document.position(0, 4), Position::new(0, 3));
+        assert_eq!(document.position(1, 7), Position::new(1, 7));
+    }
+}
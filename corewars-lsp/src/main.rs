@@ -0,0 +1,208 @@
+//! A language server for Redcode, which communicates over stdio. It reports
+//! diagnostics from the parser, and answers queries about labels.
+
+mod document;
+
+use std::collections::HashMap;
+use std::error::Error;
+
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, Response};
+use lsp_types::notification::{
+    DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument,
+    Notification as NotificationTrait, PublishDiagnostics,
+};
+use lsp_types::request::{
+    DocumentSymbolRequest, GotoDefinition, HoverRequest, References, Request as RequestTrait,
+};
+use lsp_types::{
+    DocumentSymbolParams, DocumentSymbolResponse, GotoDefinitionParams, GotoDefinitionResponse,
+    Hover, HoverParams, HoverProviderCapability, Location, OneOf, PublishDiagnosticsParams,
+    ReferenceParams, ServerCapabilities, TextDocumentSyncCapability, TextDocumentSyncKind, Url,
+};
+
+use document::Document;
+
+type Result<T> = std::result::Result<T, Box<dyn Error + Send + Sync>>;
+
+fn main() -> Result<()> {
+    let (connection, io_threads) = Connection::stdio();
+
+    let capabilities = serde_json::to_value(capabilities())?;
+    connection.initialize(capabilities)?;
+
+    Server::default().run(connection)?;
+    io_threads.join()?;
+
+    Ok(())
+}
+
+fn capabilities() -> ServerCapabilities {
+    ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        definition_provider: Some(OneOf::Left(true)),
+        references_provider: Some(OneOf::Left(true)),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        document_symbol_provider: Some(OneOf::Left(true)),
+        ..ServerCapabilities::default()
+    }
+}
+
+/// The state of the server, which is every document open in the client.
+#[derive(Debug, Default)]
+struct Server {
+    documents: HashMap<Url, Document>,
+}
+
+impl Server {
+    /// Handle messages until the client shuts the server down.
+    fn run(&mut self, connection: Connection) -> Result<()> {
+        for message in &connection.receiver {
+            match message {
+                Message::Request(request) => {
+                    if connection.handle_shutdown(&request)? {
+                        break;
+                    }
+                    connection
+                        .sender
+                        .send(self.handle_request(request).into())?;
+                }
+                Message::Notification(notification) => {
+                    if let Some(published) = self.handle_notification(notification)? {
+                        connection.sender.send(published.into())?;
+                    }
+                }
+                Message::Response(_) => {}
+            }
+        }
+
+        // Close the connection, so that the IO threads can finish
+        drop(connection);
+        Ok(())
+    }
+
+    fn handle_request(&self, request: Request) -> Response {
+        let id = request.id.clone();
+
+        let result = match request.method.as_str() {
+            GotoDefinition::METHOD => {
+                dispatch::<GotoDefinition>(request, |params| self.definition(&params))
+            }
+            References::METHOD => {
+                dispatch::<References>(request, |params| self.references(&params))
+            }
+            HoverRequest::METHOD => dispatch::<HoverRequest>(request, |params| self.hover(&params)),
+            DocumentSymbolRequest::METHOD => {
+                dispatch::<DocumentSymbolRequest>(request, |params| self.symbols(&params))
+            }
+            method => {
+                return Response::new_err(
+                    id,
+                    ErrorCode::MethodNotFound as i32,
+                    format!("unsupported request {method}"),
+                )
+            }
+        };
+
+        match result {
+            Ok(value) => Response::new_ok(id, value),
+            Err(err) => Response::new_err(id, ErrorCode::InvalidParams as i32, err.to_string()),
+        }
+    }
+
+    /// Update the open documents, and return their new diagnostics if they
+    /// have changed.
+    fn handle_notification(&mut self, notification: Notification) -> Result<Option<Notification>> {
+        let (uri, text) = match notification.method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                let params = parse_params::<DidOpenTextDocument>(notification)?;
+                (params.text_document.uri, Some(params.text_document.text))
+            }
+            DidChangeTextDocument::METHOD => {
+                // Documents are always synced in full
+                let mut params = parse_params::<DidChangeTextDocument>(notification)?;
+                let text = params.content_changes.pop().map(|change| change.text);
+                (params.text_document.uri, text)
+            }
+            DidCloseTextDocument::METHOD => {
+                let params = parse_params::<DidCloseTextDocument>(notification)?;
+                self.documents.remove(&params.text_document.uri);
+                (params.text_document.uri, None)
+            }
+            _ => return Ok(None),
+        };
+
+        let diagnostics = match text {
+            Some(text) => {
+                let document = Document::new(text);
+                let diagnostics = document.diagnostics(&uri);
+                self.documents.insert(uri.clone(), document);
+                diagnostics
+            }
+            None if self.documents.contains_key(&uri) => return Ok(None),
+            // Clear the diagnostics of a closed document
+            None => Vec::new(),
+        };
+
+        let params = PublishDiagnosticsParams::new(uri, diagnostics, None);
+        Ok(Some(Notification::new(
+            PublishDiagnostics::METHOD.to_owned(),
+            params,
+        )))
+    }
+
+    fn definition(&self, params: &GotoDefinitionParams) -> Option<GotoDefinitionResponse> {
+        let position = &params.text_document_position_params;
+        let uri = &position.text_document.uri;
+
+        let locations: Vec<Location> = self
+            .documents
+            .get(uri)?
+            .definition(position.position)
+            .into_iter()
+            .map(|range| Location::new(uri.clone(), range))
+            .collect();
+
+        Some(GotoDefinitionResponse::Array(locations))
+    }
+
+    fn references(&self, params: &ReferenceParams) -> Option<Vec<Location>> {
+        let position = &params.text_document_position;
+        let uri = &position.text_document.uri;
+
+        let locations = self
+            .documents
+            .get(uri)?
+            .references(position.position, params.context.include_declaration)
+            .into_iter()
+            .map(|range| Location::new(uri.clone(), range))
+            .collect();
+
+        Some(locations)
+    }
+
+    fn hover(&self, params: &HoverParams) -> Option<Hover> {
+        let position = &params.text_document_position_params;
+
+        self.documents
+            .get(&position.text_document.uri)?
+            .hover(position.position)
+    }
+
+    fn symbols(&self, params: &DocumentSymbolParams) -> Option<DocumentSymbolResponse> {
+        let document = self.documents.get(&params.text_document.uri)?;
+        Some(DocumentSymbolResponse::Nested(document.symbols()))
+    }
+}
+
+/// Parse the parameters of a request, and serialize the handler's result.
+fn dispatch<R: RequestTrait>(
+    request: Request,
+    handler: impl FnOnce(R::Params) -> R::Result,
+) -> std::result::Result<serde_json::Value, serde_json::Error> {
+    let params = serde_json::from_value(request.params)?;
+    serde_json::to_value(handler(params))
+}
+
+fn parse_params<N: NotificationTrait>(notification: Notification) -> Result<N::Params> {
+    Ok(serde_json::from_value(notification.params)?)
+}
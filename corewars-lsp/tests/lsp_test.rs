@@ -0,0 +1,80 @@
+use assert_cmd::Command;
+use serde_json::{json, Value};
+
+/// Frame a JSON-RPC message the way the language server protocol expects.
+fn frame(message: &Value) -> String {
+    let body = message.to_string();
+    format!("Content-Length: {}\r\n\r\n{body}", body.len())
+}
+
+/// Split the server's output into its JSON-RPC messages.
+fn messages(output: &str) -> Vec<Value> {
+    output
+        .split("Content-Length: ")
+        .filter_map(|message| message.split_once("\r\n\r\n"))
+        .map(|(_, body)| serde_json::from_str(body).expect("Server should only send JSON"))
+        .collect()
+}
+
+#[test]
+fn publishes_diagnostics_and_answers_requests() {
+    let uri = "file:///warrior.red";
+    let input = [
+        json!({"jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {"capabilities": {}}}),
+        json!({"jsonrpc": "2.0", "method": "initialized", "params": {}}),
+        json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didOpen",
+            "params": {"textDocument": {
+                "uri": uri, "languageId": "redcode", "version": 1,
+                "text": "start jmp start\n      jmp nowhere\n",
+            }},
+        }),
+        json!({
+            "jsonrpc": "2.0",
+            "id": 2,
+            "method": "textDocument/definition",
+            "params": {"textDocument": {"uri": uri}, "position": {"line": 0, "character": 12}},
+        }),
+        json!({"jsonrpc": "2.0", "id": 3, "method": "shutdown"}),
+        json!({"jsonrpc": "2.0", "method": "exit"}),
+    ];
+
+    let assert = Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .write_stdin(input.iter().map(frame).collect::<String>())
+        .assert()
+        .success();
+
+    let output = String::from_utf8(assert.get_output().stdout.clone()).unwrap();
+    let messages = messages(&output);
+
+    let initialized = messages.iter().find(|message| message["id"] == 1).unwrap();
+    assert_eq!(
+        initialized["result"]["capabilities"]["definitionProvider"],
+        true
+    );
+
+    let published = messages
+        .iter()
+        .find(|message| message["method"] == "textDocument/publishDiagnostics")
+        .unwrap();
+    let diagnostic = &published["params"]["diagnostics"][0];
+    assert_eq!(
+        diagnostic["range"]["start"],
+        json!({"line": 1, "character": 10})
+    );
+    assert!(diagnostic["message"]
+        .as_str()
+        .unwrap()
+        .starts_with(r#"no such label "nowhere""#));
+
+    let definition = messages.iter().find(|message| message["id"] == 2).unwrap();
+    assert_eq!(
+        definition["result"],
+        json!([{
+            "uri": uri,
+            "range": {"start": {"line": 0, "character": 0}, "end": {"line": 0, "character": 5}},
+        }])
+    );
+}
@@ -0,0 +1,573 @@
+//! A lossless syntax tree for Redcode source. Every character of the input,
+//! including whitespace and comments, belongs to exactly one [`Token`], so
+//! the original text can always be reproduced from the tree. Each line of
+//! code is also parsed into a [`SourceLine`], with its labels, expressions
+//! and macros kept as they were written.
+
+use std::fmt;
+
+use corewars_core::load_file::{Metadata, PseudoOpcode};
+
+use crate::error::Error;
+use crate::grammar::{self, Rule};
+use crate::phase::{syntax_error, Line};
+use crate::result::Result;
+use crate::source::{self, SourceLine};
+use crate::span::{Span, Spanned};
+
+/// The kind of text a [`Token`] contains.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TokenKind {
+    /// A label, either where it is declared or where it is used
+    Label,
+    /// The optional `:` after a label declaration
+    Colon,
+    Opcode,
+    /// A pseudo-opcode such as `EQU`, `FOR` or `ORG`
+    PseudoOpcode,
+    /// The `.` between an opcode and its modifier
+    Dot,
+    Modifier,
+    AddressMode,
+    /// The `,` between the A-field and B-field
+    Comma,
+    Number,
+    /// An operator in an expression, such as `+` or `&&`
+    Operator,
+    OpenParen,
+    CloseParen,
+    /// The text substituted for an `EQU` label
+    Substitution,
+    /// A comment, including the leading `;`
+    Comment,
+    Whitespace,
+    Newline,
+    /// Code which could not be parsed
+    Error,
+    /// Input after an `END`, which is not part of the warrior
+    Ignored,
+}
+
+/// A single token of the source, with its location.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub text: String,
+    pub span: Span,
+}
+
+/// A line of the source, including its line ending, if any.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SyntaxLine {
+    /// Every token in the line, in order
+    pub tokens: Vec<Token>,
+    /// The parsed code in the line, if it has any which is valid
+    pub code: Option<SourceLine>,
+    /// The location of the line, excluding its line ending
+    pub span: Span,
+}
+
+impl SyntaxLine {
+    /// The comment at the end of the line, if there is one.
+    #[must_use]
+    pub fn comment(&self) -> Option<&Token> {
+        self.tokens
+            .iter()
+            .find(|token| token.kind == TokenKind::Comment)
+    }
+
+    /// Whether the line contains input after an `END`.
+    #[must_use]
+    pub fn is_ignored(&self) -> bool {
+        self.tokens
+            .iter()
+            .any(|token| token.kind == TokenKind::Ignored)
+    }
+}
+
+impl fmt::Display for SyntaxLine {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        self.tokens
+            .iter()
+            .try_for_each(|token| formatter.write_str(&token.text))
+    }
+}
+
+/// The syntax tree of an entire source file. Displaying the tree reproduces
+/// the source exactly.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct SyntaxTree {
+    pub lines: Vec<SyntaxLine>,
+    /// Metadata from comments like `;name`
+    pub metadata: Metadata,
+    /// Errors from lines which could not be parsed
+    pub errors: Vec<Spanned<Error>>,
+}
+
+/// A use or declaration of a label in the source.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LabelOccurrence {
+    pub label: String,
+    pub span: Span,
+    pub is_declaration: bool,
+}
+
+impl SyntaxTree {
+    /// The tree, or the errors in it if any line has invalid syntax.
+    pub fn into_result(self) -> Result<Self> {
+        if self.errors.is_empty() {
+            Result::ok(self)
+        } else {
+            Result::Err(self.errors, Vec::new())
+        }
+    }
+
+    /// The code of the source with each statement normalized, and without
+    /// comments other than metadata like `;name`. Any input after an `END`
+    /// is left out.
+    #[must_use]
+    pub fn code(&self) -> String {
+        let lines: Vec<String> = self
+            .lines
+            .iter()
+            .filter_map(|line| line.code.as_ref())
+            .map(ToString::to_string)
+            .collect();
+
+        format!("{}{}", self.metadata, lines.join("\n"))
+    }
+
+    /// Every token in the source, in order.
+    pub fn tokens(&self) -> impl Iterator<Item = &Token> {
+        self.lines.iter().flat_map(|line| line.tokens.iter())
+    }
+
+    /// Find every label in the source. Labels used in `EQU` substitutions are
+    /// found by name, since the substituted text is not parsed until it is
+    /// used, and so are `FOR` counters concatenated with `&`.
+    #[must_use]
+    pub fn label_occurrences(&self) -> Vec<LabelOccurrence> {
+        let mut occurrences = Vec::new();
+
+        for line in &self.lines {
+            let Some(code) = &line.code else {
+                continue;
+            };
+
+            // Declarations always come before anything else in the line
+            let mut declarations = code.labels.len();
+
+            for token in &line.tokens {
+                match token.kind {
+                    TokenKind::Label => {
+                        occurrences.push(LabelOccurrence {
+                            label: token.text.clone(),
+                            span: token.span,
+                            is_declaration: declarations > 0,
+                        });
+                        declarations = declarations.saturating_sub(1);
+
+                        if let Some(concatenated) = token.text.find('&') {
+                            occurrences.extend(identifier_occurrences(token, concatenated));
+                        }
+                    }
+                    TokenKind::Substitution => {
+                        occurrences.extend(identifier_occurrences(token, 0));
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        occurrences
+    }
+}
+
+/// The uses of labels in the text of a token, starting from byte `start`.
+fn identifier_occurrences(
+    token: &Token,
+    start: usize,
+) -> impl Iterator<Item = LabelOccurrence> + '_ {
+    identifiers(&token.text[start..]).map(move |(offset, identifier)| {
+        let start = token.span.start + start + offset;
+        LabelOccurrence {
+            label: identifier.to_owned(),
+            span: Span::new(token.span.line, start, start + identifier.len()),
+            is_declaration: false,
+        }
+    })
+}
+
+/// Find each word in some text which could be a label, with its byte offset.
+pub fn identifiers(text: &str) -> impl Iterator<Item = (usize, &str)> {
+    let is_label_char = |c: char| c.is_ascii_alphanumeric() || c == '_';
+
+    text.char_indices().filter_map(move |(i, c)| {
+        let follows_label = text[..i].chars().next_back().is_some_and(is_label_char);
+        if follows_label || !(c.is_ascii_alphabetic() || c == '_') {
+            return None;
+        }
+
+        let end = text[i..]
+            .find(|c| !is_label_char(c))
+            .map_or(text.len(), |len| i + len);
+        Some((i, &text[i..end]))
+    })
+}
+
+impl fmt::Display for SyntaxTree {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        self.lines
+            .iter()
+            .try_for_each(|line| write!(formatter, "{line}"))
+    }
+}
+
+/// Parse source into a lossless syntax tree. Unlike the other parsers, this
+/// always succeeds, and any lines with invalid syntax are listed in the
+/// tree's `errors`.
+pub fn parse(input: &str) -> SyntaxTree {
+    let mut tree = SyntaxTree::default();
+    let mut ended = false;
+
+    for (i, text) in input.split_inclusive('\n').enumerate() {
+        let content = text.trim_end_matches(['\n', '\r']);
+        let mut tokens = Tokens {
+            line: i,
+            tokens: Vec::new(),
+        };
+        let mut code = None;
+
+        if ended {
+            tokens.push(TokenKind::Ignored, content, 0);
+        } else {
+            tree.metadata.parse_line(content);
+
+            let comment_start = content.find(';').unwrap_or(content.len());
+            let code_text = content[..comment_start].trim();
+            let code_start = content.len() - content.trim_start().len();
+            let code_start = code_start.min(comment_start);
+            let code_end = code_start + code_text.len();
+
+            tokens.push(TokenKind::Whitespace, &content[..code_start], 0);
+
+            if !code_text.is_empty() {
+                let line = Line::new(code_text, Span::new(i, code_start, code_end));
+                match parse_code(input, &line, &mut tokens) {
+                    Ok(source_line) => {
+                        ended = source_line.is_end();
+                        code = Some(source_line);
+                    }
+                    Err(err) => {
+                        tokens.push(TokenKind::Error, code_text, code_start);
+                        tree.errors.push(err);
+                    }
+                }
+            }
+
+            tokens.push(
+                TokenKind::Whitespace,
+                &content[code_end..comment_start],
+                code_end,
+            );
+            tokens.push(TokenKind::Comment, &content[comment_start..], comment_start);
+        }
+
+        tokens.push(TokenKind::Newline, &text[content.len()..], content.len());
+
+        tree.lines.push(SyntaxLine {
+            tokens: tokens.tokens,
+            code,
+            span: Span::new(i, 0, content.len()),
+        });
+    }
+
+    tree
+}
+
+/// The tokens of a single line, as they are built.
+struct Tokens {
+    line: usize,
+    tokens: Vec<Token>,
+}
+
+impl Tokens {
+    /// Add a token starting at column `start`, unless it is empty.
+    fn push(&mut self, kind: TokenKind, text: &str, start: usize) {
+        if !text.is_empty() {
+            self.tokens.push(Token {
+                kind,
+                text: text.to_owned(),
+                span: Span::new(self.line, start, start + text.len()),
+            });
+        }
+    }
+
+    /// Add the tokens for text which the grammar matched without producing a
+    /// token pair, e.g. whitespace, punctuation or the `EQU` keyword.
+    fn push_gap(&mut self, text: &str, start: usize) {
+        let mut chars = text.char_indices().peekable();
+
+        while let Some((i, c)) = chars.next() {
+            let mut end = i + c.len_utf8();
+            let mut extend_while = |predicate: fn(char) -> bool| {
+                while let Some(&(j, next)) = chars.peek() {
+                    if !predicate(next) {
+                        break;
+                    }
+                    end = j + next.len_utf8();
+                    chars.next();
+                }
+            };
+
+            let kind = match c {
+                c if c.is_whitespace() => {
+                    extend_while(char::is_whitespace);
+                    TokenKind::Whitespace
+                }
+                c if c.is_ascii_alphabetic() => {
+                    extend_while(|c| c.is_ascii_alphabetic());
+                    TokenKind::PseudoOpcode
+                }
+                ':' => TokenKind::Colon,
+                '.' => TokenKind::Dot,
+                ',' => TokenKind::Comma,
+                '(' => TokenKind::OpenParen,
+                ')' => TokenKind::CloseParen,
+                _ => TokenKind::Error,
+            };
+
+            self.push(kind, &text[i..end], start + i);
+        }
+    }
+}
+
+/// Parse the code of a line, adding a token for every character of it.
+fn parse_code(
+    source: &str,
+    line: &Line,
+    tokens: &mut Tokens,
+) -> std::result::Result<SourceLine, Spanned<Error>> {
+    let masked = source::mask_concatenation(&line.text);
+    let pairs = grammar::parse_line(&masked).map_err(|err| syntax_error(source, line, err))?;
+
+    let text = line.text.as_str();
+    let offset = line.span.start;
+    let mut position = 0;
+
+    let terminals = pairs
+        .clone()
+        .flatten()
+        .filter(|pair| pair.clone().into_inner().peek().is_none());
+
+    for pair in terminals {
+        let span = pair.as_span();
+        if span.start() == span.end() {
+            continue;
+        }
+
+        let kind = match pair.as_rule() {
+            Rule::Label => TokenKind::Label,
+            Rule::Opcode if pair.as_str().to_uppercase().parse::<PseudoOpcode>().is_ok() => {
+                TokenKind::PseudoOpcode
+            }
+            Rule::Opcode => TokenKind::Opcode,
+            Rule::Modifier => TokenKind::Modifier,
+            Rule::AddressMode => TokenKind::AddressMode,
+            Rule::Number => TokenKind::Number,
+            Rule::AddOp | Rule::MultiplyOp | Rule::UnaryOp | Rule::CompareOp | Rule::BooleanOp => {
+                TokenKind::Operator
+            }
+            Rule::Substitution => TokenKind::Substitution,
+            Rule::For | Rule::Rof => TokenKind::PseudoOpcode,
+            _ => TokenKind::Error,
+        };
+
+        tokens.push_gap(&text[position..span.start()], offset + position);
+        tokens.push(kind, &text[span.start()..span.end()], offset + span.start());
+        position = span.end();
+    }
+    tokens.push_gap(&text[position..], offset + position);
+
+    let source_line = source::from_pairs(text, pairs);
+
+    // A bare `END` is parsed as a label, but is really a pseudo-opcode
+    if source_line.is_end() {
+        if let Some(token) =
+            tokens.tokens.iter_mut().rev().find(|token| {
+                token.kind == TokenKind::Label && token.text.eq_ignore_ascii_case("END")
+            })
+        {
+            token.kind = TokenKind::PseudoOpcode;
+        }
+    }
+
+    Ok(source_line)
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+    use textwrap_macros::dedent;
+
+    use super::*;
+
+    use TokenKind::{
+        AddressMode, Comma, Comment, Dot, Label, Modifier, Newline, Number, Opcode, Operator,
+        PseudoOpcode, Substitution, Whitespace,
+    };
+
+    fn kinds(line: &SyntaxLine) -> Vec<(TokenKind, &str)> {
+        line.tokens
+            .iter()
+            .map(|token| (token.kind, token.text.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn reproduces_input() {
+        let input =
+            ";name test\r\n\n  x&i: mov.ab #1 , (x&i+2)*3 ; c\nbad , line\nend\nafter ; end";
+        let tree = parse(input);
+
+        assert_eq!(tree.to_string(), input);
+        assert_eq!(tree.lines.len(), 6);
+        assert_eq!(tree.metadata.name.as_deref(), Some("test"));
+        assert!(tree.lines[5].is_ignored());
+        assert_eq!(tree.errors.len(), 1);
+        assert_eq!(tree.errors[0].span, Some(Span::new(3, 4, 5)));
+    }
+
+    #[test]
+    fn tokenizes_instruction() {
+        let tree = parse("  start: mov.ab #1, start+1 ; copy\n");
+
+        assert_eq!(
+            kinds(&tree.lines[0]),
+            vec![
+                (Whitespace, "  "),
+                (Label, "start"),
+                (TokenKind::Colon, ":"),
+                (Whitespace, " "),
+                (Opcode, "mov"),
+                (Dot, "."),
+                (Modifier, "ab"),
+                (Whitespace, " "),
+                (AddressMode, "#"),
+                (Number, "1"),
+                (Comma, ","),
+                (Whitespace, " "),
+                (Label, "start"),
+                (Operator, "+"),
+                (Number, "1"),
+                (Whitespace, " "),
+                (Comment, "; copy"),
+                (Newline, "\n"),
+            ]
+        );
+        assert_eq!(tree.lines[0].tokens[4].span, Span::new(0, 9, 12));
+        assert_eq!(
+            tree.lines[0].code.as_ref().map(ToString::to_string),
+            Some(String::from("start   MOV.AB  #1,     start+1"))
+        );
+    }
+
+    #[test]
+    fn tokenizes_pseudo_opcodes() {
+        let tree = parse("x equ 4\norg x\nEnd");
+
+        assert_eq!(
+            kinds(&tree.lines[0]),
+            vec![
+                (Label, "x"),
+                (Whitespace, " "),
+                (PseudoOpcode, "equ"),
+                (Whitespace, " "),
+                (Substitution, "4"),
+                (Newline, "\n"),
+            ]
+        );
+        assert_eq!(
+            kinds(&tree.lines[1]),
+            vec![
+                (PseudoOpcode, "org"),
+                (Whitespace, " "),
+                (Label, "x"),
+                (Newline, "\n")
+            ]
+        );
+        assert_eq!(kinds(&tree.lines[2]), vec![(PseudoOpcode, "End")]);
+    }
+
+    #[test]
+    fn prints_code() {
+        let input = dedent!(
+            "
+            ;redcode-94
+            ;name   Dwarf
+            step    equ 4
+            org start
+            n   for 2
+            x&n dat #n*  step, x&n && 1
+                rof
+            target
+            start:  add.ab   #step,target
+            mov  2, @ target + 1  ; bomb away
+                    jmp   start
+            end
+            dat 0, 0
+            "
+        );
+
+        let expected = dedent!(
+            "
+            ;redcode-94
+            ;name Dwarf
+            step    EQU     4
+                    ORG     start
+            n       FOR     2
+            x&n     DAT     #n*  step, x&n && 1
+                    ROF
+            target
+            start   ADD.AB  #step,  target
+                    MOV     2,      @target + 1
+                    JMP     start
+                    END"
+        )
+        .trim_start();
+
+        assert_eq!(parse(input).code(), expected);
+    }
+
+    #[test]
+    fn finds_label_occurrences() {
+        let tree = parse(
+            "step equ x+1
+i for 2
+x&i dat step
+rof
+x jmp x
+",
+        );
+
+        let occurrences: Vec<(String, Span, bool)> = tree
+            .label_occurrences()
+            .into_iter()
+            .map(|occurrence| (occurrence.label, occurrence.span, occurrence.is_declaration))
+            .collect();
+
+        assert_eq!(
+            occurrences,
+            vec![
+                ("step".into(), Span::new(0, 0, 4), true),
+                ("x".into(), Span::new(0, 9, 10), false),
+                ("i".into(), Span::new(1, 0, 1), true),
+                ("x&i".into(), Span::new(2, 0, 3), true),
+                ("i".into(), Span::new(2, 2, 3), false),
+                ("step".into(), Span::new(2, 8, 12), false),
+                ("x".into(), Span::new(4, 0, 1), true),
+                ("x".into(), Span::new(4, 6, 7), false),
+            ]
+        );
+    }
+}
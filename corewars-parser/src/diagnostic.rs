@@ -0,0 +1,198 @@
+//! Human-readable rendering of parser errors and warnings, in a style similar
+//! to rustc's diagnostics: the offending source line is printed with a caret
+//! under the relevant span, followed by any notes and hints.
+
+use std::fmt::Write as _;
+
+use corewars_core::enum_string;
+
+use super::error::{Error, Warning};
+use super::span::{Expansion, Span, Spanned};
+
+enum_string! {
+    #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+    pub enum Severity {
+        Error => "error",
+        Warning => "warning",
+    }
+}
+
+/// A single error or warning, with everything needed to display it to a user.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    /// Whether this diagnostic prevents the warrior from being assembled.
+    pub severity: Severity,
+    /// The primary message describing the problem.
+    pub message: String,
+    /// The location of the problem in the source, if known.
+    pub span: Option<Span>,
+    /// The `EQU` expansion that produced the code at `span`, if any.
+    pub expansion: Option<Expansion>,
+    /// A hint for how to fix the problem.
+    pub help: Option<String>,
+}
+
+impl From<&Spanned<Error>> for Diagnostic {
+    fn from(error: &Spanned<Error>) -> Self {
+        Self {
+            severity: Severity::Error,
+            message: error.value.to_string(),
+            span: error.span,
+            expansion: error.expansion.clone(),
+            help: error.help(),
+        }
+    }
+}
+
+impl From<&Spanned<Warning>> for Diagnostic {
+    fn from(warning: &Spanned<Warning>) -> Self {
+        Self {
+            severity: Severity::Warning,
+            message: warning.value.to_string(),
+            span: warning.span,
+            expansion: warning.expansion.clone(),
+            help: warning.help(),
+        }
+    }
+}
+
+impl Diagnostic {
+    /// Render the diagnostic as multi-line text, quoting lines from `source`,
+    /// which is the full input that was parsed. `file_name` is only used
+    /// for display purposes.
+    #[must_use]
+    pub fn render(&self, source: &str, file_name: &str) -> String {
+        let gutter_width = [self.span, self.expansion.as_ref().map(|e| e.span)]
+            .iter()
+            .flatten()
+            .map(|span| (span.line + 1).to_string().len())
+            .max()
+            .unwrap_or(1);
+        let gutter = " ".repeat(gutter_width);
+
+        let mut output = format!("{}: {}\n", self.severity, self.message);
+
+        if let Some(span) = self.span {
+            write_snippet(&mut output, source, file_name, span, '^', &gutter);
+        }
+
+        if let Some(help) = &self.help {
+            if self.span.is_some() {
+                let _ = writeln!(output, "{gutter} |");
+            }
+            let _ = writeln!(output, "{gutter} = help: {help}");
+        }
+
+        if let Some(expansion) = &self.expansion {
+            let _ = writeln!(output, "note: in this expansion of `{}`", expansion.label);
+            write_snippet(&mut output, source, file_name, expansion.span, '-', &gutter);
+        }
+
+        output
+    }
+}
+
+/// Write a source location header and quote the source line with the given
+/// span underlined.
+fn write_snippet(
+    output: &mut String,
+    source: &str,
+    file_name: &str,
+    span: Span,
+    underline: char,
+    gutter: &str,
+) {
+    let _ = writeln!(output, "{gutter}--> {file_name}:{span}");
+
+    let Some(line) = source.lines().nth(span.line) else {
+        return;
+    };
+
+    // Tabs are expanded so the underline lines up with the quoted text
+    let display_width = |text: &str| {
+        text.chars()
+            .map(|c| if c == '\t' { 4 } else { 1 })
+            .sum::<usize>()
+    };
+
+    let start = span.start.min(line.len());
+    let end = span.end.clamp(start, line.len());
+    let padding = line.get(..start).map_or(0, display_width);
+    let length = line.get(start..end).map_or(0, display_width).max(1);
+
+    let _ = writeln!(output, "{gutter} |");
+    let _ = writeln!(
+        output,
+        "{:>width$} | {}",
+        span.line + 1,
+        line.replace('\t', "    ").trim_end(),
+        width = gutter.len(),
+    );
+    let _ = writeln!(
+        output,
+        "{gutter} | {}{}",
+        " ".repeat(padding),
+        underline.to_string().repeat(length)
+    );
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+    use textwrap_macros::dedent;
+
+    use super::*;
+
+    #[test]
+    fn renders_error() {
+        let source = "mov 0, 1\nstep equ foo\n\tstep\n";
+        let error = Spanned::new(
+            Error::LabelNotFound {
+                label: "foo".into(),
+                line: Some(2),
+            },
+            Span::new(2, 1, 5),
+        )
+        .with_expansion(Some(Expansion {
+            label: "step".into(),
+            span: Span::new(1, 0, 4),
+        }));
+
+        assert_eq!(
+            Diagnostic::from(&error).render(source, "warrior.red"),
+            dedent!(
+                r#"
+                error: no such label "foo"
+                 --> warrior.red:3:2
+                  |
+                3 |     step
+                  |     ^^^^
+                  |
+                  = help: declare `foo` by labeling an instruction, or define it with `EQU`
+                note: in this expansion of `step`
+                 --> warrior.red:2:1
+                  |
+                2 | step equ foo
+                  | ----
+                "#
+            )
+            .trim_start()
+        );
+    }
+
+    #[test]
+    fn renders_warning_without_span() {
+        let warning = Spanned::from(Warning::EmptySubstitution("foo".into()));
+
+        assert_eq!(
+            Diagnostic::from(&warning).render("", "warrior.red"),
+            dedent!(
+                r#"
+                warning: right-hand side of substitution for label "foo" is empty
+                  = help: all uses of the label will be replaced with nothing
+                "#
+            )
+            .trim_start()
+        );
+    }
+}
@@ -4,9 +4,7 @@ use std::num::TryFromIntError;
 
 use thiserror::Error as ThisError;
 
-use corewars_core::load_file::Opcode;
-
-// TODO: use pest spans for error reporting? Or at least line number
+use corewars_core::load_file::{Modifier, Opcode, PseudoOpcode, Standard};
 
 /// An error that occurred while parsing a warrior.
 #[derive(ThisError, Debug, PartialEq, Eq)]
@@ -16,6 +14,26 @@ pub enum Error {
     #[error("no such label {label:?}")]
     LabelNotFound { label: String, line: Option<usize> },
 
+    /// A label was declared more than once.
+    #[error("label {label:?} is already defined on line {line}")]
+    DuplicateLabel { label: String, line: usize },
+
+    /// A predefined constant was given a value which a warrior can't use.
+    #[error("invalid value {value} for constant {name}")]
+    InvalidConstant { name: String, value: u32 },
+
+    /// An `EQU` label was defined in terms of itself, so it can't be expanded.
+    #[error("circular `EQU` definition: {}", .cycle.join(" -> "))]
+    CircularSubstitution { cycle: Vec<String> },
+
+    /// An expression divided by zero, or took the remainder of a division by zero.
+    #[error("division by zero")]
+    DivisionByZero,
+
+    /// An expression or one of its numbers was too large to be evaluated.
+    #[error("value is too large")]
+    Overflow,
+
     /// An invalid warrior origin (not a positive integer) was specified.
     #[error("invalid origin specified")]
     InvalidOrigin(#[from] TryFromIntError),
@@ -27,6 +45,57 @@ pub enum Error {
     /// The given opcode was not given enough arguments.
     #[error("expected additional arguments for {opcode} opcode")]
     InvalidArguments { opcode: Opcode },
+
+    /// A pseudo-opcode which needs an argument, like `ORG`, was given none.
+    #[error("`{pseudo_opcode}` must be given an argument")]
+    MissingArgument { pseudo_opcode: PseudoOpcode },
+
+    /// A pseudo-opcode like `ORG` was used where only an instruction is
+    /// allowed, e.g. in the expansion of an `EQU`.
+    #[error("`{opcode}` can't be used as an instruction")]
+    MisplacedPseudoOpcode { opcode: String },
+
+    /// An `EQU` continuation line was found without a preceding labeled `EQU`.
+    #[error("`EQU` without a label")]
+    EquWithoutLabel,
+
+    /// A `FOR` block was not closed with a `ROF`.
+    #[error("`FOR` without a matching `ROF`")]
+    UnterminatedFor,
+
+    /// A `FOR` block was repeated more times than a warrior can have instructions.
+    #[error("`FOR` count {count} is more than MAXLENGTH ({max_length})")]
+    ForCountTooLarge { count: i32, max_length: u32 },
+
+    /// A `ROF` was found outside of any `FOR` block.
+    #[error("`ROF` without a matching `FOR`")]
+    UnexpectedRof,
+
+    /// Syntax from a later Redcode standard was used, e.g. a modifier in an
+    /// ICWS '88 warrior.
+    #[error("`{syntax}` is not part of the ICWS '{standard} standard")]
+    UnsupportedSyntax { syntax: String, standard: Standard },
+
+    /// A load file contained something other than a fully resolved
+    /// instruction or `ORG`, `PIN` or `END`.
+    #[error("expected {expected}, found {found}")]
+    InvalidLoadFile {
+        expected: &'static str,
+        // Boxed to keep errors small
+        found: Box<str>,
+    },
+
+    /// An `;assert` expression evaluated to zero.
+    #[error("assertion `{assertion}` failed")]
+    AssertionFailed { assertion: String },
+
+    /// An `;assert` expression could not be evaluated.
+    #[error("assertion `{assertion}` could not be evaluated: {reason}")]
+    InvalidAssertion {
+        assertion: String,
+        #[source]
+        reason: Box<Error>,
+    },
 }
 
 /// A warning that occurred while parsing a warrior.
@@ -37,11 +106,143 @@ pub enum Warning {
     #[error("origin already defined as {old:?}, new definition {new:?} will be ignored")]
     OriginRedefinition { old: String, new: String },
 
+    /// Attempt to define the warrior's P-space `PIN` more than once.
+    #[error("PIN already defined as {old:?}, new definition {new:?} will be ignored")]
+    PinRedefinition { old: String, new: String },
+
     /// Empty EQU substitution.
     #[error("right-hand side of substitution for label {0:?} is empty")]
     EmptySubstitution(String),
 
     /// Offset label declaration with no instruction.
-    #[error("no instruction offset for label {0:?}, it will not b")]
+    #[error("no instruction offset for label {0:?}, it will not be defined")]
     EmptyOffset(String),
+
+    /// A label which is declared, but never used.
+    #[error("label {0:?} is never used")]
+    UnusedLabel(String),
+
+    /// An `EQU` which gives a predefined constant like `CORESIZE` a new value.
+    #[error("`EQU` shadows the predefined constant {0:?}")]
+    ShadowedConstant(String),
+
+    /// An instruction which no process started at the origin can execute.
+    #[error("instruction is unreachable from the origin")]
+    UnreachableInstruction,
+
+    /// A `DAT` which a process started at the origin can execute.
+    #[error("`DAT` is executed when running from the origin")]
+    DatExecuted,
+
+    /// A warrior with more instructions than `MAXLENGTH`.
+    #[error("warrior has {length} instructions, more than MAXLENGTH ({max_length})")]
+    TooLong { length: u32, max_length: u32 },
+
+    /// An explicit modifier which differs from the one that would be used
+    /// without it.
+    #[error("modifier `.{modifier}` differs from the default `.{default}`")]
+    NonDefaultModifier {
+        modifier: Modifier,
+        default: Modifier,
+    },
+}
+
+impl Error {
+    /// A hint for how the user might fix the error, if there is one.
+    #[must_use]
+    pub fn help(&self) -> Option<String> {
+        match self {
+            Self::LabelNotFound { label, .. } => Some(format!(
+                "declare `{label}` by labeling an instruction, or define it with `EQU`"
+            )),
+            Self::DuplicateLabel { .. } => Some("rename or remove one of the labels".into()),
+            Self::InvalidConstant { .. } => Some(format!(
+                "constants must be at most {}, and `CORESIZE` must be at least 1",
+                i32::MAX
+            )),
+            Self::CircularSubstitution { .. } => {
+                Some("replace one of the labels in the cycle with its value".into())
+            }
+            Self::DivisionByZero => {
+                Some("make sure the right-hand side of each `/` and `%` is not zero".into())
+            }
+            Self::Overflow => Some(format!(
+                "expressions are evaluated with values from {} to {}",
+                i32::MIN,
+                i32::MAX
+            )),
+            Self::InvalidOrigin(_) => {
+                Some("the origin must evaluate to a non-negative instruction offset".into())
+            }
+            Self::InvalidSyntax(err) => Some(err.variant.message().into_owned()),
+            Self::InvalidArguments { opcode } => Some(format!(
+                "only DAT, JMP, SPL and NOP may omit the B-field, `{opcode}` needs both"
+            )),
+            Self::MissingArgument { pseudo_opcode } => match pseudo_opcode {
+                PseudoOpcode::Org => {
+                    Some("give the offset of the first instruction, e.g. `ORG start`".into())
+                }
+                PseudoOpcode::Pin => Some(
+                    "give the P-space identifier, which warriors with the same `PIN` share".into(),
+                ),
+                _ => Some(format!("add an argument after `{pseudo_opcode}`")),
+            },
+            Self::MisplacedPseudoOpcode { opcode } => Some(format!(
+                "write `{opcode}` on its own line in the warrior, rather than in an `EQU` or `FOR`"
+            )),
+            Self::EquWithoutLabel => {
+                Some("the first line of a multi-line `EQU` must declare a label".into())
+            }
+            Self::UnterminatedFor => Some("end the block with `ROF`".into()),
+            Self::ForCountTooLarge { .. } => {
+                Some("a warrior can't be longer than MAXLENGTH, so lower the count".into())
+            }
+            Self::UnexpectedRof => Some("remove the `ROF`, or start a block with `FOR`".into()),
+            Self::UnsupportedSyntax { standard, .. } => Some(format!(
+                "rewrite the instruction using only ICWS '{standard} syntax, \
+                or assemble the warrior as ICWS '{}",
+                Standard::Icws94
+            )),
+            Self::InvalidLoadFile { .. } => Some(
+                "load files contain only resolved instructions like `MOV.I $0, $1`, \
+                so labels, expressions and default modifiers must be assembled first"
+                    .into(),
+            ),
+            Self::AssertionFailed { .. } => Some(
+                "the warrior does not support these settings, e.g. a different `CORESIZE`".into(),
+            ),
+            Self::InvalidAssertion { reason, .. } => reason.help(),
+        }
+    }
+}
+
+impl Warning {
+    /// A hint for how the user might fix the warning, if there is one.
+    #[must_use]
+    pub fn help(&self) -> Option<String> {
+        match self {
+            Self::OriginRedefinition { .. } => {
+                Some("remove all but the first `ORG` or `END` with an argument".into())
+            }
+            Self::PinRedefinition { .. } => Some("remove all but the first `PIN`".into()),
+            Self::EmptySubstitution(_) => {
+                Some("all uses of the label will be replaced with nothing".into())
+            }
+            Self::EmptyOffset(_) => Some("add an instruction after the label".into()),
+            Self::UnusedLabel(_) => Some("remove the label if it is not needed".into()),
+            Self::ShadowedConstant(name) => Some(format!(
+                "rename the label, so that `{name}` keeps its predefined value"
+            )),
+            Self::UnreachableInstruction => {
+                Some("remove the instruction, or add a jump to it".into())
+            }
+            Self::DatExecuted => Some("executing `DAT` ends the process".into()),
+            Self::TooLong { .. } => {
+                Some("shorten the warrior, or it may not be loaded into the core".into())
+            }
+            Self::NonDefaultModifier { modifier, default } => Some(format!(
+                "remove the modifier if `.{default}` was intended instead of `.{modifier}`"
+            )),
+        }
+    }
 }
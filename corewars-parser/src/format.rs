@@ -0,0 +1,217 @@
+//! Formatting of Redcode source, which normalizes the layout of each statement
+//! but keeps comments, labels and macros as they were written.
+
+use corewars_core::load_file::{AddressMode, Metadata, Modifier, Standard};
+
+use crate::cst;
+use crate::result::Result;
+use crate::source::Statement;
+
+/// The column that comments after a statement are aligned to.
+const COMMENT_COLUMN: usize = 40;
+
+/// Options to control how source is formatted by [`format`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FormatOptions {
+    /// Whether to add the default modifier to instructions without one.
+    explicit_modifiers: bool,
+    /// The Redcode standard of the input, regardless of `;redcode-XX` comments.
+    standard: Option<Standard>,
+}
+
+impl FormatOptions {
+    /// Add the default modifier to every instruction which doesn't have one,
+    /// e.g. `MOV 0, 1` becomes `MOV.I 0, 1`.
+    #[must_use]
+    pub fn with_explicit_modifiers(mut self) -> Self {
+        self.explicit_modifiers = true;
+        self
+    }
+
+    /// Format for the given Redcode standard, ignoring any `;redcode-XX`
+    /// comment in the input.
+    #[must_use]
+    pub fn with_standard(mut self, standard: Standard) -> Self {
+        self.standard = Some(standard);
+        self
+    }
+
+    /// Whether modifiers should be added to `input`. ICWS '88 doesn't have
+    /// modifiers, so adding them would make the output fail to assemble.
+    fn adds_modifiers(&self, input: &str) -> bool {
+        let standard = self.standard.or_else(|| {
+            let mut metadata = Metadata::default();
+            for line in input.lines() {
+                metadata.parse_line(line);
+            }
+            metadata.standard()
+        });
+
+        self.explicit_modifiers && standard.unwrap_or_default().supports_modifiers()
+    }
+}
+
+/// Format Redcode source, using its [`SyntaxTree`](crate::SyntaxTree).
+/// Opcodes are upper-cased and statements are aligned into columns for
+/// labels, opcodes and fields. Comments are kept, and any input after an
+/// `END` is left as it is.
+///
+/// Explicit modifiers are only added to ICWS '94 input, since '88 doesn't
+/// allow them.
+pub fn format(input: &str, options: &FormatOptions) -> Result<String> {
+    let tree = match cst::parse(input).into_result() {
+        Result::Ok(tree, _) => tree,
+        Result::Err(errors, warnings) => return Result::Err(errors, warnings),
+    };
+
+    let explicit_modifiers = options.adds_modifiers(input);
+    let mut output: Vec<String> = Vec::new();
+
+    for line in tree.lines {
+        if line.is_ignored() {
+            output.push(line.to_string().trim_end().to_owned());
+            continue;
+        }
+
+        let comment = line.comment().map(|comment| comment.text.trim_end());
+
+        let Some(mut code) = line.code.clone() else {
+            match comment {
+                Some(comment) => output.push(comment.to_owned()),
+                // Collapse runs of blank lines into one
+                None if output.last().is_none_or(String::is_empty) => {}
+                None => output.push(String::new()),
+            }
+            continue;
+        };
+
+        if explicit_modifiers {
+            if let Some(statement) = code.statement.as_mut() {
+                add_default_modifier(statement);
+            }
+        }
+
+        let mut formatted = code.to_string();
+        if let Some(comment) = comment {
+            let last_line = formatted.rsplit('\n').next().unwrap_or_default();
+            let padding = COMMENT_COLUMN.saturating_sub(last_line.len()).max(1);
+            formatted = format!("{formatted}{:padding$}{comment}", "");
+        }
+        output.push(formatted);
+    }
+
+    while output.last().is_some_and(String::is_empty) {
+        output.pop();
+    }
+
+    let mut formatted = output.join("\n");
+    formatted.push('\n');
+    Result::ok(formatted)
+}
+
+/// Make the modifier of an instruction explicit, using the same defaults as
+/// when it is assembled.
+fn add_default_modifier(statement: &mut Statement) {
+    if let Statement::Instruction {
+        opcode,
+        modifier: modifier @ None,
+        a_field,
+        b_field,
+    } = statement
+    {
+        let mode = |address_mode: Option<AddressMode>| address_mode.unwrap_or_default();
+
+        *modifier = Some(Modifier::default_88_to_94(
+            *opcode,
+            mode(a_field.address_mode),
+            mode(b_field.as_ref().and_then(|field| field.address_mode)),
+        ));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+    use test_case::test_case;
+    use textwrap_macros::dedent;
+
+    use super::*;
+
+    use crate::span::Span;
+    use crate::Settings;
+
+    #[test]
+    fn aligns_statements_and_comments() {
+        let input = dedent!(
+            "
+            ;redcode-94
+            ;name   Dwarf
+
+            ; the bombing loop
+
+
+            step equ 4 ; distance
+            start:  add.ab   #step,target
+             mov  2, @ target + 1  ; bomb away
+                    jmp   start
+            end start
+            not   redcode   at all
+            "
+        );
+
+        let expected = dedent!(
+            "
+            ;redcode-94
+            ;name   Dwarf
+
+            ; the bombing loop
+
+            step    EQU     4                       ; distance
+            start   ADD.AB  #step,  target
+                    MOV     2,      @target + 1     ; bomb away
+                    JMP     start
+                    END     start
+            not   redcode   at all
+            "
+        )
+        .trim_start();
+
+        let formatted = format(input, &FormatOptions::default()).unwrap();
+        assert_eq!(formatted, expected);
+        assert_eq!(
+            format(&formatted, &FormatOptions::default()).unwrap(),
+            formatted
+        );
+    }
+
+    #[test_case("mov 0, 1", "        MOV.I   0,      1\n"; "mov")]
+    #[test_case("add #1, 1", "        ADD.AB  #1,     1\n"; "immediate a")]
+    #[test_case("dat 1", "        DAT.F   1\n"; "one field")]
+    #[test_case("jmp.a 0", "        JMP.A   0\n"; "explicit")]
+    #[test_case("x equ 1", "x       EQU     1\n"; "pseudo")]
+    fn adds_explicit_modifiers(input: &str, expected: &str) {
+        let options = FormatOptions::default().with_explicit_modifiers();
+        assert_eq!(format(input, &options).unwrap(), expected);
+    }
+
+    #[test_case(";redcode-88\nmov 0, 1\njmp -1\n", FormatOptions::default(); "comment")]
+    #[test_case("mov 0, 1\njmp -1\n", FormatOptions::default().with_standard(Standard::Icws88); "option")]
+    fn keeps_88_modifiers_implicit(input: &str, options: FormatOptions) {
+        let options = options.with_explicit_modifiers();
+        let formatted = format(input, &options).unwrap();
+
+        assert!(!formatted.contains('.'), "{}", formatted);
+
+        let settings = Settings::default().with_standard(Standard::Icws88);
+        let parsed = crate::parse_with_settings(&formatted, settings);
+        assert!(parsed.errors().is_empty(), "{:?}", parsed.errors());
+    }
+
+    #[test]
+    fn reports_syntax_errors() {
+        let result = format("mov 0, 1\nmov 0, 1 bar ; oops\n", &FormatOptions::default());
+
+        assert_eq!(result.errors().len(), 1);
+        assert_eq!(result.errors()[0].span, Some(Span::new(1, 9, 12)));
+    }
+}
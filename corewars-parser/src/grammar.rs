@@ -16,9 +16,10 @@ pub type SyntaxError = pest::error::Error<Rule>;
 pub struct Grammar;
 
 /// Parse an input line and flatten it to only include the terminal token pairs,
-/// i.e. pairs without any inner token pairs.
-pub fn tokenize(line: &str) -> Vec<Pair> {
-    parse_line(line)
+/// i.e. pairs without any inner token pairs. Any trailing text which doesn't
+/// match the grammar is ignored, since it may still be part of a substitution.
+pub fn tokenize(line: &str) -> Vec<Pair<'_>> {
+    Grammar::parse(Rule::Line, line)
         .map(|pairs| {
             pairs
                 .flatten()
@@ -29,13 +30,14 @@ pub fn tokenize(line: &str) -> Vec<Pair> {
         .unwrap_or_default()
 }
 
-/// Parse a single line of input according to the grammar.
-pub fn parse_line(line: &str) -> Result<Pairs, Error> {
-    Ok(Grammar::parse(Rule::Line, line).map_err(Box::new)?)
+/// Parse a single line of input according to the grammar. Unlike [`tokenize`],
+/// the entire line must be valid.
+pub fn parse_line(line: &str) -> Result<Pairs<'_>, Error> {
+    Ok(Grammar::parse(Rule::CompleteLine, line).map_err(Box::new)?)
 }
 
 /// Parse a single expression as a string.
-pub fn parse_expression(line: &str) -> Result<Pair, Error> {
+pub fn parse_expression(line: &str) -> Result<Pair<'_>, Error> {
     let mut pairs = Grammar::parse(Rule::Expression, line).map_err(Box::new)?;
 
     pairs
@@ -349,6 +351,13 @@ mod test {
         });
     }
 
+    #[test_case("mov 0, 1 foo"; "trailing label")]
+    #[test_case("mov 0,, 1"; "empty field")]
+    #[test_case("123"; "no statement")]
+    fn parse_incomplete_line(input: &str) {
+        parse_line(input).expect_err("Line should not parse in full");
+    }
+
     #[test_case("lbl", &[(Label, "lbl")]; "label")]
     #[test_case("lbl: ", &[(Label, "lbl")]; "label with colon")]
     #[test_case(
@@ -361,6 +370,11 @@ mod test {
         ];
         "label instruction"
     )]
+    #[test_case(
+        "mov 0, 1 lbl",
+        &[(Opcode, "mov"), (Number, "0"), (Number, "1")];
+        "trailing text"
+    )]
     #[test_case(
         "lbl equ 4",
         &[(Label, "lbl"), (Substitution, "4")];
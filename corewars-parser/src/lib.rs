@@ -5,38 +5,88 @@
 // TODO(#43)
 #![allow(clippy::missing_panics_doc)]
 
+pub use cst::{LabelOccurrence, SyntaxLine, SyntaxTree, Token, TokenKind};
+pub use diagnostic::{Diagnostic, Severity};
 pub use error::{Error, Warning};
+pub use format::{format, FormatOptions};
+pub use lint::lint;
+pub use phase::LabelDefinition;
 pub use result::Result;
+pub use settings::Settings;
+pub use source::{Operand, SourceLine, Statement};
+pub use span::{Expansion, Span, Spanned};
 
+mod cst;
+mod diagnostic;
 mod error;
+mod format;
 mod grammar;
+mod lint;
+mod load_file;
 mod phase;
 mod result;
+mod settings;
+mod source;
+mod span;
 
+use std::collections::BTreeMap;
 use std::convert::TryFrom;
 
-use corewars_core::load_file::Warrior;
+use corewars_core::load_file::ResolvedWarrior;
 
 use phase::{CommentsRemoved, Evaluated, Expanded, Output, Phase, Raw};
 
 /// Parse a given input string into a [`Result`](Result). If successful the
-/// `Result` will contain a `Warrior`, otherwise it will contain an error. In
-/// either case, one or more [`Warning`](error::Warning)s may be generated with
-/// the `Warrior`.
-pub fn parse(input: &str) -> Result<Warrior> {
-    parse_impl(input).into()
+/// `Result` will contain a `ResolvedWarrior`, ready to be loaded into a core,
+/// otherwise it will contain every error found in the input. In either case,
+/// one or more [`Warning`](error::Warning)s may be generated with the `Warrior`.
+pub fn parse(input: &str) -> Result<ResolvedWarrior> {
+    parse_with_settings(input, Settings::default())
 }
 
-fn parse_impl(input: &str) -> std::result::Result<Warrior, Error> {
-    let raw = Phase::<Raw>::from(input);
+/// Read a warrior in "load file" format, such as the output of
+/// `corewars dump`. This is much faster than [`parse`](parse), but only
+/// accepts fully resolved instructions with explicit modifiers and address
+/// modes, so that a dumped warrior is read back exactly as it was written.
+pub fn parse_load_file(input: &str) -> Result<ResolvedWarrior> {
+    load_file::parse(input)
+}
 
+/// Find the labels declared in a given input string, and what they resolve to
+/// in the environment described by `settings`. Labels are found even if the
+/// warrior has errors elsewhere.
+#[must_use]
+pub fn parse_labels(input: &str, settings: Settings) -> BTreeMap<String, LabelDefinition> {
+    let raw = Phase::<Raw>::new(input, settings);
     let cleaned = Phase::<CommentsRemoved>::from(raw);
-
     let expanded = Phase::<Expanded>::from(cleaned);
 
-    let evaluated = Phase::<Evaluated>::try_from(expanded)?;
+    expanded.state.labels
+}
+
+/// Parse a given input string into a lossless [`SyntaxTree`](SyntaxTree),
+/// which keeps every token, comment and span of the input. Lines with invalid
+/// syntax are reported in the tree rather than failing the parse.
+#[must_use]
+pub fn parse_tree(input: &str) -> SyntaxTree {
+    cst::parse(input)
+}
+
+/// Parse a given input string like [`parse`](parse), but for the environment
+/// described by `settings`, e.g. to override the value of `CORESIZE`.
+pub fn parse_with_settings(input: &str, settings: Settings) -> Result<ResolvedWarrior> {
+    let raw = Phase::<Raw>::new(input, settings);
+
+    let cleaned = Phase::<CommentsRemoved>::from(raw);
 
-    let output = Phase::<Output>::from(evaluated);
+    let mut expanded = Phase::<Expanded>::from(cleaned);
+    let warnings = std::mem::take(&mut expanded.warnings);
 
-    Ok(output.state.warrior)
+    match Phase::<Evaluated>::try_from(expanded) {
+        Ok(evaluated) => {
+            let output = Phase::<Output>::from(evaluated);
+            Result::Ok(output.state.warrior, warnings)
+        }
+        Err(errors) => Result::Err(errors, warnings),
+    }
 }
@@ -0,0 +1,409 @@
+//! Checks for Redcode which assembles, but is probably a mistake, such as
+//! labels which are never used or a `DAT` in the path of execution.
+
+use std::collections::HashSet;
+use std::convert::TryFrom;
+
+use corewars_core::load_file::{
+    AddressMode, Instruction, Modifier, Opcode, PseudoOpcode, ResolvedWarrior, Value, Warrior,
+};
+
+use crate::cst::{self, SyntaxTree, TokenKind};
+use crate::error::Warning;
+use crate::phase::{CommentsRemoved, Expanded, Line, Phase, Raw};
+use crate::result::Result;
+use crate::settings::Settings;
+use crate::source::Statement;
+use crate::span::Spanned;
+
+/// Parse a warrior like [`parse_with_settings`](crate::parse_with_settings),
+/// and add a warning for each problem found in it. Problems are only looked
+/// for if the warrior has no errors.
+pub fn lint(input: &str, settings: Settings) -> Result<ResolvedWarrior> {
+    let (warrior, mut warnings) = match crate::parse_with_settings(input, settings.clone()) {
+        Result::Ok(warrior, warnings) => (warrior, warnings),
+        Result::Err(errors, warnings) => return Result::Err(errors, warnings),
+    };
+
+    let tree = cst::parse(input);
+    let used = used_labels(&tree);
+
+    warnings.extend(check_labels(&tree, &used, &settings));
+    warnings.extend(check_modifiers(&tree));
+
+    let max_length = settings.constant("MAXLENGTH");
+    let core_size = settings.constant("CORESIZE").unwrap_or_default();
+
+    let raw = Phase::<Raw>::new(input, settings);
+    let expanded = Phase::<Expanded>::from(Phase::<CommentsRemoved>::from(raw));
+    let lines: Vec<&Line> = expanded.instruction_lines().collect();
+
+    // Every warning about instructions needs to know where they came from
+    if lines.len() == warrior.program.instructions.len() {
+        warnings.extend(check_execution(&warrior, &lines, core_size));
+
+        if let Some(max_length) = max_length {
+            let length = warrior.program.len();
+            if length > max_length {
+                let warning = Warning::TooLong { length, max_length };
+                warnings.push(match lines.get(max_length as usize) {
+                    Some(line) => line.spanned(warning, line.span),
+                    None => warning.into(),
+                });
+            }
+        }
+    }
+
+    // Report warnings in the order they appear in the input
+    warnings.sort_by_key(|warning| warning.span.map(|span| (span.line, span.start)));
+    Result::Ok(warrior, warnings)
+}
+
+/// Every label which is used somewhere, including in an `;assert` comment.
+fn used_labels(tree: &SyntaxTree) -> HashSet<String> {
+    let assertions = tree
+        .tokens()
+        .filter(|token| token.kind == TokenKind::Comment)
+        .filter_map(|token| {
+            let comment = token.text.trim_start_matches(';').trim_start();
+            let keyword = comment.get(..6)?;
+            keyword
+                .eq_ignore_ascii_case("assert")
+                .then(|| &comment[6..])
+        })
+        .flat_map(|assertion| cst::identifiers(assertion).map(|(_, label)| label.to_owned()));
+
+    tree.label_occurrences()
+        .into_iter()
+        .filter(|occurrence| !occurrence.is_declaration)
+        .map(|occurrence| occurrence.label)
+        .chain(assertions)
+        .collect()
+}
+
+/// Find labels which are unused, or shadow a predefined constant.
+fn check_labels(
+    tree: &SyntaxTree,
+    used: &HashSet<String>,
+    settings: &Settings,
+) -> Vec<Spanned<Warning>> {
+    let constants = settings.constants();
+    let mut warnings = Vec::new();
+
+    let declarations = tree
+        .label_occurrences()
+        .into_iter()
+        .filter(|occurrence| occurrence.is_declaration);
+
+    for declaration in declarations {
+        let label = declaration.label;
+
+        let statement = tree.lines[declaration.span.line]
+            .code
+            .as_ref()
+            .and_then(|code| code.statement.as_ref());
+        let is_equ = matches!(
+            statement,
+            Some(Statement::Pseudo {
+                opcode: PseudoOpcode::Equ,
+                ..
+            })
+        );
+
+        if is_equ && constants.contains_key(&label) {
+            let warning = Warning::ShadowedConstant(label.clone());
+            warnings.push(Spanned::new(warning, declaration.span));
+        }
+
+        // Labels concatenated with a `FOR` counter can't be matched by name
+        if !used.contains(&label) && !label.contains('&') {
+            warnings.push(Spanned::new(Warning::UnusedLabel(label), declaration.span));
+        }
+    }
+
+    warnings
+}
+
+/// Find explicit modifiers which are different from the default modifier
+/// for the instruction.
+fn check_modifiers(tree: &SyntaxTree) -> Vec<Spanned<Warning>> {
+    let mode = |address_mode: Option<AddressMode>| address_mode.unwrap_or_default();
+
+    tree.lines
+        .iter()
+        .filter_map(|line| {
+            let Some(Statement::Instruction {
+                opcode,
+                modifier: Some(modifier),
+                a_field,
+                b_field,
+            }) = line.code.as_ref()?.statement.as_ref()
+            else {
+                return None;
+            };
+
+            let default = Modifier::default_88_to_94(
+                *opcode,
+                mode(a_field.address_mode),
+                mode(b_field.as_ref().and_then(|field| field.address_mode)),
+            );
+            if *modifier == default {
+                return None;
+            }
+
+            let span = line
+                .tokens
+                .iter()
+                .find(|token| token.kind == TokenKind::Modifier)
+                .map_or(line.span, |token| token.span);
+
+            let warning = Warning::NonDefaultModifier {
+                modifier: *modifier,
+                default,
+            };
+            Some(Spanned::new(warning, span))
+        })
+        .collect()
+}
+
+/// Follow every path of execution from the origin, to find each `DAT` which
+/// is executed, and each other instruction which is never executed. A `DAT`
+/// which is never executed is data, like a bomb, so it isn't reported.
+fn check_execution(warrior: &Warrior, lines: &[&Line], core_size: u32) -> Vec<Spanned<Warning>> {
+    let instructions = &warrior.program.instructions;
+    let origin = warrior.program.origin.unwrap_or_default() as usize;
+    if origin >= instructions.len() {
+        return Vec::new();
+    }
+
+    let mut reachable = vec![false; instructions.len()];
+    // Whether every jump target is known, so unreachable code can be found
+    let mut complete = true;
+    let mut pending = vec![origin];
+
+    while let Some(index) = pending.pop() {
+        if reachable[index] {
+            continue;
+        }
+        reachable[index] = true;
+
+        for next in successors(index, &instructions[index], core_size) {
+            match next {
+                Some(next) if next < instructions.len() => pending.push(next),
+                Some(_) => {}
+                None => complete = false,
+            }
+        }
+    }
+
+    let mut warnings = Vec::new();
+
+    for (index, instruction) in instructions.iter().enumerate() {
+        let line = lines[index];
+        let is_dat = instruction.opcode == Opcode::Dat;
+
+        if reachable[index] && is_dat {
+            warnings.push(line.spanned(Warning::DatExecuted, line.span));
+        } else if !reachable[index] && complete && !is_dat {
+            warnings.push(line.spanned(Warning::UnreachableInstruction, line.span));
+        }
+    }
+
+    warnings
+}
+
+/// The instructions a process may execute after the one at `index`. `None`
+/// is used for a jump whose target can't be known before the warrior runs.
+fn successors(index: usize, instruction: &Instruction, core_size: u32) -> Vec<Option<usize>> {
+    let offset = |offset: i64| {
+        let core_size = i64::from(core_size.max(1));
+        let index = i64::try_from(index).ok()?;
+        usize::try_from((index + offset).rem_euclid(core_size)).ok()
+    };
+
+    let target = match (
+        &instruction.a_field.address_mode,
+        &instruction.a_field.value,
+    ) {
+        // An immediate A-field points to the instruction itself
+        (AddressMode::Immediate, _) => Some(index),
+        (AddressMode::Direct, Value::Literal(value)) => offset(i64::from(*value)),
+        _ => None,
+    };
+
+    match instruction.opcode {
+        Opcode::Dat => Vec::new(),
+        Opcode::Jmp => vec![target],
+        Opcode::Jmz | Opcode::Jmn | Opcode::Djn | Opcode::Spl => vec![target, offset(1)],
+        Opcode::Cmp | Opcode::Seq | Opcode::Sne | Opcode::Slt => vec![offset(1), offset(2)],
+        _ => vec![offset(1)],
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+    use test_case::test_case;
+    use textwrap_macros::dedent;
+
+    use super::*;
+
+    use crate::span::Span;
+
+    fn lints(input: &str) -> Vec<(Warning, Option<Span>)> {
+        match lint(input, Settings::default()) {
+            Result::Ok(_, warnings) => warnings
+                .into_iter()
+                .map(|warning| (warning.value, warning.span))
+                .collect(),
+            Result::Err(errors, _) => panic!("failed to parse {:?}: {:?}", input, errors),
+        }
+    }
+
+    #[test]
+    fn checks_labels() {
+        let input = dedent!(
+            "
+            CORESIZE equ 4000
+            unused  mov 0, 1
+            start   jmp start
+            ;assert step > 0
+            step    equ 2
+            "
+        );
+
+        assert_eq!(
+            lints(input.trim_start()),
+            vec![
+                (
+                    Warning::ShadowedConstant("CORESIZE".into()),
+                    Some(Span::new(0, 0, 8))
+                ),
+                (
+                    Warning::UnusedLabel("CORESIZE".into()),
+                    Some(Span::new(0, 0, 8))
+                ),
+                (
+                    Warning::UnusedLabel("unused".into()),
+                    Some(Span::new(1, 0, 6))
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn checks_execution() {
+        let input = dedent!(
+            "
+            start   add #4, bomb
+                    mov bomb, @bomb
+                    jmp start
+                    spl 0
+            bomb    dat 0, 0
+            "
+        );
+
+        assert_eq!(
+            lints(input.trim_start()),
+            vec![(Warning::UnreachableInstruction, Some(Span::new(3, 8, 13)))]
+        );
+
+        assert_eq!(
+            lints("jmp 3\nmov 0, 1\nmov 0, 1\ndat 0\n"),
+            vec![
+                (Warning::UnreachableInstruction, Some(Span::new(1, 0, 8))),
+                (Warning::UnreachableInstruction, Some(Span::new(2, 0, 8))),
+                (Warning::DatExecuted, Some(Span::new(3, 0, 5))),
+            ]
+        );
+    }
+
+    #[test]
+    fn checks_execution_of_used_labels() {
+        let input = "unused dat 0\nstart mov 0, 1\njmp start\ndead add 1, 1\n";
+
+        assert_eq!(
+            lints(input),
+            vec![
+                (
+                    Warning::UnusedLabel("unused".into()),
+                    Some(Span::new(0, 0, 6))
+                ),
+                (Warning::DatExecuted, Some(Span::new(0, 7, 12))),
+                (Warning::UnreachableInstruction, Some(Span::new(1, 6, 14))),
+                (Warning::UnreachableInstruction, Some(Span::new(2, 0, 9))),
+                (
+                    Warning::UnusedLabel("dead".into()),
+                    Some(Span::new(3, 0, 4))
+                ),
+                (Warning::UnreachableInstruction, Some(Span::new(3, 5, 13))),
+            ]
+        );
+
+        // A labeled instruction which is executed is code, not data
+        assert_eq!(
+            lints(&format!("org start\n{input}")),
+            vec![
+                (
+                    Warning::UnusedLabel("unused".into()),
+                    Some(Span::new(1, 0, 6))
+                ),
+                (
+                    Warning::UnusedLabel("dead".into()),
+                    Some(Span::new(4, 0, 4))
+                ),
+                (Warning::UnreachableInstruction, Some(Span::new(4, 5, 13))),
+            ]
+        );
+    }
+
+    #[test]
+    fn skips_unreachable_check_for_unknown_jumps() {
+        assert_eq!(lints("jmp @1\nmov 0, 1\n"), vec![]);
+    }
+
+    #[test_case("mov.i 0, 1", vec![]; "default")]
+    #[test_case("mov.ab 0, 1", vec![(Modifier::AB, Modifier::I)]; "mov")]
+    #[test_case("add.ab #1, 1\njmp -1", vec![]; "immediate default")]
+    #[test_case("add.f #1, 1\njmp -1", vec![(Modifier::F, Modifier::AB)]; "immediate")]
+    fn checks_modifiers(input: &str, expected: Vec<(Modifier, Modifier)>) {
+        let expected: Vec<(Warning, Option<Span>)> = expected
+            .into_iter()
+            .map(|(modifier, default)| {
+                let start = 4;
+                let end = start + modifier.to_string().len();
+                (
+                    Warning::NonDefaultModifier { modifier, default },
+                    Some(Span::new(0, start, end)),
+                )
+            })
+            .collect();
+
+        assert_eq!(lints(input), expected);
+    }
+
+    #[test]
+    fn checks_length() {
+        let settings = Settings::default().with_constant("MAXLENGTH", 2).unwrap();
+        let result = lint("mov 0, 1\nmov 0, 1\nmov 0, 1\n", settings);
+
+        assert_eq!(
+            result.warnings(),
+            &[Spanned::new(
+                Warning::TooLong {
+                    length: 3,
+                    max_length: 2
+                },
+                Span::new(2, 0, 8)
+            )]
+        );
+    }
+
+    #[test]
+    fn reports_errors_without_lints() {
+        let result = lint("unused jmp nowhere\n", Settings::default());
+
+        assert_eq!(result.errors().len(), 1);
+        assert_eq!(result.warnings(), &[]);
+    }
+}
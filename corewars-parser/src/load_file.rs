@@ -0,0 +1,355 @@
+//! A strict reader for warriors in "load file" format, as written by
+//! `corewars dump` or pMARS. Every value in a load file is already resolved,
+//! so it is read directly rather than going through the parser phases.
+
+use std::convert::TryFrom;
+use std::str::FromStr;
+
+use corewars_core::load_file::{
+    AddressMode, Field, Instruction, Modifier, Opcode, PseudoOpcode, ResolvedWarrior, Value,
+    Warrior,
+};
+
+use crate::error::{Error, Warning};
+use crate::result::Result;
+use crate::span::{Span, Spanned};
+
+/// Read a warrior in load file format. Unlike Redcode source, every
+/// instruction must have an explicit modifier, every field an explicit
+/// address mode, and every value must be a number. Labels, expressions and
+/// macros are rejected. Any input after `END` is ignored.
+pub fn parse(input: &str) -> Result<ResolvedWarrior> {
+    let mut warrior = Warrior::default();
+    let mut origin: Option<(u32, String)> = None;
+    let mut errors = Vec::new();
+    let mut warnings = Vec::new();
+
+    for (i, text) in input.lines().enumerate() {
+        let code = warrior.metadata.parse_line(text);
+        let start = text.len() - text.trim_start().len();
+        let mut fields = Fields::new(&code, i, start);
+
+        let result = match fields.word() {
+            None if code.is_empty() => continue,
+            None => Err(fields.unexpected("an opcode")),
+            Some(word) => match PseudoOpcode::from_str(&word.to_uppercase()) {
+                Ok(PseudoOpcode::Org) => fields.origin().map(Line::Origin),
+                Ok(PseudoOpcode::End) => fields.optional_origin().map(Line::End),
+                Ok(PseudoOpcode::Pin) => fields.number().map(Line::Pin),
+                _ => fields.instruction(word).map(Line::Instruction),
+            },
+        };
+
+        let line = match result.and_then(|line| fields.end().map(|()| line)) {
+            Ok(line) => line,
+            Err(err) => {
+                errors.push(err);
+                continue;
+            }
+        };
+
+        let ended = matches!(line, Line::End(_));
+        let new_origin = match line {
+            Line::Instruction(instruction) => {
+                warrior.program.instructions.push(instruction);
+                None
+            }
+            Line::Pin(pin) => {
+                warrior.program.pin = Some(pin);
+                None
+            }
+            Line::Origin(value) => Some(value),
+            Line::End(value) => value,
+        };
+
+        if let Some(value) = new_origin {
+            let text = value.to_string();
+            match &origin {
+                Some((_, old)) => warnings.push(Spanned::new(
+                    Warning::OriginRedefinition {
+                        old: old.clone(),
+                        new: text,
+                    },
+                    Span::new(i, start, start + code.len()),
+                )),
+                None => origin = Some((value, text)),
+            }
+        }
+
+        if ended {
+            break;
+        }
+    }
+
+    warrior.program.origin = origin.map(|(value, _)| value);
+
+    if errors.is_empty() {
+        // Every field is read as a number
+        let warrior = ResolvedWarrior::try_from(warrior).expect("load file should have no labels");
+        Result::Ok(warrior, warnings)
+    } else {
+        Result::Err(errors, warnings)
+    }
+}
+
+/// A line of a load file, once it has been read.
+enum Line {
+    Instruction(Instruction),
+    Origin(u32),
+    End(Option<u32>),
+    Pin(i32),
+}
+
+/// The fields of a single line, which are read in order.
+struct Fields<'a> {
+    text: &'a str,
+    line: usize,
+    /// The column where `text` starts in the line
+    offset: usize,
+    position: usize,
+}
+
+impl<'a> Fields<'a> {
+    fn new(text: &'a str, line: usize, offset: usize) -> Self {
+        Self {
+            text,
+            line,
+            offset,
+            position: 0,
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = &self.text[self.position..];
+        self.position += rest.len() - rest.trim_start().len();
+    }
+
+    /// Read a run of characters matching `predicate`, which may be empty.
+    fn take_while(&mut self, predicate: impl Fn(char) -> bool) -> &'a str {
+        let rest = &self.text[self.position..];
+        let len = rest.find(|c| !predicate(c)).unwrap_or(rest.len());
+        self.position += len;
+        &rest[..len]
+    }
+
+    fn word(&mut self) -> Option<&'a str> {
+        self.skip_whitespace();
+        Some(self.take_while(|c| c.is_ascii_alphabetic())).filter(|word| !word.is_empty())
+    }
+
+    /// Read the given character, if it is next.
+    fn symbol(&mut self, symbol: char) -> bool {
+        self.skip_whitespace();
+        let found = self.text[self.position..].starts_with(symbol);
+        if found {
+            self.position += symbol.len_utf8();
+        }
+        found
+    }
+
+    fn number(&mut self) -> std::result::Result<i32, Spanned<Error>> {
+        self.skip_whitespace();
+        let start = self.position;
+
+        if self.text[self.position..].starts_with(['-', '+']) {
+            self.position += 1;
+        }
+        self.take_while(|c| c.is_ascii_digit());
+
+        match self.text[start..self.position].parse() {
+            Ok(number) => Ok(number),
+            Err(_) => {
+                self.position = start;
+                Err(self.unexpected("a number"))
+            }
+        }
+    }
+
+    fn origin(&mut self) -> std::result::Result<u32, Spanned<Error>> {
+        self.skip_whitespace();
+        let start = self.position;
+
+        let number = self.number()?;
+        u32::try_from(number).map_err(|err| {
+            let span = self.span(start, self.position);
+            Spanned::new(Error::InvalidOrigin(err), span)
+        })
+    }
+
+    fn optional_origin(&mut self) -> std::result::Result<Option<u32>, Spanned<Error>> {
+        self.skip_whitespace();
+        if self.position == self.text.len() {
+            Ok(None)
+        } else {
+            self.origin().map(Some)
+        }
+    }
+
+    fn instruction(&mut self, opcode: &str) -> std::result::Result<Instruction, Spanned<Error>> {
+        let opcode = Opcode::from_str(&opcode.to_uppercase()).map_err(|_| {
+            self.position -= opcode.len();
+            self.unexpected("an opcode")
+        })?;
+
+        if !self.symbol('.') {
+            return Err(self.unexpected("a modifier, like `.I`"));
+        }
+        let modifier = self
+            .word()
+            .and_then(|modifier| Modifier::from_str(&modifier.to_uppercase()).ok())
+            .ok_or_else(|| self.unexpected("a modifier, like `.I`"))?;
+
+        let a_field = self.field()?;
+        if !self.symbol(',') {
+            return Err(self.unexpected("`,`"));
+        }
+        let b_field = self.field()?;
+
+        Ok(Instruction {
+            opcode,
+            modifier,
+            a_field,
+            b_field,
+        })
+    }
+
+    fn field(&mut self) -> std::result::Result<Field, Spanned<Error>> {
+        self.skip_whitespace();
+
+        let address_mode = self.text[self.position..]
+            .chars()
+            .next()
+            .and_then(|mode| AddressMode::from_str(&mode.to_string()).ok())
+            .ok_or_else(|| self.unexpected("an address mode, like `$`"))?;
+        self.position += 1;
+
+        Ok(Field {
+            address_mode,
+            value: Value::Literal(self.number()?),
+        })
+    }
+
+    /// Check that the whole line has been read.
+    fn end(&mut self) -> std::result::Result<(), Spanned<Error>> {
+        self.skip_whitespace();
+        if self.position == self.text.len() {
+            Ok(())
+        } else {
+            Err(self.unexpected("the end of the line"))
+        }
+    }
+
+    /// An error for whatever is next in the line, which isn't `expected`.
+    fn unexpected(&self, expected: &'static str) -> Spanned<Error> {
+        let rest = &self.text[self.position..];
+        let len = rest
+            .find(|c: char| c.is_whitespace() || c == ',')
+            .unwrap_or(rest.len())
+            .max(rest.chars().next().map_or(0, char::len_utf8));
+
+        let found = match &rest[..len] {
+            "" => "the end of the line".into(),
+            token => format!("`{token}`").into(),
+        };
+
+        Spanned::new(
+            Error::InvalidLoadFile { expected, found },
+            self.span(self.position, self.position + len),
+        )
+    }
+
+    fn span(&self, start: usize, end: usize) -> Span {
+        Span::new(self.line, self.offset + start, self.offset + end)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+    use test_case::test_case;
+    use textwrap_macros::dedent;
+
+    use super::*;
+
+    #[test]
+    fn reads_load_file() {
+        let input = dedent!(
+            "
+            ;redcode-94
+            ;name Dwarf
+            ORG     1
+            PIN     7
+            DAT.F   #0,     #0
+            add.ab  #4,     $-1 ; step
+            MOV.I   $-2,    @-2
+            JMP.B   $-2,    $0
+            END
+            this is ignored
+            "
+        )
+        .trim_start();
+
+        let warrior = parse(input).unwrap();
+
+        assert_eq!(warrior.metadata.name.as_deref(), Some("Dwarf"));
+        assert_eq!(warrior.program.origin, Some(1));
+        assert_eq!(warrior.program.pin, Some(7));
+        assert_eq!(warrior.program.instructions.len(), 4);
+        assert_eq!(
+            warrior.program.instructions[1],
+            Instruction {
+                opcode: Opcode::Add,
+                modifier: Modifier::AB,
+                a_field: Field::immediate(4),
+                b_field: Field::direct(-1),
+            }
+        );
+    }
+
+    #[test]
+    fn round_trips() {
+        let input = ";redcode-94\n;name Imp\nORG     0\nMOV.I   $0,     $1";
+        assert_eq!(parse(input).unwrap().to_string(), input);
+    }
+
+    #[test]
+    fn rejects_negative_origin() {
+        let result = parse("ORG -1\nDAT.F $0, $0");
+
+        assert_eq!(result.errors().len(), 1);
+        assert_eq!(result.errors()[0].span, Some(Span::new(0, 4, 6)));
+    }
+
+    #[test]
+    fn warns_origin_redefinition() {
+        let result = parse("ORG 1\nDAT.F $0, $0\nDAT.F $0, $0\nEND 0");
+
+        assert_eq!(result.warnings().len(), 1);
+        assert_eq!(result.unwrap().program.origin, Some(1));
+    }
+
+    #[test_case("MOV $0, $1", "a modifier, like `.I`", "`$0`", 4, 6; "modifier")]
+    #[test_case("MOV.I 0, $1", "an address mode, like `$`", "`0`", 6, 7; "address mode")]
+    #[test_case("MOV.I $start, $1", "a number", "`start`", 7, 12; "label")]
+    #[test_case("MOV.I $1+1, $1", "`,`", "`+1`", 8, 10; "expression")]
+    #[test_case("MOV.I $0", "`,`", "the end of the line", 8, 8; "one field")]
+    #[test_case("start MOV.I $0, $1", "an opcode", "`start`", 0, 5; "label declaration")]
+    fn rejects_unresolved_code(
+        input: &str,
+        expected: &'static str,
+        found: &str,
+        start: usize,
+        end: usize,
+    ) {
+        let result = parse(input);
+
+        let error = Error::InvalidLoadFile {
+            expected,
+            found: found.into(),
+        };
+        assert_eq!(
+            result.errors(),
+            &[Spanned::new(error, Span::new(0, start, end))]
+        );
+    }
+}
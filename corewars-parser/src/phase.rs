@@ -1,15 +1,23 @@
 //! This module defines the parser state machine. Each phase of the parser
 //! is a submodule within this module.
 
+use std::collections::BTreeMap;
 use std::convert::TryFrom;
+use std::ops::Range;
 
 mod comment;
 mod evaluation;
 mod expansion;
 
+pub use expansion::LabelDefinition;
+
 use corewars_core::load_file;
+use pest::error::InputLocation;
 
-use super::error::Error;
+use super::error::{Error, Warning};
+use super::grammar;
+use super::settings::Settings;
+use super::span::{Expansion, Span, Spanned};
 
 /// The data type that is passed through the parser phases. This is a simple state
 /// machine, which transitions to the next state by passing through a parser phase.
@@ -17,17 +25,127 @@ use super::error::Error;
 pub struct Phase<PhaseState> {
     /// The original input to the parser, which can be used for spans / string views
     buffer: String,
+    /// The environment the warrior is being assembled for
+    settings: Settings,
+    /// Errors found by the phases run so far. Phases continue past errors
+    /// where possible, so that all of them can be reported at once
+    pub errors: Vec<Spanned<Error>>,
+    /// Warnings emitted by the phases run so far
+    pub warnings: Vec<Spanned<Warning>>,
     /// State specific to the current phase of the state machine
     pub state: PhaseState,
 }
 
+/// A line of code as it passes through the parser, along with where in the
+/// original input it came from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Line {
+    /// The text of the line, which may have been rewritten by previous phases
+    pub text: String,
+    /// The location of the line's code in the original input
+    pub span: Span,
+    /// The `EQU` expansion which produced this line, if any
+    pub expansion: Option<Expansion>,
+    /// The `FOR` counters in scope for this line and their values, innermost last
+    pub counters: Vec<(String, i32)>,
+}
+
+impl Line {
+    pub fn new<S: Into<String>>(text: S, span: Span) -> Self {
+        Self {
+            text: text.into(),
+            span,
+            expansion: None,
+            counters: Vec::new(),
+        }
+    }
+
+    /// The value of a `FOR` counter in scope for this line, if `label` is one.
+    pub fn counter(&self, label: &str) -> Option<i32> {
+        self.counters
+            .iter()
+            .rev()
+            .find(|(counter, _)| counter == label)
+            .map(|&(_, value)| value)
+    }
+
+    /// Find the location in the original input of a byte range within `text`.
+    /// If the line has been rewritten since it was read, this falls back to
+    /// searching the original line for the same text, or else the whole line.
+    pub fn locate(&self, source: &str, range: Range<usize>) -> Span {
+        let unmodified = self.span.end - self.span.start == self.text.len();
+
+        if unmodified && range.end <= self.text.len() {
+            Span::new(
+                self.span.line,
+                self.span.start + range.start,
+                self.span.start + range.end,
+            )
+        } else {
+            let needle = self.text.get(range).unwrap_or_default();
+            self.span.narrow_to(source, needle)
+        }
+    }
+
+    /// Remove the first `len` bytes of the line, e.g. to strip a label declaration.
+    pub fn strip_prefix(&mut self, len: usize) {
+        let unmodified = self.span.end - self.span.start == self.text.len();
+
+        self.text.replace_range(..len, "");
+        if unmodified {
+            self.span.start += len;
+        }
+    }
+
+    /// Attach the location of this line to a value, e.g. an error or warning
+    pub fn spanned<T>(&self, value: T, span: Span) -> Spanned<T> {
+        Spanned::new(value, span).with_expansion(self.expansion.clone())
+    }
+}
+
+/// Locate an error from parsing a line with the grammar. A syntax error at a
+/// single position is widened to the rest of the word at that position.
+pub fn syntax_error(source: &str, line: &Line, err: Error) -> Spanned<Error> {
+    let range = match &err {
+        Error::InvalidSyntax(syntax_error) => match syntax_error.location {
+            InputLocation::Pos(pos) => {
+                pos..line.text[pos..]
+                    .find(char::is_whitespace)
+                    .map_or(line.text.len(), |len| pos + len)
+            }
+            InputLocation::Span((start, end)) => start..end,
+        },
+        _ => 0..line.text.len(),
+    };
+    line.spanned(err, line.locate(source, range))
+}
+
+/// Build lines from plain strings, treating each one as its own source line.
+#[cfg(test)]
+pub fn lines_from(text: &[&str]) -> Vec<Line> {
+    text.iter()
+        .enumerate()
+        .map(|(i, line)| Line::new(*line, Span::new(i, 0, line.len())))
+        .collect()
+}
+
+/// Get the text of each line, to compare against expected test output.
+#[cfg(test)]
+pub fn texts(lines: &[Line]) -> Vec<&str> {
+    lines.iter().map(|line| line.text.as_str()).collect()
+}
+
 /// The initial state of parsing, before any preprocessing has occurred.
 pub struct Raw;
 
-impl From<&str> for Phase<Raw> {
-    fn from(buf: &str) -> Self {
+impl Phase<Raw> {
+    /// Start parsing the given input, for the environment described by `settings`.
+    pub fn new(buf: &str, settings: Settings) -> Self {
         Phase {
             buffer: buf.to_string(),
+            settings,
+            errors: Vec::new(),
+            warnings: Vec::new(),
             state: Raw,
         }
     }
@@ -37,16 +155,26 @@ impl From<&str> for Phase<Raw> {
 /// This phase also parses ORG and END, and removes any text after END
 #[derive(Debug, Default, PartialEq, Eq)]
 pub struct CommentsRemoved {
-    pub lines: Vec<String>,
+    pub lines: Vec<Line>,
     pub metadata: load_file::Metadata,
-    pub origin: Option<String>,
+    pub origin: Option<Line>,
+    pub pin: Option<Line>,
+    pub assertions: Vec<Line>,
+    pub errors: Vec<Spanned<Error>>,
+    pub warnings: Vec<Spanned<Warning>>,
 }
 
 impl From<Phase<Raw>> for Phase<CommentsRemoved> {
-    fn from(prev: Phase<Raw>) -> Self {
-        let state = comment::extract_from_string(&prev.buffer);
+    fn from(mut prev: Phase<Raw>) -> Self {
+        let mut state = comment::extract_from_string(&prev.buffer);
+        prev.errors.append(&mut state.errors);
+        prev.warnings.append(&mut state.warnings);
+
         Self {
             buffer: prev.buffer,
+            settings: prev.settings,
+            errors: prev.errors,
+            warnings: prev.warnings,
             state,
         }
     }
@@ -57,31 +185,69 @@ impl From<Phase<Raw>> for Phase<CommentsRemoved> {
 #[derive(Debug, Default)]
 pub struct Expanded {
     /// The expanded lines of text to be parsed later
-    lines: Vec<String>,
+    lines: Vec<Line>,
 
     /// Metadata gathered in previous phase
     metadata: load_file::Metadata,
 
     /// The entrypoint to the program, gathered in previous phase. This is still
     /// a string because it may be an expression to be evaluated later
-    origin: Option<String>,
+    origin: Option<Line>,
+
+    /// The P-space identifier of the program, gathered in previous phase
+    pin: Option<Line>,
+
+    /// The expressions of any `;assert` comments, gathered in previous phase
+    /// and evaluated in the next one
+    assertions: Vec<Line>,
+
+    /// The labels declared in the program, which have been substituted
+    pub labels: BTreeMap<String, LabelDefinition>,
 }
 
 impl From<Phase<CommentsRemoved>> for Phase<Expanded> {
-    fn from(prev: Phase<CommentsRemoved>) -> Self {
-        let lines = expansion::expand(prev.state.lines, prev.state.origin);
+    fn from(mut prev: Phase<CommentsRemoved>) -> Self {
+        let mut lines = expansion::expand(
+            &prev.buffer,
+            prev.state.lines,
+            prev.state.origin,
+            prev.state.pin,
+            prev.state.assertions,
+            &prev.settings.constants(),
+        );
+        prev.errors.append(&mut lines.errors);
+        prev.warnings.append(&mut lines.warnings);
 
         Self {
             buffer: prev.buffer,
+            settings: prev.settings,
+            errors: prev.errors,
+            warnings: prev.warnings,
             state: Expanded {
-                lines: lines.text,
+                lines: lines.lines,
                 origin: lines.origin,
+                pin: lines.pin,
+                assertions: lines.assertions,
+                labels: lines.labels,
                 metadata: prev.state.metadata,
             },
         }
     }
 }
 
+impl Phase<Expanded> {
+    /// The lines which will be evaluated into instructions, in order.
+    pub fn instruction_lines(&self) -> impl Iterator<Item = &Line> {
+        self.state.lines.iter().filter(|line| {
+            grammar::parse_line(&line.text).is_ok_and(|mut pairs| {
+                pairs
+                    .next()
+                    .is_some_and(|pair| pair.as_rule() == grammar::Rule::Instruction)
+            })
+        })
+    }
+}
+
 /// The program after all expressions have been evaluated. This stage handles
 /// arithmetic and boolean logic, as well as parsing regular integer values.
 #[derive(Debug, Default)]
@@ -94,26 +260,83 @@ pub struct Evaluated {
 }
 
 impl TryFrom<Phase<Expanded>> for Phase<Evaluated> {
-    type Error = Error;
+    type Error = Vec<Spanned<Error>>;
+
+    fn try_from(mut prev: Phase<Expanded>) -> Result<Self, Self::Error> {
+        // A standard given in the settings takes precedence over `;redcode-XX`
+        let standard = prev
+            .settings
+            .standard()
+            .or_else(|| prev.state.metadata.standard())
+            .unwrap_or_default();
+
+        let instructions = match evaluation::evaluate(&prev.buffer, prev.state.lines, standard) {
+            Ok(instructions) => instructions,
+            Err(mut errors) => {
+                prev.errors.append(&mut errors);
+                Vec::new()
+            }
+        };
 
-    fn try_from(prev: Phase<Expanded>) -> Result<Self, Error> {
-        let instructions = evaluation::evaluate(prev.state.lines)?;
         let origin = prev
             .state
             .origin
             .as_ref()
-            .map(|s| evaluation::evaluate_expression(s))
-            .transpose()?;
+            .map(|line| {
+                evaluation::evaluate_origin(&line.text).map_err(|err| line.spanned(err, line.span))
+            })
+            .transpose();
+
+        let origin = match origin {
+            Ok(origin) => origin,
+            Err(err) => {
+                prev.errors.push(err);
+                None
+            }
+        };
+
+        let pin = prev
+            .state
+            .pin
+            .as_ref()
+            .map(|line| {
+                evaluation::evaluate_expression(&line.text)
+                    .map_err(|err| line.spanned(err, line.span))
+            })
+            .transpose();
 
-        // TODO evaluate assertions
+        let pin = match pin {
+            Ok(pin) => pin,
+            Err(err) => {
+                prev.errors.push(err);
+                None
+            }
+        };
+
+        for assertion in &prev.state.assertions {
+            if let Err(err) = evaluation::evaluate_assertion(&prev.buffer, assertion) {
+                prev.errors.push(err);
+            }
+        }
+
+        if !prev.errors.is_empty() {
+            // Report errors in the order they appear in the input
+            prev.errors
+                .sort_by_key(|err| err.span.map(|span| (span.line, span.start)));
+            return Err(prev.errors);
+        }
 
         Ok(Self {
             buffer: prev.buffer,
+            settings: prev.settings,
+            errors: prev.errors,
+            warnings: prev.warnings,
             state: Evaluated {
                 metadata: prev.state.metadata,
                 program: load_file::Program {
                     instructions,
                     origin,
+                    pin,
                 },
             },
         })
@@ -123,18 +346,23 @@ impl TryFrom<Phase<Expanded>> for Phase<Evaluated> {
 /// The final resulting output of the parser, which is suitable for simulation.
 #[derive(Debug)]
 pub struct Output {
-    pub warrior: load_file::Warrior,
+    pub warrior: load_file::ResolvedWarrior,
 }
 
 impl From<Phase<Evaluated>> for Phase<Output> {
     fn from(prev: Phase<Evaluated>) -> Self {
         Self {
             buffer: prev.buffer,
+            settings: prev.settings,
+            errors: prev.errors,
+            warnings: prev.warnings,
             state: Output {
-                warrior: load_file::Warrior {
+                // Evaluation replaces every label with its value
+                warrior: load_file::ResolvedWarrior::try_from(load_file::Warrior {
                     metadata: prev.state.metadata,
                     program: prev.state.program,
-                },
+                })
+                .expect("evaluated warrior should have no labels"),
             },
         }
     }
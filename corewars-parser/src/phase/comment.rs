@@ -1,60 +1,85 @@
 //! In this phase, all comments are removed from the input phase.
 //! Any comments like `;redcode` and `;author` will be parsed and stored in
-//! [`Metadata`]. This phase also finds the origin and end of the program.
+//! [`Metadata`]. This phase also finds the origin, P-space `PIN` and end of
+//! the program, and the expressions of any `;assert` comments.
 
-use super::CommentsRemoved;
+use super::{CommentsRemoved, Line};
 
-use corewars_core::load_file::Metadata;
+use corewars_core::load_file::{Metadata, PseudoOpcode};
 
+use crate::error::{Error, Warning};
 use crate::grammar;
+use crate::span::{Span, Spanned};
 
-enum OriginInLine {
+enum PseudoOpInLine {
     NewOrigin(String),
     EndWithNewOrigin(String),
     End,
+    Pin(String),
     NotFound,
 }
 
 /// Parse a raw String input and return the output sans comments, with metadata.
 pub fn extract_from_string(input: &str) -> CommentsRemoved {
     let mut metadata = Metadata::default();
-    let mut origin: Option<String> = None;
-
-    // Returns whether a new origin was set
-    let mut set_origin = |new_origin: String| {
-        if let Some(old_origin) = origin.as_ref() {
-            // TODO (#25) proper warnings instead of just eprintln
-            eprintln!(
-                "Warning: ORG already defined as {:?}, new definition {:?} will be ignored",
-                new_origin, old_origin
-            );
-        } else {
-            origin = Some(new_origin);
-        }
-    };
+    let mut origin: Option<Line> = None;
+    let mut pin: Option<Line> = None;
+    let mut assertions = Vec::new();
+    let mut errors = Vec::new();
+    let mut warnings = Vec::new();
 
-    let mut lines: Vec<String> = Vec::new();
+    let mut lines: Vec<Line> = Vec::new();
 
-    for line in input.lines() {
+    for (i, line) in input.lines().enumerate() {
         let trimmed_line = metadata.parse_line(line);
+        assertions.extend(find_assertion_in_line(line, i));
+
         if trimmed_line.is_empty() {
             continue;
         }
 
-        if let Ok(found_origin) = find_origin_in_line(&trimmed_line) {
-            match found_origin {
-                OriginInLine::NewOrigin(new_origin) => {
-                    set_origin(new_origin);
+        let start = line.len() - line.trim_start().len();
+        let span = Span::new(i, start, start + trimmed_line.len());
+
+        match find_pseudo_op_in_line(&trimmed_line) {
+            Ok((label, found)) => {
+                if !label.is_empty() && !matches!(found, PseudoOpInLine::NotFound) {
+                    // Keep the label declaration, which refers to the next instruction
+                    let label_span = Span::new(i, span.start, span.start + label.len());
+                    lines.push(Line::new(label, label_span));
                 }
-                OriginInLine::EndWithNewOrigin(new_origin) => {
-                    set_origin(new_origin);
-                    break;
+
+                // The argument expression is always at the end of the line
+                let argument_line = |argument: String| {
+                    let start = span.end - argument.len();
+                    Line::new(argument, Span::new(i, start, span.end))
+                };
+                let origin_redefinition = |old, new| Warning::OriginRedefinition { old, new };
+
+                match found {
+                    PseudoOpInLine::NewOrigin(new_origin) => {
+                        let new_origin = argument_line(new_origin);
+                        define_once(&mut origin, new_origin, &mut warnings, origin_redefinition);
+                    }
+                    PseudoOpInLine::EndWithNewOrigin(new_origin) => {
+                        let new_origin = argument_line(new_origin);
+                        define_once(&mut origin, new_origin, &mut warnings, origin_redefinition);
+                        break;
+                    }
+                    PseudoOpInLine::End => break,
+                    PseudoOpInLine::Pin(new_pin) => {
+                        let pin_redefinition = |old, new| Warning::PinRedefinition { old, new };
+                        define_once(
+                            &mut pin,
+                            argument_line(new_pin),
+                            &mut warnings,
+                            pin_redefinition,
+                        );
+                    }
+                    PseudoOpInLine::NotFound => lines.push(Line::new(trimmed_line, span)),
                 }
-                OriginInLine::End => break,
-                OriginInLine::NotFound => lines.push(trimmed_line),
             }
-        } else {
-            // TODO (#25) return error
+            Err(err) => errors.push(Spanned::new(err, span)),
         }
     }
 
@@ -62,44 +87,93 @@ pub fn extract_from_string(input: &str) -> CommentsRemoved {
         lines,
         metadata,
         origin,
+        pin,
+        assertions,
+        errors,
+        warnings,
     }
 }
 
-/// Find and return the origin defined in the given line.
-fn find_origin_in_line(line: &str) -> Result<OriginInLine, ()> {
-    use OriginInLine::{End, EndWithNewOrigin, NewOrigin, NotFound};
+/// Set the argument of a pseudo-opcode which may only be given once, like
+/// `ORG`. Later definitions are ignored with a warning.
+fn define_once(
+    definition: &mut Option<Line>,
+    new_definition: Line,
+    warnings: &mut Vec<Spanned<Warning>>,
+    warning: fn(String, String) -> Warning,
+) {
+    if let Some(old_definition) = definition.as_ref() {
+        warnings.push(new_definition.spanned(
+            warning(old_definition.text.clone(), new_definition.text.clone()),
+            new_definition.span,
+        ));
+    } else {
+        *definition = Some(new_definition);
+    }
+}
 
-    let tokenized = grammar::tokenize(line);
+/// Find the expression of an `;assert` comment in the given line, if any.
+fn find_assertion_in_line(line: &str, index: usize) -> Option<Line> {
+    let comment = line.split_once(';')?.1.trim_start();
+    let directive = comment.split(char::is_whitespace).next()?;
 
-    if tokenized.is_empty() {
-        return Ok(NotFound);
+    if !directive.eq_ignore_ascii_case("assert") {
+        return None;
     }
 
-    match &tokenized[0].as_rule() {
-        grammar::Rule::Opcode => {
-            let remainder = tokenized
-                .get(1)
-                .map(|s| &line[s.as_span().start()..])
-                .filter(|s| !s.is_empty());
-
-            match tokenized[0].as_str().to_uppercase().as_str() {
-                "ORG" => {
-                    if let Some(remainder) = remainder {
-                        Ok(NewOrigin(remainder.to_owned()))
-                    } else {
-                        // TODO (#25) proper error handling, probably in the return type
-                        eprintln!("Error: ORG must be given an argument!");
-                        Err(())
-                    }
-                }
-                "END" => remainder.map_or(Ok(End), |remainder| {
-                    Ok(EndWithNewOrigin(remainder.to_owned()))
-                }),
-                _ => Ok(NotFound),
-            }
-        }
-        _ => Ok(NotFound),
+    let expression = comment[directive.len()..].trim_start();
+    let start = line.len() - expression.len();
+    let expression = expression.trim_end();
+
+    if expression.is_empty() {
+        return None;
     }
+
+    Some(Line::new(
+        expression,
+        Span::new(index, start, start + expression.len()),
+    ))
+}
+
+/// Find and return the origin, end or PIN defined in the given line, along
+/// with the label declared before it, if any.
+fn find_pseudo_op_in_line(line: &str) -> Result<(&str, PseudoOpInLine), Error> {
+    use grammar::Rule;
+    use PseudoOpInLine::{End, EndWithNewOrigin, NewOrigin, NotFound, Pin};
+
+    let tokenized = grammar::tokenize(line);
+    let rules: Vec<Rule> = tokenized.iter().map(grammar::Pair::as_rule).collect();
+
+    // Without an argument, `ORG` or `PIN` isn't a valid instruction, so the
+    // grammar reads it as a label instead
+    let index = match rules.as_slice() {
+        [Rule::Opcode, ..] | [Rule::Label] => 0,
+        [Rule::Label, Rule::Opcode, ..] | [Rule::Label, Rule::Label] => 1,
+        _ => return Ok(("", NotFound)),
+    };
+    let label = line[..tokenized[index].as_span().start()].trim_end();
+
+    let remainder = tokenized
+        .get(index + 1)
+        .map(|s| &line[s.as_span().start()..])
+        .filter(|s| !s.is_empty());
+
+    // The argument is the rest of the line, which must not be empty
+    let argument = |pseudo_opcode| {
+        remainder
+            .map(str::to_owned)
+            .ok_or(Error::MissingArgument { pseudo_opcode })
+    };
+
+    let found = match tokenized[index].as_str().to_uppercase().as_str() {
+        "ORG" => argument(PseudoOpcode::Org).map(NewOrigin),
+        // `END` may be given an origin, but doesn't need one
+        "END" => Ok(argument(PseudoOpcode::End).map_or(End, EndWithNewOrigin)),
+        "PIN" => argument(PseudoOpcode::Pin).map(Pin),
+        _ => Ok(NotFound),
+    }?;
+
+    Ok((label, found))
 }
 
 #[cfg(test)]
@@ -111,9 +185,12 @@ mod test {
 
     use super::*;
 
+    #[derive(Default)]
     struct Param {
         input: &'static str,
-        expected: CommentsRemoved,
+        lines: &'static [&'static str],
+        metadata: Metadata,
+        origin: Option<&'static str>,
     }
 
     #[test_case(
@@ -124,14 +201,12 @@ mod test {
                 bar di bar
                 baz.  "
             ),
-            expected: CommentsRemoved {
-                lines: vec![
-                    "foo who".to_string(),
-                    "bar di bar".to_string(),
-                    "baz.".to_string(),
-                ],
-                ..Default::default()
-            }
+            lines: &[
+                "foo who",
+                "bar di bar",
+                "baz.",
+            ],
+            ..Default::default()
         };
         "no comments"
     )]
@@ -142,13 +217,11 @@ mod test {
                 ; bar di bar
                 baz. ; bar"
             ),
-            expected: CommentsRemoved {
-                lines: vec![
-                    "foo who".to_string(),
-                    "baz.".to_string(),
-                ],
-                ..Default::default()
-            }
+            lines: &[
+                "foo who",
+                "baz.",
+            ],
+            ..Default::default()
         };
         "remove comments"
     )]
@@ -161,16 +234,14 @@ mod test {
                 ; name my-amazing-warrior
                 MOV 1, 1"
             ),
-            expected: CommentsRemoved {
-                lines: vec!["MOV 1, 1".to_string()],
-                metadata: Metadata {
-                    redcode: Some(String::new()),
-                    name: Some("my-amazing-warrior".to_string()),
-                    author: Some("Ian Chamberlain".to_string()),
-                    ..Default::default()
-                },
+            lines: &["MOV 1, 1"],
+            metadata: Metadata {
+                redcode: Some(String::new()),
+                name: Some("my-amazing-warrior".to_string()),
+                author: Some("Ian Chamberlain".to_string()),
                 ..Default::default()
             },
+            ..Default::default()
         };
         "parse info comments"
     )]
@@ -183,13 +254,11 @@ mod test {
                 ; ORG 4 behind comment ignored
                 "
             ),
-            expected: CommentsRemoved {
-                lines: vec![
-                    "MOV 0, 1".to_string()
-                ],
-                origin: Some("5".to_string()),
-                ..Default::default()
-            },
+            lines: &[
+                "MOV 0, 1"
+            ],
+            origin: Some("5"),
+            ..Default::default()
         };
         "parse ORG"
     )]
@@ -202,13 +271,11 @@ mod test {
                 ; ORG 4 behind comment ignored
                 "
             ),
-            expected: CommentsRemoved {
-                lines: vec![
-                    "lbl1 MOV 0, 1".to_string()
-                ],
-                origin: Some("lbl1".to_string()),
-                ..Default::default()
-            },
+            lines: &[
+                "lbl1 MOV 0, 1"
+            ],
+            origin: Some("lbl1"),
+            ..Default::default()
         };
         "parse ORG label"
     )]
@@ -221,13 +288,11 @@ mod test {
                 ; ORG 4 behind comment ignored
                 "
             ),
-            expected: CommentsRemoved {
-                lines: vec![
-                    "lbl1 MOV 0, 1".to_string()
-                ],
-                origin: Some("lbl1 + 1".to_string()),
-                ..Default::default()
-            },
+            lines: &[
+                "lbl1 MOV 0, 1"
+            ],
+            origin: Some("lbl1 + 1"),
+            ..Default::default()
         };
         "parse ORG expression"
     )]
@@ -239,11 +304,9 @@ mod test {
                 ORG 2 ; should warn and leave org 5
                 "
             ),
-            expected: CommentsRemoved {
-                lines: vec![],
-                origin: Some("5".to_string()),
-                ..Default::default()
-            }
+            lines: &[],
+            origin: Some("5"),
+            ..Default::default()
 
         };
         "parse multiple ORG"
@@ -256,11 +319,9 @@ mod test {
                 END 2 ; should warn and leave org 5
                 "
             ),
-            expected: CommentsRemoved {
-                lines: vec![],
-                origin: Some("5".to_string()),
-                ..Default::default()
-            }
+            lines: &[],
+            origin: Some("5"),
+            ..Default::default()
         };
         "parse ORG and END"
     )]
@@ -273,11 +334,9 @@ mod test {
                 END 2
                 "
             ),
-            expected: CommentsRemoved {
-                lines: vec!["MOV 1, 1".to_string()],
-                origin: Some("2".to_string()),
-                ..Default::default()
-            }
+            lines: &["MOV 1, 1"],
+            origin: Some("2"),
+            ..Default::default()
         };
         "parse END"
     )]
@@ -291,11 +350,9 @@ mod test {
                 stuff here should also be ignored
                 "
             ),
-            expected: CommentsRemoved {
-                lines: vec!["MOV 1, 1".to_string()],
-                origin: Some("2".to_string()),
-                ..Default::default()
-            }
+            lines: &["MOV 1, 1"],
+            origin: Some("2"),
+            ..Default::default()
         };
         "parse multiple END"
     )]
@@ -306,14 +363,20 @@ mod test {
                 ; no real data in this input
                 ; some silly comment"
             ),
-            expected: Default::default()
+            ..Default::default()
         };
         "empty result"
     )]
     fn parse(param: &Param) {
         let result = extract_from_string(param.input);
 
-        assert_eq!(result, param.expected);
+        let lines: Vec<&str> = result.lines.iter().map(|line| line.text.as_str()).collect();
+        assert_eq!(lines, param.lines);
+        assert_eq!(result.metadata, param.metadata);
+        assert_eq!(
+            result.origin.as_ref().map(|line| line.text.as_str()),
+            param.origin
+        );
     }
 
     #[test_case(
@@ -324,16 +387,134 @@ mod test {
                 MOV 0, 1
                 "
             ),
-            expected: CommentsRemoved {
-                lines: vec!["MOV 0, 1".to_string()],
-                ..Default::default()
-            }
-        } => ignore ["should error"];
+            lines: &["MOV 0, 1"],
+            ..Default::default()
+        },
+        PseudoOpcode::Org;
         "parse ORG without arg"
     )]
-    fn parse_error(param: &Param) {
+    #[test_case(
+        &Param {
+            input: dedent!(
+                "
+                pin
+                MOV 0, 1
+                "
+            ),
+            lines: &["MOV 0, 1"],
+            ..Default::default()
+        },
+        PseudoOpcode::Pin;
+        "parse PIN without arg"
+    )]
+    #[test_case(
+        &Param {
+            input: dedent!(
+                "
+                key pin
+                MOV 0, 1
+                "
+            ),
+            lines: &["MOV 0, 1"],
+            ..Default::default()
+        },
+        PseudoOpcode::Pin;
+        "parse labeled PIN without arg"
+    )]
+    fn parse_error(param: &Param, pseudo_opcode: PseudoOpcode) {
         let result = extract_from_string(param.input);
 
-        assert_eq!(result, param.expected);
+        let lines: Vec<&str> = result.lines.iter().map(|line| line.text.as_str()).collect();
+        assert_eq!(lines, param.lines);
+
+        let line_length = param.input.lines().nth(1).unwrap_or_default().len();
+        assert_eq!(
+            result.errors,
+            vec![Spanned::new(
+                Error::MissingArgument { pseudo_opcode },
+                Span::new(1, 0, line_length)
+            )]
+        );
+    }
+
+    #[test]
+    fn tracks_line_spans() {
+        let result = extract_from_string("; comment\n\n  mov 0, 1 ; comment\nORG 1 + 1");
+
+        assert_eq!(
+            result.lines,
+            vec![Line::new("mov 0, 1", Span::new(2, 2, 10))]
+        );
+        assert_eq!(result.origin, Some(Line::new("1 + 1", Span::new(3, 4, 9))));
+    }
+
+    #[test]
+    fn finds_assertions() {
+        let result = extract_from_string(
+            ";assert CORESIZE == 8000\nmov 0, 1 ; ASSERT  MAXLENGTH >= 100 \n; asserted 0\n;assert",
+        );
+
+        assert_eq!(
+            result.assertions,
+            vec![
+                Line::new("CORESIZE == 8000", Span::new(0, 8, 24)),
+                Line::new("MAXLENGTH >= 100", Span::new(1, 19, 35)),
+            ]
+        );
+    }
+
+    #[test]
+    fn finds_pin() {
+        let result = extract_from_string("PIN 7 ; shared\nmov 0, 1\npin 8");
+
+        assert_eq!(
+            result.lines,
+            vec![Line::new("mov 0, 1", Span::new(1, 0, 8))]
+        );
+        assert_eq!(result.pin, Some(Line::new("7", Span::new(0, 4, 5))));
+        assert_eq!(
+            result.warnings,
+            vec![Spanned::new(
+                Warning::PinRedefinition {
+                    old: "7".into(),
+                    new: "8".into()
+                },
+                Span::new(2, 4, 5)
+            )]
+        );
+    }
+
+    #[test]
+    fn finds_labeled_pseudo_ops() {
+        let result = extract_from_string("key pin 3\nstart: org 1\nmov 0, 1\nlast end");
+
+        assert_eq!(
+            result.lines,
+            vec![
+                Line::new("key", Span::new(0, 0, 3)),
+                Line::new("start:", Span::new(1, 0, 6)),
+                Line::new("mov 0, 1", Span::new(2, 0, 8)),
+                Line::new("last", Span::new(3, 0, 4)),
+            ]
+        );
+        assert_eq!(result.pin, Some(Line::new("3", Span::new(0, 8, 9))));
+        assert_eq!(result.origin, Some(Line::new("1", Span::new(1, 11, 12))));
+        assert_eq!(result.errors, Vec::new());
+    }
+
+    #[test]
+    fn warns_origin_redefinition() {
+        let result = extract_from_string("ORG 5\nmov 0, 1\nEND 2");
+
+        assert_eq!(
+            result.warnings,
+            vec![Spanned::new(
+                Warning::OriginRedefinition {
+                    old: "5".into(),
+                    new: "2".into()
+                },
+                Span::new(2, 4, 5)
+            )]
+        );
     }
 }
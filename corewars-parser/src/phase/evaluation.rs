@@ -5,37 +5,154 @@ mod expression;
 use std::convert::TryFrom;
 use std::str::FromStr;
 
-use corewars_core::load_file;
+use corewars_core::load_file::{self, Standard};
 
 use super::super::error::Error;
 use super::super::grammar;
-
-/// Convert the text input lines into in-memory data structures
-pub fn evaluate(lines: Vec<String>) -> Result<load_file::Instructions, Error> {
+use super::super::span::Spanned;
+use super::Line;
+
+/// Convert the text input lines into in-memory data structures. Each line is
+/// evaluated independently, so that every invalid line can be reported at once.
+/// Any syntax which isn't part of `standard` is rejected.
+pub fn evaluate(
+    source: &str,
+    lines: Vec<Line>,
+    standard: Standard,
+) -> Result<load_file::Instructions, Vec<Spanned<Error>>> {
     let mut instructions = Vec::with_capacity(lines.len());
+    let mut errors = Vec::new();
 
     for line in lines {
-        if let Some(parse_result) = grammar::parse_line(&line)?.next() {
-            match &parse_result.as_rule() {
-                grammar::Rule::Instruction => {
-                    instructions.push(parse_instruction(parse_result.into_inner())?);
-                }
-                rule => eprintln!("Unexpected rule {rule:?}"),
-            }
+        match evaluate_line(source, &line, standard) {
+            Ok(Some(instruction)) => instructions.push(instruction),
+            Ok(None) => {}
+            Err(err) => errors.push(err),
         }
     }
 
-    Ok(instructions)
+    if errors.is_empty() {
+        Ok(instructions)
+    } else {
+        Err(errors)
+    }
+}
+
+/// Convert a single line into an instruction, if it contains one.
+fn evaluate_line(
+    source: &str,
+    line: &Line,
+    standard: Standard,
+) -> Result<Option<load_file::Instruction>, Spanned<Error>> {
+    let mut pairs =
+        grammar::parse_line(&line.text).map_err(|err| super::syntax_error(source, line, err))?;
+
+    let Some(parse_result) = pairs.next() else {
+        return Ok(None);
+    };
+
+    if let Some(label) = find_label(&parse_result) {
+        let span = label.as_span();
+        return Err(line.spanned(
+            Error::LabelNotFound {
+                label: label.as_str().to_owned(),
+                line: Some(line.span.line + 1),
+            },
+            line.locate(source, span.start()..span.end()),
+        ));
+    }
+
+    if let Some(unsupported) = find_unsupported(&parse_result, standard) {
+        let span = unsupported.as_span();
+        let mut range = span.start()..span.end();
+        if unsupported.as_rule() == grammar::Rule::Modifier {
+            // Include the `.` separating the modifier from the opcode
+            range.start -= 1;
+        }
+
+        return Err(line.spanned(
+            Error::UnsupportedSyntax {
+                syntax: line.text[range.clone()].to_owned(),
+                standard,
+            },
+            line.locate(source, range),
+        ));
+    }
+
+    match &parse_result.as_rule() {
+        grammar::Rule::Instruction => parse_instruction(parse_result.into_inner())
+            .map(Some)
+            .map_err(|err| line.spanned(err, line.span)),
+        rule => {
+            log::warn!("Unexpected rule {rule:?}");
+            Ok(None)
+        }
+    }
 }
 
 /// Parse and evaluate a single expression string to find the entry point to
 /// a warrior.
-pub fn evaluate_expression(expr: &str) -> Result<u32, Error> {
+pub fn evaluate_origin(expr: &str) -> Result<u32, Error> {
+    Ok(u32::try_from(evaluate_expression(expr)?)?)
+}
+
+/// Evaluate the expression of an `;assert` comment, which must be non-zero
+/// for the warrior to be assembled.
+pub fn evaluate_assertion(source: &str, line: &Line) -> Result<(), Spanned<Error>> {
+    // Report the assertion as it was written, before any substitutions
+    let assertion = line.span.text(source).unwrap_or(&line.text).to_owned();
+
+    match evaluate_expression(&line.text) {
+        Ok(0) => Err(line.spanned(Error::AssertionFailed { assertion }, line.span)),
+        Ok(_) => Ok(()),
+        Err(err) => Err(line.spanned(
+            Error::InvalidAssertion {
+                assertion,
+                reason: Box::new(err),
+            },
+            line.span,
+        )),
+    }
+}
+
+/// Parse and evaluate a single expression string.
+pub fn evaluate_expression(expr: &str) -> Result<i32, Error> {
     let expr_pair = grammar::parse_expression(expr)?;
 
-    let origin = expression::evaluate(expr_pair);
+    if let Some(label) = find_label(&expr_pair) {
+        return Err(Error::LabelNotFound {
+            label: label.as_str().to_owned(),
+            line: None,
+        });
+    }
+
+    expression::evaluate(expr_pair)
+}
 
-    Ok(u32::try_from(origin)?)
+/// Find any label remaining in a parsed line or expression. By this phase all
+/// labels should have been substituted, so any remaining ones are undefined.
+fn find_label<'a>(pair: &grammar::Pair<'a>) -> Option<grammar::Pair<'a>> {
+    pair.clone()
+        .into_inner()
+        .flatten()
+        .find(|pair| pair.as_rule() == grammar::Rule::Label)
+}
+
+/// Find the first opcode, modifier or address mode in a parsed line which
+/// isn't part of the given standard.
+fn find_unsupported<'a>(pair: &grammar::Pair<'a>, standard: Standard) -> Option<grammar::Pair<'a>> {
+    pair.clone()
+        .into_inner()
+        .flatten()
+        .find(|pair| match pair.as_rule() {
+            grammar::Rule::Opcode => {
+                parse_opcode(pair).is_ok_and(|opcode| !standard.supports_opcode(opcode))
+            }
+            grammar::Rule::Modifier => !standard.supports_modifiers(),
+            grammar::Rule::AddressMode => load_file::AddressMode::from_str(pair.as_str())
+                .is_ok_and(|mode| !standard.supports_address_mode(mode)),
+            _ => false,
+        })
 }
 
 #[allow(clippy::option_if_let_else)] // TODO
@@ -51,7 +168,7 @@ fn parse_instruction(
         &operation_pairs
             .next()
             .expect("Opcode must be first pair in Operation"),
-    );
+    )?;
 
     let maybe_modifier = operation_pairs
         .peek()
@@ -62,12 +179,13 @@ fn parse_instruction(
         instruction_pairs
             .next()
             .expect("Field must appear after Opcode"),
-    );
+    )?;
 
     let b_field = instruction_pairs
         .next()
         .filter(|pair| pair.as_rule() == grammar::Rule::Field)
-        .map(parse_field);
+        .map(parse_field)
+        .transpose()?;
 
     if let Some(b_field) = b_field {
         let modifier = maybe_modifier.unwrap_or_else(|| {
@@ -113,11 +231,15 @@ fn parse_modifier(modifier_pair: &grammar::Pair) -> load_file::Modifier {
     load_file::Modifier::from_str(modifier_pair.as_str().to_uppercase().as_ref()).unwrap()
 }
 
-fn parse_opcode(opcode_pair: &grammar::Pair) -> load_file::Opcode {
-    load_file::Opcode::from_str(opcode_pair.as_str().to_uppercase().as_ref()).unwrap()
+/// Parse an opcode, which may be a pseudo-opcode like `PIN` if it was
+/// produced by an `EQU`, and so wasn't handled with the other pseudo-opcodes.
+fn parse_opcode(opcode_pair: &grammar::Pair) -> Result<load_file::Opcode, Error> {
+    let opcode = opcode_pair.as_str().to_uppercase();
+
+    load_file::Opcode::from_str(&opcode).map_err(|_| Error::MisplacedPseudoOpcode { opcode })
 }
 
-fn parse_field(field_pair: grammar::Pair) -> load_file::Field {
+fn parse_field(field_pair: grammar::Pair) -> Result<load_file::Field, Error> {
     let mut field_pairs = field_pair.into_inner();
 
     let address_mode = field_pairs
@@ -131,33 +253,33 @@ fn parse_field(field_pair: grammar::Pair) -> load_file::Field {
         field_pairs
             .find(|pair| pair.as_rule() == grammar::Rule::Expression)
             .unwrap_or_else(|| panic!("No expression found in Field: {:?}", field_pairs)),
-    );
+    )?;
 
-    load_file::Field {
+    Ok(load_file::Field {
         address_mode,
         value: load_file::Value::Literal(offset),
-    }
+    })
 }
 
 #[cfg(test)]
 mod test {
+    use test_case::test_case;
 
     use super::*;
+    use crate::phase::lines_from;
+    use crate::span::Span;
     use load_file::{Field, Instruction, Opcode};
 
     #[test]
     fn parse_simple_file() {
-        let simple_input = [
+        let simple_input = lines_from(&[
             "mov 1, 3",
             "mov 100, #12",
             "dat #0, #0",
             "jmp +123, #45",
             "jmp -4",
             "jmp -1",
-        ]
-        .iter()
-        .map(ToString::to_string)
-        .collect();
+        ]);
 
         let expected_core = vec![
             Instruction::new(Opcode::Mov, Field::direct(1), Field::direct(3)),
@@ -168,20 +290,178 @@ mod test {
             Instruction::new(Opcode::Jmp, Field::direct(-1), Field::direct(0)),
         ];
 
-        let parsed = evaluate(simple_input)
-            .unwrap_or_else(|err| panic!("Failed to parse simple file: {}", err));
+        let parsed = evaluate("", simple_input, Standard::Icws94)
+            .unwrap_or_else(|errors| panic!("Failed to parse simple file: {:?}", errors));
 
         assert_eq!(parsed, expected_core);
     }
 
     #[test]
     fn evaluates_origin() {
-        let evaluated = evaluate_expression("2 * (4 + 3)").expect("Should parse successfully");
+        let evaluated = evaluate_origin("2 * (4 + 3)").expect("Should parse successfully");
         assert_eq!(evaluated, 14);
     }
 
     #[test]
     fn fails_for_negative_origin() {
-        evaluate_expression("-10").expect_err("-10 should be an invalid origin");
+        evaluate_origin("-10").expect_err("-10 should be an invalid origin");
+    }
+
+    #[test]
+    fn fails_for_undefined_label() {
+        let source = "mov 0, 1\njmp 0, foo + 1";
+        let lines = lines_from(&["mov 0, 1", "jmp 0, foo + 1"]);
+
+        let errors =
+            evaluate(source, lines, Standard::Icws94).expect_err("foo should not be defined");
+
+        assert_eq!(
+            errors,
+            vec![Spanned::new(
+                Error::LabelNotFound {
+                    label: "foo".into(),
+                    line: Some(2),
+                },
+                Span::new(1, 7, 10)
+            )]
+        );
+    }
+
+    #[test_case("mov.ab 0, 1", ".ab", Span::new(0, 3, 6); "modifier")]
+    #[test_case("sne 0, 1", "sne", Span::new(0, 0, 3); "opcode")]
+    #[test_case("mov }0, 1", "}", Span::new(0, 4, 5); "address mode")]
+    fn rejects_syntax_outside_standard(input: &str, syntax: &str, span: Span) {
+        let lines = lines_from(&[input]);
+        let errors = evaluate(input, lines.clone(), Standard::Icws88)
+            .expect_err("Input is not valid ICWS '88");
+
+        assert_eq!(
+            errors,
+            vec![Spanned::new(
+                Error::UnsupportedSyntax {
+                    syntax: syntax.into(),
+                    standard: Standard::Icws88,
+                },
+                span
+            )]
+        );
+
+        evaluate(input, lines, Standard::Icws94).expect("Input is valid ICWS '94");
+    }
+
+    #[test]
+    fn locates_invalid_arguments() {
+        let source = "  mov 1, 2\n  lbl add 1 ; comment";
+        let lines = vec![
+            Line::new("mov 1, 2", Span::new(0, 2, 10)),
+            Line::new("add 1", Span::new(1, 6, 11)),
+        ];
+
+        let errors = evaluate(source, lines, Standard::Icws94)
+            .expect_err("ADD should require two arguments");
+
+        assert_eq!(
+            errors,
+            vec![Spanned::new(
+                Error::InvalidArguments {
+                    opcode: Opcode::Add
+                },
+                Span::new(1, 6, 11)
+            )]
+        );
+    }
+
+    #[test_case("pin 5"; "pin")]
+    #[test_case("org 1"; "org")]
+    fn rejects_expanded_pseudo_opcode(input: &str) {
+        let errors = evaluate(input, lines_from(&[input]), Standard::Icws94)
+            .expect_err("Pseudo-opcodes are not instructions");
+
+        assert_eq!(
+            errors,
+            vec![Spanned::new(
+                Error::MisplacedPseudoOpcode {
+                    opcode: input[..3].to_uppercase()
+                },
+                Span::new(0, 0, 5)
+            )]
+        );
+    }
+
+    #[test]
+    fn reports_every_invalid_line() {
+        let source = "mov 0, foo\nmov 0, 1 bar\ndat 0\nadd 1\nmov 0 1";
+        let lines = lines_from(&["mov 0, foo", "mov 0, 1 bar", "dat 0", "add 1", "mov 0 1"]);
+
+        let errors =
+            evaluate(source, lines, Standard::Icws94).expect_err("Input contains several errors");
+
+        let spans: Vec<_> = errors.iter().map(|err| err.span.unwrap()).collect();
+        assert_eq!(
+            spans,
+            vec![
+                Span::new(0, 7, 10),
+                Span::new(1, 9, 12),
+                Span::new(3, 0, 5),
+                Span::new(4, 6, 7),
+            ]
+        );
+
+        assert!(matches!(errors[0].value, Error::LabelNotFound { .. }));
+        assert!(matches!(errors[1].value, Error::InvalidSyntax(_)));
+        assert!(matches!(errors[2].value, Error::InvalidArguments { .. }));
+        assert!(matches!(errors[3].value, Error::InvalidSyntax(_)));
+    }
+
+    #[test]
+    fn evaluates_assertions() {
+        let source = ";assert CORESIZE == 8000\n;assert CORESIZE % 4\n;assert foo";
+        let assertion = |text: &str, line: usize, end: usize| {
+            let mut line = Line::new(text, Span::new(line, 8, end));
+            line.text = text.replace("CORESIZE", "8000");
+            line
+        };
+
+        assert_eq!(
+            evaluate_assertion(source, &assertion("CORESIZE == 8000", 0, 24)),
+            Ok(())
+        );
+        assert_eq!(
+            evaluate_assertion(source, &assertion("CORESIZE % 4", 1, 20)),
+            Err(Spanned::new(
+                Error::AssertionFailed {
+                    assertion: "CORESIZE % 4".into()
+                },
+                Span::new(1, 8, 20)
+            ))
+        );
+
+        let error = evaluate_assertion(source, &assertion("foo", 2, 11))
+            .expect_err("Assertion contains an undefined label");
+        assert_eq!(error.span, Some(Span::new(2, 8, 11)));
+        assert!(matches!(
+            error.value,
+            Error::InvalidAssertion { ref reason, .. }
+                if matches!(**reason, Error::LabelNotFound { .. })
+        ));
+    }
+
+    #[test_case("1/0", Error::DivisionByZero; "division by zero")]
+    #[test_case("2147483647+1", Error::Overflow; "overflow")]
+    #[test_case("99999999999", Error::Overflow; "number too large")]
+    fn reports_invalid_assertions(assertion: &str, reason: Error) {
+        let source = format!(";assert {assertion}");
+        let span = Span::new(0, 8, source.len());
+
+        assert_eq!(
+            evaluate_assertion(&source, &Line::new(assertion, span)),
+            Err(Spanned::new(
+                Error::InvalidAssertion {
+                    assertion: assertion.into(),
+                    reason: Box::new(reason),
+                },
+                span
+            ))
+        );
     }
 }
@@ -1,14 +1,17 @@
 //! Helper functions for evaluating an expression syntax tree.
 //!
-//! Most functions here panic instead of returning Result because at this point
-//! any errors should have been caught earlier during initial parsing.
+//! Most functions here panic on an invalid tree instead of returning Result
+//! because at this point any syntax errors should have been caught earlier
+//! during initial parsing. Arithmetic errors like division by zero depend on
+//! the values in the expression, so those are returned as an [`Error`].
 
+use crate::error::Error;
 use crate::grammar::{Pair, Rule};
 
 /// Evaluate an Expression. Panics if the expression tree is invalid, which
 /// should only happen due to programmer error (either the grammar or this code
 /// is incorrect).
-pub fn evaluate(pair: Pair) -> i32 {
+pub fn evaluate(pair: Pair) -> Result<i32, Error> {
     let mut result = None;
     let mut boolean_op: fn(i32, i32) -> i32 =
         |_, _| unreachable!("BooleanOp called before first operand");
@@ -16,7 +19,7 @@ pub fn evaluate(pair: Pair) -> i32 {
     for inner_pair in pair.into_inner() {
         match inner_pair.as_rule() {
             Rule::Value => {
-                let operand = evaluate_value(inner_pair);
+                let operand = evaluate_value(inner_pair)?;
                 result = result.map(|x| boolean_op(x, operand)).or(Some(operand));
             }
             Rule::BooleanOp => {
@@ -30,10 +33,10 @@ pub fn evaluate(pair: Pair) -> i32 {
         }
     }
 
-    result.unwrap_or_else(|| panic!("Invalid Expression"))
+    Ok(result.unwrap_or_else(|| panic!("Invalid Expression")))
 }
 
-fn evaluate_value(pair: Pair) -> i32 {
+fn evaluate_value(pair: Pair) -> Result<i32, Error> {
     let mut result = None;
     let mut compare_op: fn(i32, i32) -> i32 =
         |_, _| unreachable!("CompareOp called before first operand");
@@ -41,7 +44,7 @@ fn evaluate_value(pair: Pair) -> i32 {
     for inner_pair in pair.into_inner() {
         match inner_pair.as_rule() {
             Rule::Sum => {
-                let operand = evaluate_sum(inner_pair);
+                let operand = evaluate_sum(inner_pair)?;
                 result = result.map(|x| compare_op(x, operand)).or(Some(operand));
             }
             Rule::CompareOp => {
@@ -60,23 +63,27 @@ fn evaluate_value(pair: Pair) -> i32 {
         }
     }
 
-    result.unwrap_or_else(|| panic!("Invalid Value"))
+    Ok(result.unwrap_or_else(|| panic!("Invalid Value")))
 }
 
-fn evaluate_sum(pair: Pair) -> i32 {
+fn evaluate_sum(pair: Pair) -> Result<i32, Error> {
     let mut result = None;
-    let mut add_op: fn(i32, i32) -> i32 = |_, _| unreachable!("AddOp called before first operand");
+    let mut add_op: fn(i32, i32) -> Result<i32, Error> =
+        |_, _| unreachable!("AddOp called before first operand");
 
     for inner_pair in pair.into_inner() {
         match inner_pair.as_rule() {
             Rule::Product => {
-                let operand = evaluate_product(inner_pair);
-                result = result.map(|x| add_op(x, operand)).or(Some(operand));
+                let operand = evaluate_product(inner_pair)?;
+                result = Some(match result {
+                    Some(x) => add_op(x, operand)?,
+                    None => operand,
+                });
             }
             Rule::AddOp => {
                 add_op = match inner_pair.as_str() {
-                    "+" => |a, b| a + b,
-                    "-" => |a, b| a - b,
+                    "+" => |a, b| a.checked_add(b).ok_or(Error::Overflow),
+                    "-" => |a, b| a.checked_sub(b).ok_or(Error::Overflow),
                     op => unreachable!("Invalid AddOp {:?}", op),
                 };
             }
@@ -84,25 +91,28 @@ fn evaluate_sum(pair: Pair) -> i32 {
         }
     }
 
-    result.unwrap_or_else(|| panic!("Invalid Sum"))
+    Ok(result.unwrap_or_else(|| panic!("Invalid Sum")))
 }
 
-fn evaluate_product(pair: Pair) -> i32 {
+fn evaluate_product(pair: Pair) -> Result<i32, Error> {
     let mut result = None;
-    let mut mul_op: fn(i32, i32) -> i32 =
+    let mut mul_op: fn(i32, i32) -> Result<i32, Error> =
         |_, _| unreachable!("MultiplyOp called before first operand");
 
     for inner_pair in pair.into_inner() {
         match inner_pair.as_rule() {
             Rule::UnaryExpr => {
-                let operand = evaluate_unary(inner_pair);
-                result = result.map(|x| mul_op(x, operand)).or(Some(operand));
+                let operand = evaluate_unary(inner_pair)?;
+                result = Some(match result {
+                    Some(x) => mul_op(x, operand)?,
+                    None => operand,
+                });
             }
             Rule::MultiplyOp => {
                 mul_op = match inner_pair.as_str() {
-                    "*" => |a, b| a * b,
-                    "/" => |a, b| a / b,
-                    "%" => |a, b| a % b,
+                    "*" => |a, b| a.checked_mul(b).ok_or(Error::Overflow),
+                    "/" => |a, b| checked(a, b, i32::checked_div),
+                    "%" => |a, b| checked(a, b, i32::checked_rem),
                     op => unreachable!("Invalid MultiplyOp {:?}", op),
                 };
             }
@@ -114,21 +124,31 @@ fn evaluate_product(pair: Pair) -> i32 {
         }
     }
 
-    result.unwrap_or_else(|| panic!("Invalid Product"))
+    Ok(result.unwrap_or_else(|| panic!("Invalid Product")))
 }
 
-fn evaluate_unary(pair: Pair) -> i32 {
+/// Apply a checked division or remainder, which fails if `b` is zero or the
+/// result overflows.
+fn checked(a: i32, b: i32, op: fn(i32, i32) -> Option<i32>) -> Result<i32, Error> {
+    if b == 0 {
+        return Err(Error::DivisionByZero);
+    }
+
+    op(a, b).ok_or(Error::Overflow)
+}
+
+fn evaluate_unary(pair: Pair) -> Result<i32, Error> {
     let mut result = None;
-    let mut unary_ops: Vec<fn(i32) -> i32> = Vec::new();
+    let mut unary_ops: Vec<fn(i32) -> Option<i32>> = Vec::new();
 
     for inner_pair in pair.into_inner() {
         match inner_pair.as_rule() {
-            Rule::Number => result = Some(evaluate_number(&inner_pair)),
-            Rule::Expression => result = Some(evaluate(inner_pair)),
+            Rule::Number => result = Some(evaluate_number(&inner_pair)?),
+            Rule::Expression => result = Some(evaluate(inner_pair)?),
             Rule::UnaryOp => match inner_pair.as_str() {
-                "-" => unary_ops.push(|x| -x),
+                "-" => unary_ops.push(i32::checked_neg),
                 "+" => (), // Identity function
-                "!" => unary_ops.push(|x| i32::from(x == 0)),
+                "!" => unary_ops.push(|x| Some(i32::from(x == 0))),
                 other => unreachable!("Invalid unary operator {:?}", other),
             },
             other => unreachable!(
@@ -139,18 +159,19 @@ fn evaluate_unary(pair: Pair) -> i32 {
         }
     }
 
+    let mut result = result.unwrap_or_else(|| panic!("UnaryExpr did not contain a value"));
     for op in unary_ops {
-        result = result.map(op);
+        result = op(result).ok_or(Error::Overflow)?;
     }
 
-    result.unwrap_or_else(|| panic!("UnaryExpr did not contain a value"))
+    Ok(result)
 }
 
-fn evaluate_number(pair: &Pair) -> i32 {
+fn evaluate_number(pair: &Pair) -> Result<i32, Error> {
     assert!(pair.as_rule() == Rule::Number);
-    pair.as_str()
-        .parse::<i32>()
-        .unwrap_or_else(|_| panic!("Invalid Number: {:?}", pair.as_str()))
+
+    // The grammar only allows digits, so the number can only be too large
+    pair.as_str().parse::<i32>().map_err(|_| Error::Overflow)
 }
 
 #[cfg(test)]
@@ -191,6 +212,18 @@ mod test {
     fn evaluates_expressions(input: &str) -> i32 {
         let pair = parse_expression(input).expect("Failed to parse as Expression");
 
-        evaluate(pair)
+        evaluate(pair).expect("Failed to evaluate Expression")
+    }
+
+    #[test_case("1 / 0", Error::DivisionByZero; "division by zero")]
+    #[test_case("1 % (2 - 2)", Error::DivisionByZero; "modulo by zero")]
+    #[test_case("2147483647 + 1", Error::Overflow; "sum overflow")]
+    #[test_case("-2147483647 - 2", Error::Overflow; "difference overflow")]
+    #[test_case("65536 * 65536", Error::Overflow; "product overflow")]
+    #[test_case("99999999999", Error::Overflow; "number too large")]
+    fn rejects_invalid_arithmetic(input: &str, expected: Error) {
+        let pair = parse_expression(input).expect("Failed to parse as Expression");
+
+        assert_eq!(evaluate(pair), Err(expected));
     }
 }
@@ -1,68 +1,125 @@
 //! This phase finds and expands substitutions, namely:
 //! - EQU definitions
-//! - FOR blocks (not yet implemented)
+//! - FOR blocks, including their counters and `&` concatenation
 //! - Standard labels which alias an address
 //!
 //! Labels used in the right-hand side of an expression substituted in-place.
 
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap};
 use std::convert::{TryFrom, TryInto};
+use std::fmt::Write as _;
 use std::string::ToString;
 
 use pest::Span;
 
+use crate::error::{Error, Warning};
 use crate::grammar;
+use crate::settings;
+use crate::span::{self, Expansion, Spanned};
 
 use super::evaluation;
+use super::Line;
 
-use corewars_core::load_file::DEFAULT_CONSTANTS;
+use corewars_core::load_file::{LabelMap, DEFAULT_CONSTANTS};
 
 /// The result of expansion and substitution
 #[derive(Debug, Default, PartialEq, Eq)]
 pub struct Lines {
-    pub text: Vec<String>,
-    pub origin: Option<String>,
+    pub lines: Vec<Line>,
+    pub origin: Option<Line>,
+    pub pin: Option<Line>,
+    pub assertions: Vec<Line>,
+    pub labels: BTreeMap<String, LabelDefinition>,
+    pub errors: Vec<Spanned<Error>>,
+    pub warnings: Vec<Spanned<Warning>>,
 }
 
-/// Collect and subsitute all labels found in the input lines.
-pub fn expand(mut text: Vec<String>, mut origin: Option<String>) -> Lines {
-    let labels = collect_and_expand(&mut text);
-
-    substitute_offsets(&mut text, &labels);
-
-    if let Some(origin_str) = origin.as_mut() {
-        substitute_offsets_in_line(origin_str, &labels, 0);
+/// Collect and subsitute all labels found in the input lines. Predefined
+/// labels such as `CORESIZE` are given the values in `constants`.
+pub fn expand(
+    source: &str,
+    mut lines: Vec<Line>,
+    mut origin: Option<Line>,
+    pin: Option<Line>,
+    mut assertions: Vec<Line>,
+    constants: &LabelMap,
+) -> Lines {
+    let (labels, mut errors, warnings) = collect_and_expand(source, &mut lines, constants);
+
+    substitute_offsets(&mut lines, &labels);
+
+    if let Some(origin_line) = origin.as_mut() {
+        substitute_offsets_in_line(&mut origin_line.text, &labels, 0);
     }
 
-    Lines { text, origin }
+    // An expression which uses a circular `EQU` is reported here, and not
+    // evaluated, since its label can't be substituted
+    let mut substitute = |expression: &mut Line| {
+        substitute_in_expression(source, expression, &labels)
+            .map_err(|err| errors.push(err))
+            .is_ok()
+    };
+    let pin = pin.and_then(|mut line| substitute(&mut line).then_some(line));
+    assertions.retain_mut(|assertion| substitute(assertion));
+
+    Lines {
+        lines,
+        origin,
+        pin,
+        assertions,
+        labels: declared_labels(&labels),
+        errors,
+        warnings,
+    }
 }
 
 /// Collect and strip out offset-based label declarations, meanwhile expanding
 /// `EQU` labels.
 #[allow(clippy::too_many_lines)] // TODO(#74): fix this
-fn collect_and_expand(lines: &mut Vec<String>) -> Labels {
+fn collect_and_expand(
+    source: &str,
+    lines: &mut Vec<Line>,
+    constants: &LabelMap,
+) -> (Labels, Vec<Spanned<Error>>, Vec<Spanned<Warning>>) {
     use grammar::Rule;
 
-    let mut collector = Collector::new();
+    let mut collector = Collector::new(constants);
+
+    // A `FOR` block is never repeated more times than a warrior can have instructions
+    let max_count = constants
+        .get("MAXLENGTH")
+        .or_else(|| DEFAULT_CONSTANTS.get("MAXLENGTH"))
+        .copied()
+        .unwrap_or_default();
 
     let mut i: usize = 0;
     let mut offset: u32 = 0;
 
     while i < lines.len() {
         let line = lines[i].clone();
-        let tokenized_line = grammar::tokenize(&line);
+        let tokenized_line = grammar::tokenize(&line.text);
 
         if tokenized_line.is_empty() {
+            // Nothing to expand, but the line may still be invalid, which
+            // will be reported when it is evaluated
+            i += 1;
             continue;
         }
 
         let first_token = &tokenized_line[0];
+        let token_span = |token: &grammar::Pair| {
+            let span = token.as_span();
+            line.locate(source, span.start()..span.end())
+        };
 
         // Returns true if anything was expanded, false otherwise
-        let mut expand_next_token = |collector: &Collector, is_for_expr: bool| {
+        let mut expand_next_token = |collector: &mut Collector| {
             for token in tokenized_line[1..].iter() {
                 if token.as_rule() == Rule::Label {
-                    let label_value = collector.get_label_value(token.as_str(), offset);
+                    let label_value = line
+                        .counter(token.as_str())
+                        .map(LabelValue::RelativeOffset)
+                        .or_else(|| collector.get_label_value(token.as_str(), offset));
 
                     if let Some(label_value) = label_value {
                         match label_value {
@@ -81,25 +138,35 @@ fn collect_and_expand(lines: &mut Vec<String>) -> Labels {
                                     i,
                                     &token.as_span(),
                                     &[relative_offset.to_string()],
+                                    None,
                                 );
                             }
                             LabelValue::RelativeOffset(rel_offset) => {
-                                expand_lines(lines, i, &token.as_span(), &[rel_offset.to_string()]);
+                                expand_lines(
+                                    lines,
+                                    i,
+                                    &token.as_span(),
+                                    &[rel_offset.to_string()],
+                                    None,
+                                );
                             }
                             LabelValue::Substitution(subst) => {
-                                expand_lines(lines, i, &token.as_span(), &subst);
+                                let expansion = collector.expansion(token.as_str());
+                                let subst = collector.check_substitution(
+                                    token.as_str(),
+                                    subst,
+                                    token_span(token),
+                                );
+                                expand_lines(lines, i, &token.as_span(), &subst, expansion);
                             }
                         }
 
                         return true;
                     }
 
-                    if is_for_expr {
-                        panic!("No label value found for expr {:?}", token.as_str());
-                    } else {
-                        // this is probably a forward usage of a label not
-                        // yet declared, which _could_ be an error
-                    }
+                    // this is probably a forward usage of a label not
+                    // yet declared, which _could_ be an error. If this is
+                    // a FOR count, it will be reported when it is evaluated
                 }
             }
 
@@ -108,95 +175,48 @@ fn collect_and_expand(lines: &mut Vec<String>) -> Labels {
 
         match first_token.as_rule() {
             Rule::For => {
-                collector.resolve_pending_labels(offset);
+                collector.resolve_pending_equ();
 
-                if expand_next_token(&collector, true) {
+                if expand_next_token(&mut collector) {
                     continue;
                 }
 
-                let line_remainder = &line[first_token.as_span().end()..];
-                collector.push_for(None, i, offset, line_remainder);
-                // Continue processing lines as normal, since we still need to collect
-                // labels and potentially nested for loops
+                let count_start = first_token.as_span().end();
+                if let Err(err) = expand_for(source, lines, i, None, count_start, max_count) {
+                    collector.errors.push(err);
+                }
+                continue;
             }
             Rule::Rof => {
-                let for_stmt = collector.pop_for();
-
-                let range_to_repeat = (for_stmt.start_line + 1)..i;
-                let i_range_len = i32::try_from(range_to_repeat.len())
-                    .expect("FOR expansion resulted in > i32::MAX lines");
-                let range_len = u32::try_from(range_to_repeat.len())
-                    .expect("FOR expansion resulted in > u32::MAX lines");
-
-                let insert_line_count = for_stmt.iter_count as usize * range_to_repeat.len();
-
-                // We need to subtract the offset, since we end up replacing
-                // those lines. They will be processed normally after substitution
-                offset -= range_len;
-
-                let new_contents = lines[range_to_repeat.clone()]
-                    .iter()
-                    .cloned()
-                    .cycle()
-                    .take(insert_line_count)
-                    .enumerate()
-                    .map(|(i, mut line)| {
-                        if let Some(label) = &for_stmt.index_label {
-                            // UNWRAP: we already checked i_range_len above
-                            let i = i32::try_from(i).unwrap();
-
-                            let subst = i / i_range_len + 1;
-
-                            // This method of label stuff is a little hacky,
-                            // but seems reasonable I guess...
-                            collector
-                                .labels
-                                .insert(label.clone(), LabelValue::RelativeOffset(subst));
-
-                            // UNWRAP: we already checked range_len above
-                            let i = u32::try_from(i).unwrap();
-
-                            substitute_offsets_in_line(
-                                &mut line,
-                                &collector.labels,
-                                for_stmt.start_offset + i,
-                            );
-
-                            collector.labels.remove(label);
-                        }
-                        line
-                    })
-                    .collect::<Vec<_>>();
-
-                let range_to_replace = for_stmt.start_line..=i;
-                lines.splice(range_to_replace, new_contents);
-
-                i = for_stmt.start_line;
-
+                collector
+                    .errors
+                    .push(line.spanned(Error::UnexpectedRof, line.span));
+                lines.remove(i);
                 continue;
             }
             Rule::Label => {
                 if let Some(next_token) = tokenized_line.get(1) {
                     match next_token.as_rule() {
                         Rule::Substitution => {
-                            collector.process_equ(first_token.as_str(), next_token.as_str());
+                            collector.process_equ(
+                                first_token.as_str(),
+                                next_token.as_str(),
+                                token_span(first_token),
+                            );
                             lines.remove(i);
                             continue;
                         }
                         Rule::For => {
-                            collector.resolve_pending_labels(offset);
-
-                            if !expand_next_token(&collector, true) {
-                                let line_remainder = &line[next_token.as_span().end()..];
-
-                                collector.push_for(
-                                    first_token.as_str().to_string(),
-                                    i,
-                                    offset,
-                                    line_remainder,
-                                );
-
-                                i += 1;
+                            collector.resolve_pending_equ();
+
+                            if !expand_next_token(&mut collector) {
+                                let counter = Some(first_token.as_str());
+                                let count_start = next_token.as_span().end();
+                                if let Err(err) =
+                                    expand_for(source, lines, i, counter, count_start, max_count)
+                                {
+                                    collector.errors.push(err);
+                                }
                             }
                             continue;
                         }
@@ -209,13 +229,19 @@ fn collect_and_expand(lines: &mut Vec<String>) -> Labels {
                 if let Some(LabelValue::Substitution(substitution)) =
                     collector.get_label_value(first_token.as_str(), offset)
                 {
-                    expand_lines(lines, i, &first_token.as_span(), &substitution);
+                    let expansion = collector.expansion(first_token.as_str());
+                    let substitution = collector.check_substitution(
+                        first_token.as_str(),
+                        substitution,
+                        token_span(first_token),
+                    );
+                    expand_lines(lines, i, &first_token.as_span(), &substitution, expansion);
                     continue;
                 }
 
-                collector.add_pending_label(first_token.as_str());
+                collector.add_pending_label(first_token.as_str(), token_span(first_token));
 
-                if expand_next_token(&collector, false) {
+                if expand_next_token(&mut collector) {
                     continue;
                 }
 
@@ -224,21 +250,25 @@ fn collect_and_expand(lines: &mut Vec<String>) -> Labels {
                     offset += 1;
 
                     let next_token = tokenized_line[1].as_span();
-                    lines[i] = line[next_token.start()..].to_owned();
+                    lines[i].strip_prefix(next_token.start());
                 } else {
                     lines.remove(i);
                     continue;
                 }
             }
             Rule::Substitution => {
-                collector.process_equ_continuation(first_token.as_str());
+                if !collector.process_equ_continuation(first_token.as_str()) {
+                    collector
+                        .errors
+                        .push(line.spanned(Error::EquWithoutLabel, line.span));
+                }
                 lines.remove(i);
                 continue;
             }
             other_rule => {
                 collector.resolve_pending_labels(offset);
 
-                if expand_next_token(&collector, false) {
+                if expand_next_token(&mut collector) {
                     continue;
                 }
 
@@ -258,41 +288,157 @@ fn collect_and_expand(lines: &mut Vec<String>) -> Labels {
     collector.finish()
 }
 
-fn expand_lines(lines: &mut Vec<String>, index: usize, span: &Span, substitution: &[String]) {
+/// Replace the `FOR` block starting at `index` with its body, repeated as
+/// many times as its count. If the block has a counter, it is substituted in
+/// each repetition of the body. The block is removed if it cannot be expanded,
+/// e.g. if its count is more than `max_count`.
+fn expand_for(
+    source: &str,
+    lines: &mut Vec<Line>,
+    index: usize,
+    counter: Option<&str>,
+    count_start: usize,
+    max_count: u32,
+) -> Result<(), Spanned<Error>> {
+    use grammar::Rule;
+
+    let line = lines[index].clone();
+
+    let mut depth = 0;
+    let end = lines[index + 1..].iter().position(|line| {
+        let tokenized_line = grammar::tokenize(&line.text);
+        let rules: Vec<Rule> = tokenized_line.iter().take(2).map(|t| t.as_rule()).collect();
+
+        match rules[..] {
+            [Rule::For, ..] | [Rule::Label, Rule::For] => depth += 1,
+            [Rule::Rof, ..] if depth == 0 => return true,
+            [Rule::Rof, ..] => depth -= 1,
+            _ => {}
+        }
+        false
+    });
+
+    let Some(end) = end.map(|end| index + 1 + end) else {
+        lines.remove(index);
+        return Err(line.spanned(Error::UnterminatedFor, line.span));
+    };
+
+    let count_expr = &line.text[count_start..];
+    let count_span = || {
+        let start = count_start + count_expr.len() - count_expr.trim_start().len();
+        line.locate(source, start..line.text.len())
+    };
+
+    let (count, result) = match evaluation::evaluate_expression(count_expr) {
+        Ok(count) if u32::try_from(count).is_ok_and(|count| count > max_count) => {
+            let err = Error::ForCountTooLarge {
+                count,
+                max_length: max_count,
+            };
+            (0, Err(line.spanned(err, count_span())))
+        }
+        // A negative count repeats the block zero times
+        Ok(count) => (count.max(0), Ok(())),
+        Err(err) => (0, Err(line.spanned(err, count_span()))),
+    };
+
+    let body = &lines[index + 1..end];
+    let unrolled: Vec<Line> = (1..=count)
+        .flat_map(|value| {
+            body.iter().cloned().map(move |mut body_line| {
+                if let Some(counter) = counter {
+                    body_line.text = concatenate(&body_line.text, counter, value);
+                    body_line.counters.push((counter.to_owned(), value));
+                }
+                body_line
+            })
+        })
+        .collect();
+
+    lines.splice(index..=end, unrolled);
+
+    result
+}
+
+/// Replace each use of `&counter` in a line with the counter's value, which
+/// is zero-padded to two digits like pMARS does, e.g. `x&i` becomes `x01`.
+fn concatenate(text: &str, counter: &str, value: i32) -> String {
+    let pattern = format!("&{counter}");
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(found) = rest.find(&pattern) {
+        let after = &rest[found + pattern.len()..];
+        result.push_str(&rest[..found]);
+
+        if after.starts_with(|c: char| c.is_ascii_alphanumeric() || c == '_') {
+            // Only part of a longer label, e.g. `&ij` when the counter is `i`
+            result.push_str(&pattern);
+        } else {
+            let _ = write!(result, "{value:02}");
+        }
+
+        rest = after;
+    }
+
+    result.push_str(rest);
+    result
+}
+
+fn expand_lines(
+    lines: &mut Vec<Line>,
+    index: usize,
+    span: &Span,
+    substitution: &[String],
+    expansion: Option<Expansion>,
+) {
     let line = &lines[index];
 
-    let before = &line[..span.start()];
-    let after = &line[span.end()..];
+    let before = &line.text[..span.start()];
+    let after = &line.text[span.end()..];
 
     assert!(!substitution.is_empty());
-    let mut new_lines = substitution.to_vec();
+    let mut new_lines: Vec<Line> = substitution
+        .iter()
+        .map(|text| Line {
+            text: text.clone(),
+            span: line.span,
+            expansion: expansion.clone().or_else(|| line.expansion.clone()),
+            counters: line.counters.clone(),
+        })
+        .collect();
 
-    new_lines[0] = before.to_owned() + &new_lines[0];
-    new_lines.last_mut().unwrap().push_str(after);
+    new_lines[0].text = before.to_owned() + &new_lines[0].text;
+    new_lines.last_mut().unwrap().text.push_str(after);
 
     lines.splice(index..=index, new_lines);
 }
 
-fn substitute_offsets(lines: &mut [String], labels: &Labels) {
+fn substitute_offsets(lines: &mut [Line], labels: &Labels) {
     let mut i = 0;
     for line in lines.iter_mut() {
-        let cloned = line.clone();
+        let cloned = line.text.clone();
         let tokenized_line = grammar::tokenize(&cloned);
 
-        if tokenized_line[0].as_rule() == grammar::Rule::Label {
+        let Some(first_token) = tokenized_line.first() else {
+            // An invalid line, which will be reported when it is evaluated
+            continue;
+        };
+
+        if first_token.as_rule() == grammar::Rule::Label {
             if let Some(next_token) = tokenized_line.get(1) {
-                line.replace_range(..next_token.as_span().start(), "");
+                line.strip_prefix(next_token.as_span().start());
             } else {
-                line.clear();
+                line.text.clear();
                 // Skip incrementing offset since the line was just a label
                 continue;
             }
         }
 
-        substitute_offsets_in_line(line, labels, i);
+        substitute_offsets_in_line(&mut line.text, labels, i);
 
-        if tokenized_line[0].as_rule() != grammar::Rule::Opcode
-            || tokenized_line[0].as_str().to_uppercase() != "ORG"
+        if first_token.as_rule() != grammar::Rule::Opcode
+            || first_token.as_str().to_uppercase() != "ORG"
         {
             i += 1;
         }
@@ -334,6 +480,124 @@ fn substitute_offsets_in_line(line: &mut String, labels: &Labels, from_offset: u
     }
 }
 
+/// Substitute all labels in a standalone expression, such as a `PIN` or `;assert`.
+/// Offset labels are replaced by their offset from the start of the program,
+/// and `EQU` labels by their value, joined into a single line. Fails if the
+/// expression uses a circular `EQU`.
+fn substitute_in_expression(
+    source: &str,
+    line: &mut Line,
+    labels: &Labels,
+) -> Result<(), Spanned<Error>> {
+    // An invalid expression will be reported when it is evaluated
+    let Ok(expression) = grammar::parse_expression(&line.text) else {
+        return Ok(());
+    };
+
+    let substitution = expression
+        .into_inner()
+        .flatten()
+        .filter(|pair| pair.as_rule() == grammar::Rule::Label)
+        .find_map(|label| {
+            let span = label.as_span();
+            let range = span.start()..span.end();
+
+            if let Some(cycle) = find_cycle(labels, &mut vec![label.as_str().to_owned()]) {
+                return Some(Err((range, cycle)));
+            }
+
+            let value = match labels.get(label.as_str())? {
+                LabelValue::AbsoluteOffset(offset) => offset.to_string(),
+                LabelValue::RelativeOffset(offset) => offset.to_string(),
+                LabelValue::Substitution(values) => values.join(" "),
+            };
+            Some(Ok((range, value)))
+        });
+
+    match substitution {
+        Some(Ok((range, value))) => {
+            line.text.replace_range(range, &value);
+
+            // As with offsets, re-parse the expression after each substitution
+            substitute_in_expression(source, line, labels)
+        }
+        Some(Err((range, cycle))) => Err(line.spanned(
+            Error::CircularSubstitution { cycle },
+            line.locate(source, range),
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Search the labels used by the last `EQU` label in `path` for one which is
+/// already in the path, and return the labels which form the cycle.
+fn find_cycle(labels: &Labels, path: &mut Vec<String>) -> Option<Vec<String>> {
+    let Some(LabelValue::Substitution(values)) = labels.get(path.last()?) else {
+        return None;
+    };
+
+    let used: Vec<String> = values
+        .iter()
+        .flat_map(|value| {
+            grammar::tokenize(value)
+                .into_iter()
+                .filter(|token| token.as_rule() == grammar::Rule::Label)
+                .map(|token| token.as_str().to_owned())
+                .collect::<Vec<_>>()
+        })
+        .collect();
+
+    for label in used {
+        if let Some(start) = path.iter().position(|other| *other == label) {
+            let mut cycle = path[start..].to_vec();
+            cycle.push(label);
+            return Some(cycle);
+        }
+
+        path.push(label);
+        if let Some(cycle) = find_cycle(labels, path) {
+            return Some(cycle);
+        }
+        path.pop();
+    }
+
+    None
+}
+
+/// A label declared in a warrior, as it was resolved during expansion.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LabelDefinition {
+    /// A label for the instruction at this offset from the start of the warrior
+    Offset(u32),
+    /// An `EQU` label, with the text it is replaced by. If that text is a
+    /// constant expression, its value is also given.
+    Substitution { text: String, value: Option<i32> },
+}
+
+/// The labels declared in the warrior itself, leaving out predefined
+/// constants and `FOR` counters.
+fn declared_labels(labels: &Labels) -> BTreeMap<String, LabelDefinition> {
+    labels
+        .iter()
+        .filter_map(|(label, value)| {
+            let definition = match value {
+                LabelValue::AbsoluteOffset(offset) => LabelDefinition::Offset(*offset),
+                LabelValue::RelativeOffset(_) => return None,
+                LabelValue::Substitution(values) => {
+                    let text = values.join("\n");
+                    let mut expression = Line::new(values.join(" "), span::Span::default());
+                    let value = substitute_in_expression("", &mut expression, labels)
+                        .ok()
+                        .and_then(|()| evaluation::evaluate_expression(&expression.text).ok());
+
+                    LabelDefinition::Substitution { text, value }
+                }
+            };
+            Some((label.clone(), definition))
+        })
+        .collect()
+}
+
 #[derive(Debug, Eq, PartialEq, Clone)]
 enum LabelValue {
     AbsoluteOffset(u32),
@@ -343,82 +607,121 @@ enum LabelValue {
 
 type Labels = HashMap<String, LabelValue>;
 
-fn default_labels() -> Labels {
-    DEFAULT_CONSTANTS
-        .iter()
-        // Counterintuitively, we use a relative offset here so that it doesn't
-        // get translated like absolute offset labels would be
-        .map(|(lbl, value)| {
-            let value = i32::try_from(*value).expect("Default constant values contain invalid i32");
-            (lbl.clone(), LabelValue::RelativeOffset(value))
-        })
-        .collect()
-}
+/// The labels for predefined constants. Constants with a value which can't
+/// be used are left undefined, with an error for each one.
+fn predefined_labels(constants: &LabelMap) -> (Labels, Vec<Spanned<Error>>) {
+    let mut labels = Labels::new();
+    let mut errors = Vec::new();
+
+    for (label, &value) in constants {
+        match settings::check_constant(label, value) {
+            // Counterintuitively, we use a relative offset here so that it
+            // doesn't get translated like absolute offset labels would be.
+            // The check ensures the value fits in an i32.
+            Ok(()) => {
+                labels.insert(label.clone(), LabelValue::RelativeOffset(value as i32));
+            }
+            // Constants come from the settings rather than the input, so the
+            // error has no location
+            Err(err) => errors.push(err.into()),
+        }
+    }
 
-#[derive(Debug)]
-struct ForStatement {
-    index_label: Option<String>,
-    iter_count: u32,
-    start_line: usize,
-    start_offset: u32,
+    (labels, errors)
 }
 
 #[derive(Debug)]
 struct Collector {
     labels: Labels,
+    definitions: HashMap<String, span::Span>,
     current_equ: Option<(String, Vec<String>)>,
-    pending_labels: HashSet<String>,
-    for_stack: Vec<ForStatement>,
-    for_offsets: HashMap<String, u32>,
+    pending_labels: HashMap<String, span::Span>,
+    errors: Vec<Spanned<Error>>,
+    warnings: Vec<Spanned<Warning>>,
 }
 
 impl Collector {
-    fn new() -> Self {
+    fn new(constants: &LabelMap) -> Self {
+        let (labels, errors) = predefined_labels(constants);
+
         Self {
-            labels: default_labels(),
+            labels,
+            definitions: HashMap::new(),
             current_equ: None,
-            pending_labels: HashSet::new(),
-            for_stack: Vec::new(),
-            for_offsets: HashMap::new(),
+            pending_labels: HashMap::new(),
+            errors,
+            warnings: Vec::new(),
         }
     }
 
-    fn process_equ(&mut self, label: &str, substitution: &str) {
+    fn process_equ(&mut self, label: &str, substitution: &str, span: span::Span) {
         if substitution.is_empty() {
-            // TODO #25 warning empty RHS of EQU (see docs/pmars-redcode-94.txt:170)
+            // See docs/pmars-redcode-94.txt:170
+            self.warnings.push(Spanned::new(
+                Warning::EmptySubstitution(label.to_owned()),
+                span,
+            ));
         }
 
         if self.current_equ.is_some() {
             self.resolve_pending_equ();
         }
 
+        self.define(label, span);
         self.current_equ = Some((label.to_owned(), vec![substitution.to_owned()]));
     }
 
-    fn process_equ_continuation(&mut self, substitution: &str) {
+    /// Record where a label is defined, reporting an error if it already was.
+    fn define(&mut self, label: &str, span: span::Span) {
+        if let Some(previous) = self.definitions.insert(label.to_owned(), span) {
+            self.duplicate(label, previous, span);
+        }
+    }
+
+    /// Report a label defined at both `previous` and `span`. A line is
+    /// processed again after each substitution in it, and a `FOR` block
+    /// repeats its lines, so a label from the same line is not a duplicate.
+    fn duplicate(&mut self, label: &str, previous: span::Span, span: span::Span) {
+        if previous.line == span.line {
+            return;
+        }
+
+        let error = Error::DuplicateLabel {
+            label: label.to_owned(),
+            line: previous.line + 1,
+        };
+        self.errors.push(Spanned::new(error, span));
+    }
+
+    /// Add a line to the current multi-line `EQU`. Returns false if there is
+    /// no `EQU` to continue.
+    fn process_equ_continuation(&mut self, substitution: &str) -> bool {
         if let Some((_, ref mut values)) = self.current_equ {
             values.push(substitution.to_string());
+            true
         } else {
-            // TODO #25 real error
-            eprintln!("Error: first occurrence of EQU without label");
+            false
         }
     }
 
-    fn add_pending_label(&mut self, label: &str) {
-        self.pending_labels.insert(label.to_owned());
+    fn add_pending_label(&mut self, label: &str, span: span::Span) {
+        if let Some(previous) = self.pending_labels.insert(label.to_owned(), span) {
+            self.duplicate(label, previous, span);
+        }
     }
 
     fn resolve_pending_labels(&mut self, offset: u32) {
         let mut result = HashMap::new();
 
         let pending_labels = std::mem::take(&mut self.pending_labels);
-        for pending_label in pending_labels {
+        for (pending_label, span) in pending_labels {
+            self.define(&pending_label, span);
             result.insert(pending_label, LabelValue::AbsoluteOffset(offset));
         }
 
         self.resolve_pending_equ();
 
-        self.labels.extend(result.into_iter());
+        self.labels.extend(result);
     }
 
     fn resolve_pending_equ(&mut self) {
@@ -431,59 +734,54 @@ impl Collector {
         }
     }
 
-    fn push_for<T: Into<Option<String>>>(
-        &mut self,
-        label: T,
-        line: usize,
-        offset: u32,
-        expression: &str,
-    ) {
-        // TODO handle errors instead of unwrap
-        let expr_value = evaluation::evaluate_expression(expression).unwrap();
-
-        self.for_stack.push(ForStatement {
-            index_label: label.into(),
-            iter_count: expr_value,
-            start_line: line,
-            start_offset: offset,
-        });
-    }
-
-    fn pop_for(&mut self) -> ForStatement {
-        let for_stmt = self.for_stack.pop().unwrap();
-        if let Some(label) = &for_stmt.index_label {
-            self.for_offsets
-                .insert(label.clone(), for_stmt.start_offset);
-        }
-        for_stmt
-    }
-
     fn get_label_value(&self, label: &str, current_offset: u32) -> Option<LabelValue> {
         let value = self.labels.get(label).cloned();
 
-        if let Some(LabelValue::Substitution(_)) = value {
-            // Always return substitutions even if we are in a for loop
+        if value.is_none() && label == "CURLINE" {
+            // Special-case for current line number
+            let current_offset = i32::try_from(current_offset).expect("CURLINE > i32::MAX");
+            Some(LabelValue::RelativeOffset(current_offset))
+        } else {
             value
-        } else if self.for_stack.is_empty() {
-            // Otherwise, only expand labels if we are finished unrolling loops
-            // This ensures the relative offsets are calculated after the unroll
-            if value.is_some() {
-                value
-            } else if label == "CURLINE" {
-                // Special-case for current line number
-                let current_offset = i32::try_from(current_offset).expect("CURLINE > i32::MAX");
-                Some(LabelValue::RelativeOffset(current_offset))
-            } else {
-                None
+        }
+    }
+
+    /// Check that an `EQU` label isn't defined in terms of itself, which would
+    /// be expanded forever. Returns the text to substitute for the label,
+    /// which is `0` if there is a cycle, so the rest of the line can still
+    /// be checked.
+    fn check_substitution(
+        &mut self,
+        label: &str,
+        substitution: Vec<String>,
+        span: span::Span,
+    ) -> Vec<String> {
+        match find_cycle(&self.labels, &mut vec![label.to_owned()]) {
+            Some(cycle) => {
+                self.errors
+                    .push(Spanned::new(Error::CircularSubstitution { cycle }, span));
+                vec![String::from("0")]
             }
-        } else {
-            None
+            None => substitution,
         }
     }
 
-    fn finish(mut self) -> Labels {
-        if !self.pending_labels.is_empty() {
-            // TODO #25 warning for empty definition for each pending label
+    /// The expansion info for a substitution, used to trace the origin of
+    /// expanded lines.
+    fn expansion(&self, label: &str) -> Option<Expansion> {
+        self.definitions.get(label).map(|&span| Expansion {
+            label: label.to_owned(),
+            span,
+        })
+    }
+
+    fn finish(mut self) -> (Labels, Vec<Spanned<Error>>, Vec<Spanned<Warning>>) {
+        let mut pending_labels: Vec<_> = self.pending_labels.drain().collect();
+        pending_labels.sort_by_key(|(_, span)| (span.line, span.start));
+
+        for (label, span) in pending_labels {
+            self.warnings
+                .push(Spanned::new(Warning::EmptyOffset(label), span));
         }
 
         self.labels.extend(
@@ -492,7 +790,7 @@ impl Collector {
                 .map(|(label, values)| (label, LabelValue::Substitution(values))),
         );
 
-        self.labels
+        (self.labels, self.errors, self.warnings)
     }
 }
 
@@ -502,14 +800,15 @@ mod test {
     use test_case::test_case;
 
     use super::*;
+    use crate::phase::{lines_from, texts};
     use LabelValue::{AbsoluteOffset, Substitution};
 
     #[test]
     fn collects_equ() {
-        let mut collector = Collector::new();
+        let mut collector = Collector::new(&DEFAULT_CONSTANTS);
 
-        collector.process_equ("foo", "1");
-        let labels = collector.finish();
+        collector.process_equ("foo", "1", span::Span::default());
+        let (labels, _, _) = collector.finish();
 
         assert_eq!(
             Some(&Substitution(vec![String::from("1")])),
@@ -519,11 +818,11 @@ mod test {
 
     #[test]
     fn collects_multi_line_equ() {
-        let mut collector = Collector::new();
+        let mut collector = Collector::new(&DEFAULT_CONSTANTS);
 
-        collector.process_equ("foo", "mov 1, 1");
+        collector.process_equ("foo", "mov 1, 1", span::Span::default());
         collector.process_equ_continuation("jne 0, -1");
-        let labels = collector.finish();
+        let (labels, _, _) = collector.finish();
 
         assert_eq!(
             Some(&Substitution(vec![
@@ -536,19 +835,240 @@ mod test {
 
     #[test]
     fn collects_label_offset() {
-        let mut collector = Collector::new();
+        let mut collector = Collector::new(&DEFAULT_CONSTANTS);
 
-        collector.add_pending_label("foo");
-        collector.add_pending_label("bar");
+        collector.add_pending_label("foo", span::Span::new(0, 0, 3));
+        collector.add_pending_label("bar", span::Span::new(1, 0, 3));
         collector.resolve_pending_labels(1);
 
-        collector.add_pending_label("zip");
-        collector.add_pending_label("zap");
-        collector.add_pending_label("gone");
-        let labels = collector.finish();
+        collector.add_pending_label("zip", span::Span::new(2, 0, 3));
+        collector.add_pending_label("zap", span::Span::new(3, 0, 3));
+        let (labels, _, warnings) = collector.finish();
 
         assert_eq!(Some(&AbsoluteOffset(1)), labels.get("foo"),);
         assert_eq!(Some(&AbsoluteOffset(1)), labels.get("bar"),);
+        assert_eq!(
+            warnings,
+            vec![
+                Spanned::new(Warning::EmptyOffset("zip".into()), span::Span::new(2, 0, 3)),
+                Spanned::new(Warning::EmptyOffset("zap".into()), span::Span::new(3, 0, 3)),
+            ]
+        );
+    }
+
+    #[test]
+    fn declares_labels() {
+        let mut collector = Collector::new(&DEFAULT_CONSTANTS);
+
+        collector.process_equ("step", "CORESIZE / 4", span::Span::default());
+        collector.add_pending_label("start", span::Span::default());
+        collector.resolve_pending_labels(2);
+        collector.process_equ("code", "mov 0, 1", span::Span::default());
+        let (labels, _, _) = collector.finish();
+
+        assert_eq!(
+            declared_labels(&labels),
+            BTreeMap::from([
+                (
+                    "code".into(),
+                    LabelDefinition::Substitution {
+                        text: "mov 0, 1".into(),
+                        value: None
+                    }
+                ),
+                ("start".into(), LabelDefinition::Offset(2)),
+                (
+                    "step".into(),
+                    LabelDefinition::Substitution {
+                        text: "CORESIZE / 4".into(),
+                        value: Some(2000)
+                    }
+                ),
+            ])
+        );
+    }
+
+    #[test]
+    fn warns_empty_equ() {
+        let mut collector = Collector::new(&DEFAULT_CONSTANTS);
+
+        collector.process_equ("foo", "", span::Span::new(4, 0, 3));
+        let (_, _, warnings) = collector.finish();
+
+        assert_eq!(
+            warnings,
+            vec![Spanned::new(
+                Warning::EmptySubstitution("foo".into()),
+                span::Span::new(4, 0, 3)
+            )]
+        );
+    }
+
+    #[test]
+    fn reports_invalid_expansions() {
+        let mut lines = lines_from(&["equ 4", "123", "for foo", "dat 0", "rof", "mov 0, 1"]);
+        let (_, errors, _) = collect_and_expand("", &mut lines, &DEFAULT_CONSTANTS);
+
+        assert_eq!(
+            errors,
+            vec![
+                Spanned::new(Error::EquWithoutLabel, span::Span::new(0, 0, 5)),
+                Spanned::new(
+                    Error::LabelNotFound {
+                        label: "foo".into(),
+                        line: None,
+                    },
+                    span::Span::new(2, 4, 7)
+                ),
+            ]
+        );
+        assert_eq!(texts(&lines), vec!["123", "mov 0, 1"]);
+    }
+
+    #[test]
+    fn reports_unmatched_for() {
+        let mut lines = lines_from(&["rof", "for 2", "mov 0, 1"]);
+        let (_, errors, _) = collect_and_expand("", &mut lines, &DEFAULT_CONSTANTS);
+
+        assert_eq!(
+            errors,
+            vec![
+                Spanned::new(Error::UnexpectedRof, span::Span::new(0, 0, 3)),
+                Spanned::new(Error::UnterminatedFor, span::Span::new(1, 0, 5)),
+            ]
+        );
+        assert_eq!(texts(&lines), vec!["mov 0, 1"]);
+    }
+
+    #[test]
+    fn rejects_for_count_above_max_length() {
+        let mut lines = lines_from(&["for 100000000", "mov 0, 1", "rof", "for MAXLENGTH", "rof"]);
+        let (_, errors, _) = collect_and_expand("", &mut lines, &DEFAULT_CONSTANTS);
+
+        assert_eq!(
+            errors,
+            vec![Spanned::new(
+                Error::ForCountTooLarge {
+                    count: 100_000_000,
+                    max_length: 100
+                },
+                span::Span::new(0, 4, 13)
+            )]
+        );
+        assert_eq!(texts(&lines), Vec::<&str>::new());
+    }
+
+    #[test]
+    fn reports_duplicate_labels() {
+        let mut lines = lines_from(&[
+            "x mov 0, 1",
+            "x equ 2",
+            "y",
+            "y dat 0",
+            "i for 2",
+            "z dat i",
+            "rof",
+        ]);
+        let (_, errors, _) = collect_and_expand("", &mut lines, &DEFAULT_CONSTANTS);
+
+        let duplicate = |label: &str, line| Error::DuplicateLabel {
+            label: label.into(),
+            line,
+        };
+
+        assert_eq!(
+            errors,
+            vec![
+                Spanned::new(duplicate("x", 1), span::Span::new(1, 0, 1)),
+                Spanned::new(duplicate("y", 3), span::Span::new(3, 0, 1)),
+            ]
+        );
+    }
+
+    #[test_case(&["a equ a+1", "dat a"], &["a", "a"]; "direct")]
+    #[test_case(&["a equ b", "b equ c+1", "c equ b", "dat a"], &["b", "c", "b"]; "indirect")]
+    fn reports_circular_equ(lines: &[&str], cycle: &[&str]) {
+        let mut lines = lines_from(lines);
+        let (_, errors, _) = collect_and_expand("", &mut lines, &DEFAULT_CONSTANTS);
+
+        let cycle = cycle.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            errors
+                .into_iter()
+                .map(Spanned::into_inner)
+                .collect::<Vec<_>>(),
+            vec![Error::CircularSubstitution { cycle }]
+        );
+        assert_eq!(texts(&lines), vec!["dat 0"]);
+    }
+
+    #[test_case(Some("x"), None, span::Span::new(1, 4, 5); "pin")]
+    #[test_case(None, Some("x == 1"), span::Span::new(2, 8, 9); "assertion")]
+    fn reports_circular_equ_in_expression(
+        pin: Option<&str>,
+        assertion: Option<&str>,
+        expected_span: span::Span,
+    ) {
+        let source = "x equ x+1\npin x\n;assert x == 1\nmov 0, 1";
+        let lines = vec![
+            Line::new("x equ x+1", span::Span::new(0, 0, 9)),
+            Line::new("mov 0, 1", span::Span::new(3, 0, 8)),
+        ];
+        let pin = pin.map(|text| Line::new(text, span::Span::new(1, 4, 5)));
+        let assertions = assertion
+            .map(|text| Line::new(text, span::Span::new(2, 8, 14)))
+            .into_iter()
+            .collect();
+
+        let result = expand(source, lines, None, pin, assertions, &DEFAULT_CONSTANTS);
+
+        assert_eq!(
+            result.errors,
+            vec![Spanned::new(
+                Error::CircularSubstitution {
+                    cycle: vec!["x".into(), "x".into()]
+                },
+                expected_span
+            )]
+        );
+        assert_eq!(result.pin, None);
+        assert_eq!(result.assertions, Vec::new());
+    }
+
+    #[test_case("x&i", "x01"; "suffix")]
+    #[test_case("x&i&j y&i", "x01&j y01"; "multiple")]
+    #[test_case("x&ij, i", "x&ij, i"; "longer label")]
+    fn concatenates_counter(text: &str, expected: &str) {
+        assert_eq!(concatenate(text, "i", 1), expected);
+    }
+
+    #[test]
+    fn tracks_expansion_origin() {
+        let mut lines = lines_from(&["step equ mov 1, 2", "equ nop 0", "lbl step"]);
+        collect_and_expand("", &mut lines, &DEFAULT_CONSTANTS);
+
+        let expansion = Some(Expansion {
+            label: "step".into(),
+            span: span::Span::new(0, 0, 4),
+        });
+
+        assert_eq!(
+            lines,
+            vec![
+                Line {
+                    text: "mov 1, 2".into(),
+                    span: span::Span::new(2, 0, 8),
+                    expansion: expansion.clone(),
+                    counters: Vec::new(),
+                },
+                Line {
+                    text: "nop 0".into(),
+                    span: span::Span::new(2, 0, 8),
+                    expansion,
+                    counters: Vec::new(),
+                },
+            ]
+        );
     }
 
     #[test_case("step", 0, 4, &["a"], &["a"]; "single line")]
@@ -608,11 +1128,11 @@ mod test {
             .map(ToString::to_string)
             .collect::<Vec<String>>();
 
-        let mut lines = vec![line.to_string()];
+        let mut lines = lines_from(&[line]);
 
-        expand_lines(&mut lines, 0, &span, &substitution);
+        expand_lines(&mut lines, 0, &span, &substitution, None);
 
-        assert_eq!(lines, expected);
+        assert_eq!(texts(&lines), expected);
     }
 
     #[test_case(
@@ -678,8 +1198,8 @@ mod test {
         "label with expansion"
     )]
     fn collects_and_expands_labels(lines: &[&str], expected: &Labels) {
-        let mut lines = lines.iter().map(ToString::to_string).collect();
-        let result = collect_and_expand(&mut lines);
+        let mut lines = lines_from(lines);
+        let (result, _, _) = collect_and_expand("", &mut lines, &DEFAULT_CONSTANTS);
 
         for (k, v) in expected {
             assert_eq!(Some(v), result.get(k));
@@ -837,13 +1357,51 @@ mod test {
         ];
         "expand expr labels"
     )]
+    #[test_case(
+        &[
+            "for 0",
+            "this block is a comment",
+            "rof",
+            "mov 0, 1",
+        ],
+        &[
+            "mov 0, 1",
+        ];
+        "comment block"
+    )]
+    #[test_case(
+        &[
+            "i for 2",
+            "j for i",
+            "dat i, j",
+            "rof",
+            "rof",
+        ],
+        &[
+            "dat 1, 1",
+            "dat 2, 1",
+            "dat 2, 2",
+        ];
+        "nested counters"
+    )]
+    #[test_case(
+        &[
+            "value equ N * 10",
+            "N for 2",
+            "dat value",
+            "rof",
+        ],
+        &[
+            "dat 1 * 10",
+            "dat 2 * 10",
+        ];
+        "counter in equ"
+    )]
     fn collects_and_expands_forrof(lines: &[&str], expected: &[&str]) {
-        let mut lines = lines.iter().map(ToString::to_string).collect();
-        collect_and_expand(&mut lines);
+        let mut lines = lines_from(lines);
+        collect_and_expand("", &mut lines, &DEFAULT_CONSTANTS);
 
-        let expected_lines: Vec<String> = expected.iter().map(ToString::to_string).collect();
-
-        assert_eq!(lines, expected_lines);
+        assert_eq!(texts(&lines), expected);
     }
 
     #[test_case(
@@ -939,17 +1497,75 @@ mod test {
         ];
         "expand default labels"
     )]
+    #[test_case(
+        &[
+            "dat PSPACESIZE, VERSION",
+            "dat WARRIORS, READLIMIT + WRITELIMIT",
+            "for WARRIORS > 1",
+            "dat 0, 0",
+            "rof",
+        ],
+        &[
+            "dat 500, 94",
+            "dat 1, 8000 + 8000",
+        ];
+        "expand pmars labels"
+    )]
+    #[test_case(
+        &[
+            "N for 2",
+            "x&N dat N",
+            "rof",
+            "jmp x01, x02",
+        ],
+        &[
+            "dat 1",
+            "dat 2",
+            "jmp -2, -1",
+        ];
+        "concatenate counter"
+    )]
     fn expands_substitutions(lines: &[&str], expected: &[&str]) {
-        let lines = lines.iter().map(ToString::to_string).collect();
-        let expected: Vec<String> = expected.iter().map(ToString::to_string).collect();
+        let result = expand(
+            "",
+            lines_from(lines),
+            None,
+            None,
+            Vec::new(),
+            &DEFAULT_CONSTANTS,
+        );
+
+        assert_eq!(texts(&result.lines), expected);
+        assert_eq!(result.origin, None);
+    }
+
+    #[test]
+    fn expands_overridden_constants() {
+        let mut constants = DEFAULT_CONSTANTS.clone();
+        constants.insert("CORESIZE".into(), 55440);
+
+        let lines = lines_from(&["mov 1, CORESIZE-1", "dat MAXLENGTH, MAXLENGTH"]);
+        let result = expand("", lines, None, None, Vec::new(), &constants);
+
+        assert_eq!(texts(&result.lines), vec!["mov 1, 55440-1", "dat 100, 100"]);
+    }
+
+    #[test]
+    fn rejects_invalid_constants() {
+        let mut constants = DEFAULT_CONSTANTS.clone();
+        constants.insert("CORESIZE".into(), 3_000_000_000);
+
+        let mut lines = lines_from(&["mov 1, CORESIZE-1"]);
+        let (labels, errors, _) = collect_and_expand("", &mut lines, &constants);
 
         assert_eq!(
-            Lines {
-                text: expected,
-                origin: None,
-            },
-            expand(lines, None),
+            errors,
+            vec![Spanned::from(Error::InvalidConstant {
+                name: "CORESIZE".into(),
+                value: 3_000_000_000,
+            })]
         );
+        assert_eq!(labels.get("CORESIZE"), None);
     }
 
     #[test_case(
@@ -1018,15 +1634,29 @@ mod test {
         origin: Option<String>,
         expected_origin: Option<String>,
     ) {
-        let lines = lines.iter().map(ToString::to_string).collect();
-        let expected: Vec<String> = expected_lines.iter().map(ToString::to_string).collect();
-
-        assert_eq!(
-            expand(lines, origin),
-            Lines {
-                text: expected,
-                origin: expected_origin,
-            }
+        let origin = origin.map(|text| Line::new(text, span::Span::default()));
+        let result = expand(
+            "",
+            lines_from(lines),
+            origin,
+            None,
+            Vec::new(),
+            &DEFAULT_CONSTANTS,
         );
+
+        assert_eq!(texts(&result.lines), expected_lines);
+        assert_eq!(result.origin.map(|line| line.text), expected_origin);
+    }
+
+    #[test_case("CORESIZE == 8000", "8000 == 8000"; "constant")]
+    #[test_case("(step + 1) % 4", "(2 * 3 + 1) % 4"; "substitution")]
+    #[test_case("bomb - start < MAXLENGTH", "2 - 1 < 100"; "offsets")]
+    #[test_case("foo == 1", "foo == 1"; "unknown label")]
+    fn expands_assertion(assertion: &str, expected: &str) {
+        let lines = lines_from(&["step equ 2 * 3", "jmp 0", "start mov 0, 1", "bomb dat 0"]);
+        let assertions = vec![Line::new(assertion, span::Span::default())];
+        let result = expand("", lines, None, None, assertions, &DEFAULT_CONSTANTS);
+
+        assert_eq!(texts(&result.assertions), vec![expected]);
     }
 }
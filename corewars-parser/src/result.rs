@@ -1,21 +1,24 @@
 //! Error handling for the corewars parser.
 //! [`Result`](Result) matches the `std::result::Result` type, except that it
-//! may also contain warnings alongside either an `Ok` or `Err` type.
+//! may also contain warnings alongside either an `Ok` or `Err` type, and an
+//! `Err` contains every error found in the input rather than only the first.
 
 use super::error::{Error, Warning};
+use super::span::Spanned;
 
 use std::result::Result as StdResult;
 
 /// `Result` mimics the `std::result::Result` type, but each variant also carries
-/// zero or more [`Warning`](Warning)s with it.
+/// zero or more [`Warning`](Warning)s with it. Errors and warnings are annotated
+/// with their location in the source, if it is known, and are ordered by it.
 #[must_use = "this `Result` may be an `Err` variant, which should be handled"]
 #[derive(Debug, PartialEq, Eq)]
 pub enum Result<T> {
     /// Contains the success value and zero or more warnings
-    Ok(T, Vec<Warning>),
+    Ok(T, Vec<Spanned<Warning>>),
 
-    /// Contains the error value and zero or more warnings
-    Err(Error, Vec<Warning>),
+    /// Contains one or more errors and zero or more warnings
+    Err(Vec<Spanned<Error>>, Vec<Spanned<Warning>>),
 }
 
 impl<T> Result<T> {
@@ -25,15 +28,34 @@ impl<T> Result<T> {
     }
 
     /// Create an `Err` variant from an error.
-    pub fn err(err: Error) -> Self {
-        Self::Err(err, Vec::new())
+    pub fn err<E: Into<Spanned<Error>>>(err: E) -> Self {
+        Self::Err(vec![err.into()], Vec::new())
+    }
+
+    /// The errors generated while parsing, which is empty for an `Ok`.
+    #[must_use]
+    pub fn errors(&self) -> &[Spanned<Error>] {
+        match self {
+            Self::Ok(..) => &[],
+            Self::Err(errors, _) => errors,
+        }
+    }
+
+    /// The warnings generated while parsing, regardless of success.
+    #[must_use]
+    pub fn warnings(&self) -> &[Spanned<Warning>] {
+        match self {
+            Self::Ok(_, warnings) | Self::Err(_, warnings) => warnings,
+        }
     }
 
     /// Unwrap the parse result, panicking if it was not an `Ok`.
     pub fn unwrap(self) -> T {
         match self {
             Self::Ok(value, _) => value,
-            Self::Err(err, _) => panic!("called `Result::unwrap()` on an `Err` value: {:?}", &err),
+            Self::Err(errors, _) => {
+                panic!("called `Result::unwrap()` on an `Err` value: {:?}", &errors)
+            }
         }
     }
 
@@ -41,22 +63,22 @@ impl<T> Result<T> {
     pub fn expect(self, msg: &str) -> T {
         match self {
             Self::Ok(value, _) => value,
-            Self::Err(err, _) => panic!("{}: {:?}", msg, &err),
+            Self::Err(errors, _) => panic!("{}: {:?}", msg, &errors),
         }
     }
 }
 
-impl<T> From<StdResult<T, Error>> for Result<T> {
-    fn from(result: StdResult<T, Error>) -> Self {
+impl<T> From<StdResult<T, Spanned<Error>>> for Result<T> {
+    fn from(result: StdResult<T, Spanned<Error>>) -> Self {
         match result {
             Ok(value) => Self::Ok(value, Vec::new()),
-            Err(err) => Self::Err(err, Vec::new()),
+            Err(err) => Self::Err(vec![err], Vec::new()),
         }
     }
 }
 
 impl<T> From<Error> for Result<T> {
     fn from(err: Error) -> Self {
-        Self::Err(err, Vec::new())
+        Self::Err(vec![err.into()], Vec::new())
     }
 }
@@ -0,0 +1,113 @@
+//! Settings which describe the environment a warrior is assembled for.
+
+use std::convert::TryFrom;
+
+use corewars_core::load_file::{derived_constants, LabelMap, Standard, DEFAULT_CONSTANTS};
+
+use crate::error::Error;
+
+/// The environment to assemble a warrior for. By default, this matches the
+/// standard ICWS '94 environment used by most hills.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Settings {
+    /// Predefined constants which have been given a non-default value.
+    overrides: LabelMap,
+    /// The Redcode standard to assemble for, regardless of `;redcode-XX` comments.
+    standard: Option<Standard>,
+}
+
+impl Settings {
+    /// Override the value of a predefined constant, or define a new one.
+    /// Fails if the constant can't be given `value`, e.g. a `CORESIZE` of 0.
+    pub fn with_constant<S: Into<String>>(mut self, name: S, value: u32) -> Result<Self, Error> {
+        let name = name.into();
+        check_constant(&name, value)?;
+
+        self.overrides.insert(name, value);
+        Ok(self)
+    }
+
+    /// Assemble for the given Redcode standard, ignoring any `;redcode-XX`
+    /// comment in the warrior.
+    #[must_use]
+    pub fn with_standard(mut self, standard: Standard) -> Self {
+        self.standard = Some(standard);
+        self
+    }
+
+    /// The Redcode standard to assemble for, if one was forced.
+    #[must_use]
+    pub fn standard(&self) -> Option<Standard> {
+        self.standard
+    }
+
+    /// The value of a predefined constant such as `CORESIZE`, if it is defined.
+    #[must_use]
+    pub fn constant(&self, name: &str) -> Option<u32> {
+        self.constants().get(name).copied()
+    }
+
+    /// The values of all predefined constants, which may be used as labels
+    /// in a warrior. Constants like `PSPACESIZE` follow `CORESIZE` unless they
+    /// have been overridden themselves.
+    #[must_use]
+    pub fn constants(&self) -> LabelMap {
+        let mut constants = DEFAULT_CONSTANTS.clone();
+
+        if let Some(&core_size) = self.overrides.get("CORESIZE") {
+            constants.extend(derived_constants(core_size));
+        }
+
+        constants.extend(self.overrides.clone());
+        constants
+    }
+}
+
+/// Check that a predefined constant can be given `value`. Constants are used
+/// like any other label, so they must fit in a field value, and a core must
+/// have at least one instruction.
+pub(crate) fn check_constant(name: &str, value: u32) -> Result<(), Error> {
+    if i32::try_from(value).is_err() || (name == "CORESIZE" && value == 0) {
+        return Err(Error::InvalidConstant {
+            name: name.to_owned(),
+            value,
+        });
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn derives_from_core_size() {
+        let settings = Settings::default()
+            .with_constant("CORESIZE", 55440)
+            .and_then(|settings| settings.with_constant("WRITELIMIT", 100))
+            .unwrap();
+
+        assert_eq!(settings.constant("CORESIZE"), Some(55440));
+        assert_eq!(settings.constant("PSPACESIZE"), Some(3465));
+        assert_eq!(settings.constant("READLIMIT"), Some(55440));
+        assert_eq!(settings.constant("WRITELIMIT"), Some(100));
+        assert_eq!(settings.constant("MAXPROCESSES"), Some(8000));
+    }
+
+    #[test]
+    fn checks_constants() {
+        let with_constant = |name, value| Settings::default().with_constant(name, value);
+
+        assert!(with_constant("CORESIZE", 1).is_ok());
+        assert!(with_constant("MAXLENGTH", 0).is_ok());
+        assert_eq!(
+            with_constant("CORESIZE", 0),
+            Err(Error::InvalidConstant {
+                name: "CORESIZE".into(),
+                value: 0
+            })
+        );
+        assert!(with_constant("MAXCYCLES", 3_000_000_000).is_err());
+    }
+}
@@ -0,0 +1,242 @@
+//! The code of a single line as it was written, before any labels are
+//! resolved or macros expanded. Each [`SyntaxLine`](crate::SyntaxLine) of
+//! the syntax tree has one, so that it can be printed again with normalized
+//! formatting.
+
+use std::fmt;
+use std::str::FromStr;
+
+use corewars_core::load_file::{AddressMode, Modifier, Opcode, PseudoOpcode};
+
+use crate::grammar::{Pair, Pairs, Rule};
+
+/// A single line of code, with any labels it declares.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SourceLine {
+    pub labels: Vec<String>,
+    pub statement: Option<Statement>,
+}
+
+impl SourceLine {
+    /// Whether this line is an `END`, after which any input is ignored.
+    #[must_use]
+    pub fn is_end(&self) -> bool {
+        matches!(
+            self.statement,
+            Some(Statement::Pseudo {
+                opcode: PseudoOpcode::End,
+                ..
+            })
+        )
+    }
+}
+
+impl fmt::Display for SourceLine {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        let mut lines: Vec<String> = Vec::new();
+
+        // All but the last label are declared on lines of their own
+        let (last_label, other_labels) = match self.labels.split_last() {
+            Some((last, others)) => (last.as_str(), others),
+            None => ("", &[][..]),
+        };
+        lines.extend(other_labels.iter().cloned());
+
+        match &self.statement {
+            // Example output:
+            // start   MOV.AB  #step,  target+1
+            // |----->||----->|
+            Some(statement) => lines.push(format!("{last_label:<7} {statement}")),
+            None => lines.push(last_label.to_owned()),
+        }
+
+        write!(formatter, "{}", lines.join("\n"))
+    }
+}
+
+/// The statement in a line of code, with every expression kept as written.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Statement {
+    Instruction {
+        opcode: Opcode,
+        modifier: Option<Modifier>,
+        a_field: Operand,
+        b_field: Option<Operand>,
+    },
+    Pseudo {
+        opcode: PseudoOpcode,
+        argument: String,
+    },
+}
+
+impl fmt::Display for Statement {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Instruction {
+                opcode,
+                modifier,
+                a_field,
+                b_field,
+            } => {
+                let operation = match modifier {
+                    Some(modifier) => format!("{opcode}.{modifier}"),
+                    None => opcode.to_string(),
+                };
+
+                match b_field {
+                    Some(b_field) => {
+                        let a_field = format!("{a_field},");
+                        write!(formatter, "{operation:<8}{a_field:<7} {b_field}")
+                    }
+                    None => write!(formatter, "{operation:<8}{a_field}"),
+                }
+            }
+            Self::Pseudo { opcode, argument } => {
+                let line = format!("{opcode:<8}{argument}");
+                write!(formatter, "{}", line.trim_end())
+            }
+        }
+    }
+}
+
+/// A field of an instruction, with its expression kept as written.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Operand {
+    /// The address mode, if one was given explicitly
+    pub address_mode: Option<AddressMode>,
+    pub expression: String,
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(address_mode) = self.address_mode {
+            write!(formatter, "{address_mode}")?;
+        }
+        write!(formatter, "{}", self.expression)
+    }
+}
+
+/// Build a line from the grammar's parse of its code, after masking any
+/// concatenation. `text` is the code as it was written.
+pub fn from_pairs(text: &str, pairs: Pairs) -> SourceLine {
+    let mut source_line = SourceLine::default();
+    let mut pairs = pairs.peekable();
+
+    while let Some(pair) = pairs.next() {
+        let statement = match pair.as_rule() {
+            Rule::Label => {
+                source_line.labels.push(as_written(text, &pair).to_owned());
+                continue;
+            }
+            Rule::Instruction => parse_instruction(text, pair),
+            Rule::Substitution => Statement::Pseudo {
+                opcode: PseudoOpcode::Equ,
+                argument: as_written(text, &pair).trim().to_owned(),
+            },
+            Rule::For => Statement::Pseudo {
+                opcode: PseudoOpcode::For,
+                argument: pairs
+                    .next_if(|pair| pair.as_rule() == Rule::Expression)
+                    .map_or_else(String::new, |pair| {
+                        as_written(text, &pair).trim().to_owned()
+                    }),
+            },
+            Rule::Rof => Statement::Pseudo {
+                opcode: PseudoOpcode::Rof,
+                argument: String::new(),
+            },
+            _ => continue,
+        };
+        source_line.statement = Some(statement);
+    }
+
+    // A bare `END` has no field, so the grammar sees it as a label
+    if source_line.statement.is_none()
+        && source_line
+            .labels
+            .last()
+            .is_some_and(|label| label.eq_ignore_ascii_case("END"))
+    {
+        source_line.labels.pop();
+        source_line.statement = Some(Statement::Pseudo {
+            opcode: PseudoOpcode::End,
+            argument: String::new(),
+        });
+    }
+
+    source_line
+}
+
+/// Replace each `&` used for concatenation, e.g. `x&i`, with a character
+/// that is valid in a label. Boolean `&&` operators are left alone.
+pub fn mask_concatenation(text: &str) -> String {
+    let bytes = text.as_bytes();
+
+    text.char_indices()
+        .map(|(i, c)| {
+            let is_and = |j: Option<usize>| j.and_then(|j| bytes.get(j)) == Some(&b'&');
+            if c == '&' && !is_and(i.checked_sub(1)) && !is_and(Some(i + 1)) {
+                '_'
+            } else {
+                c
+            }
+        })
+        .collect()
+}
+
+/// The original text of a token parsed from the masked line.
+fn as_written<'a>(text: &'a str, pair: &Pair) -> &'a str {
+    &text[pair.as_span().start()..pair.as_span().end()]
+}
+
+fn parse_instruction(text: &str, instruction: Pair) -> Statement {
+    let instruction_end = instruction.as_span().end();
+
+    let mut inner = instruction.into_inner();
+    let mut operation = inner.next().unwrap().into_inner();
+    let opcode = operation.next().unwrap().as_str().to_uppercase();
+
+    let fields: Vec<Pair> = inner.collect();
+
+    if let Ok(pseudo_opcode) = PseudoOpcode::from_str(&opcode) {
+        let argument_start = fields[0].as_span().start();
+        return Statement::Pseudo {
+            opcode: pseudo_opcode,
+            argument: text[argument_start..instruction_end].trim().to_owned(),
+        };
+    }
+
+    let modifier = operation.next().map(|pair| {
+        Modifier::from_str(pair.as_str().to_uppercase().as_ref()).expect("Invalid Modifier")
+    });
+
+    Statement::Instruction {
+        opcode: Opcode::from_str(&opcode).expect("Invalid Opcode"),
+        modifier,
+        a_field: parse_operand(text, fields[0].clone()),
+        b_field: fields
+            .get(1)
+            .map(|field| parse_operand(text, field.clone())),
+    }
+}
+
+fn parse_operand(text: &str, field: Pair) -> Operand {
+    let mut address_mode = None;
+    let mut expression = String::new();
+
+    for pair in field.into_inner() {
+        match pair.as_rule() {
+            Rule::AddressMode => {
+                address_mode =
+                    Some(AddressMode::from_str(pair.as_str()).expect("Invalid AddressMode"));
+            }
+            Rule::Expression => as_written(text, &pair).trim().clone_into(&mut expression),
+            _ => unreachable!(),
+        }
+    }
+
+    Operand {
+        address_mode,
+        expression,
+    }
+}
@@ -0,0 +1,169 @@
+//! Types used to track where in the original source text a piece of parsed
+//! Redcode came from, so that errors and warnings can point back to it.
+
+use std::fmt;
+use std::ops::Deref;
+
+/// A region of a single line in the original input. All values are zero-based,
+/// and `start` / `end` are byte offsets within the line.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Span {
+    /// The index of the line in the input.
+    pub line: usize,
+    /// The byte offset where the span starts, relative to the start of the line.
+    pub start: usize,
+    /// The byte offset where the span ends (exclusive), relative to the start of the line.
+    pub end: usize,
+}
+
+impl Span {
+    /// Create a span covering the given range of a line.
+    #[must_use]
+    pub fn new(line: usize, start: usize, end: usize) -> Self {
+        Self { line, start, end }
+    }
+
+    /// The text covered by this span in `source`, if it is in range.
+    #[must_use]
+    pub fn text<'a>(&self, source: &'a str) -> Option<&'a str> {
+        source
+            .lines()
+            .nth(self.line)
+            .and_then(|line| line.get(self.start..self.end))
+    }
+
+    /// Narrow this span to the first occurrence of `needle` within it. If the
+    /// needle cannot be found (e.g. because it was produced by a substitution),
+    /// the original span is returned unchanged.
+    #[must_use]
+    pub fn narrow_to(self, source: &str, needle: &str) -> Self {
+        let needle = needle.trim();
+        if needle.is_empty() {
+            return self;
+        }
+
+        self.text(source)
+            .and_then(|text| text.find(needle))
+            .map_or(self, |found| Self {
+                line: self.line,
+                start: self.start + found,
+                end: self.start + found + needle.len(),
+            })
+    }
+}
+
+impl fmt::Display for Span {
+    /// Format as a one-based `line:column`, the way most editors expect.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.line + 1, self.start + 1)
+    }
+}
+
+/// The `EQU` substitution that produced a line of code, if any.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Expansion {
+    /// The name of the substituted label.
+    pub label: String,
+    /// Where the label was defined.
+    pub span: Span,
+}
+
+/// A value annotated with the location in the source that it relates to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Spanned<T> {
+    /// The annotated value.
+    pub value: T,
+    /// The location in the source, if known.
+    pub span: Option<Span>,
+    /// The macro expansion that produced the code at `span`, if any.
+    pub expansion: Option<Expansion>,
+}
+
+impl<T> Spanned<T> {
+    /// Annotate a value with a location.
+    pub fn new(value: T, span: Span) -> Self {
+        Self {
+            value,
+            span: Some(span),
+            expansion: None,
+        }
+    }
+
+    /// Record the macro expansion which produced this value.
+    #[must_use]
+    pub fn with_expansion(mut self, expansion: Option<Expansion>) -> Self {
+        self.expansion = expansion;
+        self
+    }
+
+    /// Discard the location information and return the inner value.
+    pub fn into_inner(self) -> T {
+        self.value
+    }
+}
+
+impl<T> From<T> for Spanned<T> {
+    fn from(value: T) -> Self {
+        Self {
+            value,
+            span: None,
+            expansion: None,
+        }
+    }
+}
+
+impl<T> Deref for Spanned<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
+
+impl<T: fmt::Display> fmt::Display for Spanned<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(span) = self.span {
+            write!(f, "{span}: ")?;
+        }
+        write!(f, "{}", self.value)
+    }
+}
+
+impl<T: std::error::Error> std::error::Error for Spanned<T> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.value.source()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn narrows_span() {
+        let source = "foo\n  mov bar, 1 ; comment\n";
+        let span = Span::new(1, 2, 12);
+
+        assert_eq!(span.narrow_to(source, "bar"), Span::new(1, 6, 9));
+        assert_eq!(span.narrow_to(source, "comment"), span);
+        assert_eq!(span.narrow_to(source, ""), span);
+    }
+
+    #[test]
+    fn slices_source_text() {
+        let source = "foo\n  mov bar, 1\n";
+
+        assert_eq!(Span::new(1, 6, 9).text(source), Some("bar"));
+        assert_eq!(Span::new(1, 6, 90).text(source), None);
+        assert_eq!(Span::new(5, 0, 1).text(source), None);
+    }
+
+    #[test]
+    fn displays_one_based() {
+        assert_eq!(Span::new(0, 0, 1).to_string(), "1:1");
+        assert_eq!(
+            Spanned::new("oops", Span::new(2, 4, 5)).to_string(),
+            "3:5: oops"
+        );
+    }
+}
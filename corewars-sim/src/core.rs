@@ -7,13 +7,15 @@ use std::ops::{Index, Range};
 
 use thiserror::Error as ThisError;
 
-use corewars_core::load_file::{self, Instruction, Offset};
-use corewars_core::Warrior;
+use corewars_core::load_file::{self, derived_constants, Instruction, Offset};
+use corewars_core::ResolvedWarrior;
 
 mod address;
+pub mod disassembly;
 mod modifier;
 mod opcode;
 mod process;
+mod pspace;
 
 const DEFAULT_MAXCYCLES: usize = 10_000;
 
@@ -25,10 +27,18 @@ pub enum Error {
     #[error("warrior has too many instructions to fit in the core")]
     WarriorTooLong,
 
-    /// The specified core size was larger than the allowed max
-    #[error("cannot create a core with size {0}; must be less than {}", u32::MAX)]
+    /// The specified core size was empty or larger than the allowed max
+    #[error("cannot create a core with size {0}; must be between 1 and {}", u32::MAX - 1)]
     InvalidCoreSize(u32),
 
+    /// The specified P-space size was empty
+    #[error("cannot create a P-space with size 0")]
+    InvalidPSpaceSize,
+
+    /// The specified max number of processes was 0
+    #[error("cannot limit warriors to 0 processes")]
+    InvalidMaxProcesses,
+
     #[error(transparent)]
     WarriorAlreadyLoaded(#[from] process::Error),
 }
@@ -37,28 +47,83 @@ pub enum Error {
 pub struct Core {
     instructions: Box<[Instruction]>,
     process_queue: process::Queue,
+    pspaces: pspace::Table,
     steps_taken: usize,
+    /// The max number of processes each warrior may have (`MAXPROCESSES`)
+    max_processes: u32,
+    /// The name of each warrior loaded into the core, in the order they were loaded
+    warriors: Vec<String>,
 }
 
 impl Core {
     /// Create a new Core with the given number of possible instructions.
     pub fn new(core_size: u32) -> Result<Self, Error> {
-        if core_size == u32::MAX {
+        if core_size == 0 || core_size == u32::MAX {
             return Err(Error::InvalidCoreSize(core_size));
         }
 
         Ok(Self {
             instructions: vec![Instruction::default(); core_size as usize].into_boxed_slice(),
             process_queue: process::Queue::new(),
+            pspaces: pspace::Table::new(derived_constants(core_size)["PSPACESIZE"]),
             steps_taken: 0,
+            max_processes: load_file::DEFAULT_CONSTANTS["MAXPROCESSES"],
+            warriors: Vec::new(),
         })
     }
 
+    /// Set the number of cells in each warrior's P-space (available to programs
+    /// via the `PSPACESIZE` label). This defaults to 1/16th of the core size,
+    /// and must be set before any warriors are loaded.
+    pub fn with_pspace_size(mut self, pspace_size: u32) -> Result<Self, Error> {
+        if pspace_size == 0 {
+            return Err(Error::InvalidPSpaceSize);
+        }
+
+        self.pspaces = pspace::Table::new(pspace_size);
+        Ok(self)
+    }
+
+    /// Set the max number of processes each warrior may have (available to
+    /// programs via the `MAXPROCESSES` label). A `SPL` by a warrior which
+    /// already has this many processes doesn't create a new one.
+    pub fn with_max_processes(mut self, max_processes: u32) -> Result<Self, Error> {
+        if max_processes == 0 {
+            return Err(Error::InvalidMaxProcesses);
+        }
+
+        self.max_processes = max_processes;
+        Ok(self)
+    }
+
     #[must_use]
     pub fn steps_taken(&self) -> usize {
         self.steps_taken
     }
 
+    /// The names of the warriors loaded into the core, in the order they were
+    /// loaded. Each name is unique, even if two warriors have the same `;name`.
+    #[must_use]
+    pub fn warriors(&self) -> &[String] {
+        &self.warriors
+    }
+
+    /// Whether the warrior with the given name has any processes left
+    #[must_use]
+    pub fn is_alive(&self, warrior_name: &str) -> bool {
+        self.process_queue.contains(warrior_name)
+            && self.process_queue.thread_count(warrior_name) > 0
+    }
+
+    /// The number of warriors which have any processes left
+    #[must_use]
+    pub fn warriors_alive(&self) -> usize {
+        self.warriors
+            .iter()
+            .filter(|name| self.is_alive(name))
+            .count()
+    }
+
     #[cfg(test)]
     fn program_counter(&self) -> Offset {
         self.process_queue
@@ -120,26 +185,40 @@ impl Core {
         self.instructions[index.value() as usize] = value;
     }
 
-    /// Load a [`Warrior`](Warrior) into the core starting at the front (first instruction of the core).
-    /// Returns an error if the Warrior was too long to fit in the core, or had unresolved labels
-    pub fn load_warrior(&mut self, warrior: &Warrior) -> Result<(), Error> {
+    /// Load a [`ResolvedWarrior`](ResolvedWarrior) into the core starting at the front (first instruction of the core).
+    /// Returns an error if the Warrior was too long to fit in the core
+    pub fn load_warrior(&mut self, warrior: &ResolvedWarrior) -> Result<(), Error> {
+        self.load_warrior_at(warrior, 0)
+    }
+
+    /// Load a [`ResolvedWarrior`](ResolvedWarrior) into the core starting at
+    /// `position`, wrapping around the end of the core. Any warriors already
+    /// loaded take turns with this one, to battle each other.
+    /// Returns an error if the Warrior was too long to fit in the core
+    pub fn load_warrior_at(
+        &mut self,
+        warrior: &ResolvedWarrior,
+        position: u32,
+    ) -> Result<(), Error> {
         if warrior.len() > self.len() {
             return Err(Error::WarriorTooLong);
         }
 
-        // TODO check that all instructions are fully resolved? Or require a type
-        // safe way of loading a resolved warrior perhaps
+        let start = self.offset(0) + position % self.len();
 
         for (i, instruction) in warrior.program.instructions.iter().enumerate() {
-            self.instructions[i] = self.normalize(instruction.clone());
+            let instruction = self.normalize(instruction.clone());
+            *self.get_offset_mut(start + i as u32) = instruction;
         }
 
-        // TODO: Maybe some kinda increasing counter for warrior names
-        let warrior_name = warrior
-            .metadata
-            .name
-            .clone()
-            .unwrap_or_else(|| String::from("Warrior0"));
+        // Warriors are told apart by name, so a warrior whose name is already
+        // taken is numbered by its load order instead
+        let index = self.warriors.len();
+        let warrior_name = match &warrior.metadata.name {
+            Some(name) if !self.warriors.contains(name) => name.clone(),
+            Some(name) => format!("{name} ({index})"),
+            None => format!("Warrior{index}"),
+        };
 
         let origin: i32 = warrior
             .program
@@ -148,8 +227,19 @@ impl Core {
             .try_into()
             .unwrap_or_else(|err| panic!("Warrior {:?} has invalid origin: {}", warrior_name, err));
 
+        self.pspaces
+            .allocate(&warrior_name, warrior.program.pin, self.len());
+
+        log::info!(
+            "Loaded warrior {:?} with {} instructions, starting at {}",
+            warrior_name,
+            warrior.len(),
+            origin
+        );
+
         self.process_queue
-            .push(warrior_name, self.offset(origin), None);
+            .push(warrior_name.clone(), start + origin, None);
+        self.warriors.push(warrior_name);
 
         Ok(())
     }
@@ -178,7 +268,7 @@ impl Core {
     pub fn step(&mut self) -> Result<(), process::Error> {
         let current_process = self.process_queue.pop()?;
 
-        eprintln!(
+        log::debug!(
             "Step{:>6} (t{:>2}): {:0>5} {}",
             self.steps_taken,
             current_process.thread,
@@ -187,12 +277,24 @@ impl Core {
         );
         self.steps_taken += 1;
 
-        let result = opcode::execute(self, current_process.offset);
+        let result = opcode::execute(self, &current_process.name, current_process.offset);
 
         match result {
             Err(err) => match err {
                 process::Error::DivideByZero | process::Error::ExecuteDat(_) => {
+                    log::debug!(
+                        "Thread {} of {:?} {}",
+                        current_process.thread,
+                        current_process.name,
+                        err
+                    );
+
                     if self.process_queue.thread_count(&current_process.name) < 1 {
+                        log::info!(
+                            "Warrior {:?} terminated after {} steps",
+                            current_process.name,
+                            self.steps_taken
+                        );
                         Err(err)
                     } else {
                         // This is fine, the task terminated but the process is still alive
@@ -210,6 +312,14 @@ impl Core {
                         current_process.offset + 1,
                         Some(current_process.thread),
                     );
+
+                    // The executing process isn't in the queue, so it is counted
+                    // separately. If the warrior can't have any more processes,
+                    // it just continues at PC+1
+                    let process_count = self.process_queue.thread_count(&current_process.name);
+                    if process_count >= self.max_processes as usize {
+                        return Ok(());
+                    }
                     None
                 } else {
                     Some(current_process.thread)
@@ -232,17 +342,49 @@ impl Core {
     }
 
     /// Run a core to completion. Return value determines whether the core resulted
-    /// in a tie (Ok) or something cause the warrior to stop executing ([`process::Error`])
+    /// in a tie (Ok) or something cause the warrior to stop executing ([`process::Error`]).
+    /// In a battle between several warriors, each executes up to `max_cycles`
+    /// instructions, and the battle ends (Ok) when only one warrior is left.
     pub fn run<T: Into<Option<usize>>>(&mut self, max_cycles: T) -> Result<(), process::Error> {
         let max_cycles = max_cycles.into().unwrap_or(DEFAULT_MAXCYCLES);
-
-        while self.steps_taken < max_cycles {
-            self.step()?;
+        // Each warrior executes one instruction per cycle
+        let max_steps = max_cycles * self.warriors.len().max(1);
+
+        while self.steps_taken < max_steps {
+            if let Err(err) = self.step() {
+                match self.warriors_alive() {
+                    0 => return Err(err),
+                    // The last warrior standing wins a battle
+                    1 if self.warriors.len() > 1 => return Ok(()),
+                    _ => {}
+                }
+            }
         }
 
         Ok(())
     }
 
+    /// Prepare the core for another round between the same warriors, which
+    /// should then be loaded again in the same order. The core and process
+    /// queue are cleared, but P-space is kept. Location 0 of each warrior's
+    /// P-space is set to the result of this round: 0 if the warrior was
+    /// killed, or the number of warriors still alive.
+    pub fn next_round(&mut self) {
+        let alive = self.warriors_alive();
+        let alive: i32 = alive.try_into().expect("more than i32::MAX warriors alive");
+
+        for name in &self.warriors {
+            let result = if self.is_alive(name) { alive } else { 0 };
+            let result = Offset::new(result, self.len());
+            self.pspaces.set_result(name, result);
+        }
+
+        self.instructions.fill(Instruction::default());
+        self.process_queue = process::Queue::new();
+        self.steps_taken = 0;
+        self.warriors.clear();
+    }
+
     // TODO: clean up this impl a bunch
     fn format_lines<F: Fn(usize, &Instruction) -> String, G: Fn(usize, &Instruction) -> String>(
         &self,
@@ -337,7 +479,10 @@ impl Index<Range<usize>> for Core {
 mod tests {
     use pretty_assertions::assert_eq;
 
+    use std::convert::TryFrom;
+
     use corewars_core::load_file::{Field, Opcode, Program};
+    use corewars_core::Warrior;
 
     use super::*;
 
@@ -356,6 +501,15 @@ mod tests {
         assert_eq!(core.len(), 128);
     }
 
+    #[test]
+    fn new_core_invalid_size() {
+        assert!(matches!(Core::new(0), Err(Error::InvalidCoreSize(0))));
+        assert!(matches!(
+            Core::new(u32::MAX),
+            Err(Error::InvalidCoreSize(u32::MAX))
+        ));
+    }
+
     #[test]
     fn load_program() {
         let mut core = Core::new(128).unwrap();
@@ -404,11 +558,12 @@ mod tests {
                     255
                 ],
                 origin: None,
+                pin: None,
             },
             ..Warrior::default()
         };
 
-        core.load_warrior(&warrior)
+        core.load_warrior(&ResolvedWarrior::try_from(warrior).unwrap())
             .expect_err("Should have failed to load warrior: too long");
 
         assert_eq!(core.len(), 128);
@@ -427,4 +582,77 @@ mod tests {
         core.step().unwrap();
         assert_eq!(core.program_counter().value(), 1);
     }
+
+    #[test]
+    fn battle_until_one_warrior_is_left() {
+        let imp = corewars_parser::parse(";name Imp\nmov 0, 1").unwrap();
+        let bomb = corewars_parser::parse(";name Bomb\ndat 0, 0").unwrap();
+
+        let mut core = Core::new(8000).unwrap();
+        core.load_warrior(&imp).unwrap();
+        core.load_warrior_at(&bomb, 4000).unwrap();
+        core.load_warrior_at(&imp, 2000).unwrap();
+
+        assert_eq!(core.warriors(), &["Imp", "Bomb", "Imp (2)"]);
+        assert_eq!(core.get(4000).opcode, Opcode::Dat);
+        assert_eq!(core.warriors_alive(), 3);
+
+        core.run(100).unwrap();
+
+        assert_eq!(core.steps_taken(), 300);
+        assert!(core.is_alive("Imp"));
+        assert!(!core.is_alive("Bomb"));
+        assert_eq!(core.warriors_alive(), 2);
+
+        let mut core = Core::new(8000).unwrap();
+        core.load_warrior(&imp).unwrap();
+        core.load_warrior_at(&bomb, 4000).unwrap();
+
+        core.run(100).unwrap();
+
+        assert_eq!(core.steps_taken(), 2);
+        assert_eq!(core.warriors_alive(), 1);
+    }
+
+    #[test]
+    fn limit_processes() {
+        let warrior = corewars_parser::parse("spl 0\njmp -1").unwrap();
+
+        let mut core = Core::new(8000).unwrap().with_max_processes(3).unwrap();
+        core.load_warrior(&warrior).unwrap();
+        core.run(10).unwrap();
+
+        assert_eq!(core.process_queue.thread_count("Warrior0"), 3);
+        assert!(matches!(
+            Core::new(8000).unwrap().with_max_processes(0),
+            Err(Error::InvalidMaxProcesses)
+        ));
+    }
+
+    #[test]
+    fn next_round_keeps_pspace() {
+        let imp = corewars_parser::parse(";name Imp\nmov 0, 1").unwrap();
+        let bomb = corewars_parser::parse(";name Bomb\ndat 0, 0").unwrap();
+
+        let mut core = Core::new(8000).unwrap();
+        core.load_warrior(&imp).unwrap();
+        core.load_warrior_at(&bomb, 4000).unwrap();
+        core.run(100).unwrap();
+
+        core.next_round();
+
+        assert_eq!(core.steps_taken(), 0);
+        assert_eq!(core.warriors(), &[] as &[String]);
+        assert_eq!(core.get(0), &Instruction::default());
+
+        core.load_warrior(&imp).unwrap();
+        core.load_warrior_at(&bomb, 4000).unwrap();
+
+        let result = |core: &mut Core, name| {
+            let pspace = core.pspaces.get_mut(name).unwrap();
+            pspace.load(Offset::new(0, 8000)).value()
+        };
+        assert_eq!(result(&mut core, "Imp"), 1);
+        assert_eq!(result(&mut core, "Bomb"), 0);
+    }
 }
@@ -0,0 +1,227 @@
+//! A readable view of a region of a [`Core`](super::Core), e.g. to inspect
+//! memory in the middle of a battle. Unlike the `Display` of a whole core,
+//! each instruction is shown with its address, and fields are signed offsets
+//! like `-1` rather than `CORESIZE - 1`.
+
+use std::convert::TryFrom;
+use std::fmt;
+
+use corewars_core::load_file::{AddressMode, Instruction, Offset, Opcode, Value};
+
+use super::Core;
+
+/// A single instruction in a [`Disassembly`](Disassembly)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Line {
+    /// The absolute address of the instruction, from 0 to `CORESIZE - 1`
+    pub address: u32,
+
+    /// The address relative to the center of the disassembly
+    pub relative: i32,
+
+    /// The label of the instruction, if it is the target of a labelled jump
+    pub label: Option<String>,
+
+    /// The instruction, with each field as a signed offset or a label
+    pub instruction: Instruction,
+}
+
+impl fmt::Display for Line {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        let label = self
+            .label
+            .as_ref()
+            .map(|label| format!("{label}:"))
+            .unwrap_or_default();
+
+        // Example output:
+        // 000123     -1  L1:     MOV.I   $0,     $1
+        write!(
+            formatter,
+            "{:0>6} {:>+6}  {label:<8}{}",
+            self.address, self.relative, self.instruction
+        )
+    }
+}
+
+/// The instructions in a region of a core, in order of their address
+#[derive(Clone, Debug)]
+pub struct Disassembly {
+    lines: Vec<Line>,
+    core_size: u32,
+}
+
+impl Disassembly {
+    /// Label the target of each jump whose A-field is a direct address in
+    /// this region, and use the label in the jump instead of its offset.
+    /// Labels are named `L1`, `L2` and so on, in order of their address.
+    #[must_use]
+    pub fn with_labels(mut self) -> Self {
+        let jumps: Vec<(usize, usize)> = self
+            .lines
+            .iter()
+            .enumerate()
+            .filter_map(|(index, line)| Some((index, self.target(index, line)?)))
+            .collect();
+
+        let mut targets: Vec<usize> = jumps.iter().map(|&(_, target)| target).collect();
+        targets.sort_unstable();
+        targets.dedup();
+
+        for (number, &target) in targets.iter().enumerate() {
+            self.lines[target].label = Some(format!("L{}", number + 1));
+        }
+
+        for (jump, target) in jumps {
+            let label = self.lines[target].label.clone().unwrap_or_default();
+            self.lines[jump].instruction.a_field.value = Value::Label(label);
+        }
+
+        self
+    }
+
+    /// The disassembled instructions
+    #[must_use]
+    pub fn lines(&self) -> &[Line] {
+        &self.lines
+    }
+
+    /// The index of the line which a line jumps to, if it is in this region.
+    /// Jumps through an indirect address aren't followed, since their target
+    /// depends on the rest of the core.
+    fn target(&self, index: usize, line: &Line) -> Option<usize> {
+        let instruction = &line.instruction;
+        let is_jump = matches!(
+            instruction.opcode,
+            Opcode::Jmp | Opcode::Jmz | Opcode::Jmn | Opcode::Djn | Opcode::Spl
+        );
+
+        match (
+            is_jump,
+            instruction.a_field.address_mode,
+            &instruction.a_field.value,
+        ) {
+            (true, AddressMode::Direct, Value::Literal(value)) => {
+                // The region may wrap around the end of the core
+                let index = u32::try_from(index).ok()?;
+                let target = (Offset::new(*value, self.core_size) + index).value() as usize;
+                (target < self.lines.len()).then_some(target)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Disassembly {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        let lines: Vec<String> = self.lines.iter().map(ToString::to_string).collect();
+        write!(formatter, "{}", lines.join("\n"))
+    }
+}
+
+impl Core {
+    /// Disassemble the instructions within `radius` of `address`. A region
+    /// larger than the core includes each instruction only once.
+    #[must_use]
+    pub fn disassemble(&self, address: i32, radius: u32) -> Disassembly {
+        let before = radius.min((self.len() - 1) / 2);
+        let after = radius.min(self.len() - 1 - before);
+        let center = self.offset(address);
+
+        // Both fit in an i32, since they are at most half the core size
+        let lines = (-(before as i32)..=after as i32)
+            .map(|relative| {
+                let offset = center + relative;
+                let mut instruction = self.get_offset(offset).clone();
+
+                for field in [&mut instruction.a_field, &mut instruction.b_field] {
+                    if let Value::Literal(value) = field.value {
+                        field.value = Value::Literal(self.offset(value).signed_value());
+                    }
+                }
+
+                Line {
+                    address: offset.value(),
+                    relative,
+                    label: None,
+                    instruction,
+                }
+            })
+            .collect();
+
+        Disassembly {
+            lines,
+            core_size: self.len(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::super::tests::build_core;
+    use super::*;
+
+    #[test]
+    fn disassembles_region() {
+        let core = build_core("add #4, 3\nmov 2, @2\njmp -2\ndat 0, 0");
+        let disassembly = core.disassemble(1, 2);
+
+        let addresses: Vec<(u32, i32)> = disassembly
+            .lines()
+            .iter()
+            .map(|line| (line.address, line.relative))
+            .collect();
+        assert_eq!(addresses, vec![(7999, -2), (0, -1), (1, 0), (2, 1), (3, 2)]);
+
+        assert_eq!(
+            disassembly.to_string(),
+            "007999     -2          DAT.F   $0,     $0\n\
+             000000     -1          ADD.AB  #4,     $3\n\
+             000001     +0          MOV.I   $2,     @2\n\
+             000002     +1          JMP.B   $-2,    $0\n\
+             000003     +2          DAT.F   $0,     $0"
+        );
+    }
+
+    #[test]
+    fn labels_jump_targets() {
+        let core = build_core("spl 2\njmp 0\njmp -2\njmp @-1\njmp 10");
+        let disassembly = core.disassemble(0, 4).with_labels();
+
+        assert_eq!(
+            disassembly.to_string(),
+            "007996     -4          DAT.F   $0,     $0\n\
+             007997     -3          DAT.F   $0,     $0\n\
+             007998     -2          DAT.F   $0,     $0\n\
+             007999     -1          DAT.F   $0,     $0\n\
+             000000     +0  L1:     SPL.B   $L3,    $0\n\
+             000001     +1  L2:     JMP.B   $L2,    $0\n\
+             000002     +2  L3:     JMP.B   $L1,    $0\n\
+             000003     +3          JMP.B   @-1,    $0\n\
+             000004     +4          JMP.B   $10,    $0"
+        );
+    }
+
+    #[test]
+    fn disassembles_whole_core() {
+        let mut core = Core::new(4).unwrap();
+        core.get_mut(3).opcode = Opcode::Jmp;
+        core.get_mut(3).a_field.value = Value::Literal(1);
+
+        let disassembly = core.disassemble(1, 100).with_labels();
+        let addresses: Vec<u32> = disassembly
+            .lines()
+            .iter()
+            .map(|line| line.address)
+            .collect();
+
+        assert_eq!(addresses, vec![0, 1, 2, 3]);
+        assert_eq!(disassembly.lines()[0].label.as_deref(), Some("L1"));
+        assert_eq!(
+            disassembly.lines()[3].instruction.a_field.value,
+            Value::Label("L1".into())
+        );
+    }
+}
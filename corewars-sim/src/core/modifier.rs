@@ -9,7 +9,7 @@ use super::Core;
 /// This struct maintains the "registers" used for evaluating instructions
 pub(super) struct Executor<'a> {
     core: &'a mut Core,
-    program_counter: Offset,
+    modifier: Modifier,
     a_value: Instruction,
     b_value: Instruction,
     a_ptr: Offset,
@@ -35,9 +35,11 @@ impl<'a> Executor<'a> {
         let b_value = core.get_offset(b_ptr).clone();
         address::apply_b_pointer(core, program_counter, address::EvalTime::Post);
 
+        let modifier = core.get_offset(program_counter).modifier;
+
         Self {
             core,
-            program_counter,
+            modifier,
             a_value,
             b_value,
             a_ptr,
@@ -50,6 +52,15 @@ impl<'a> Executor<'a> {
         self.a_ptr
     }
 
+    /// Treat the `F`, `X` and `I` modifiers like `B`, for opcodes which only
+    /// ever operate on a single field, such as `LDP` and `STP`.
+    pub fn single_field(mut self) -> Self {
+        if matches!(self.modifier, Modifier::F | Modifier::X | Modifier::I) {
+            self.modifier = Modifier::B;
+        }
+        self
+    }
+
     /// Execute a given operation (`FieldOp`) on a given instruction. This is a convenience
     /// shortcut for [`run_on_instructions`](Self::run_on_instructions) without an `InstructionOp`.
     pub fn run_on_fields<FieldOp>(self, field_op: FieldOp)
@@ -71,8 +82,6 @@ impl<'a> Executor<'a> {
         InstructionOp: FnMut(Instruction, Instruction) -> Option<Instruction>,
         OptionalInstructionOp: Into<Option<InstructionOp>>,
     {
-        let instruction = self.core.get_offset(self.program_counter).clone();
-
         let a_value_a_offset = self.core.offset(self.a_value.a_field.unwrap_value());
         let a_value_b_offset = self.core.offset(self.a_value.b_field.unwrap_value());
 
@@ -81,7 +90,7 @@ impl<'a> Executor<'a> {
 
         let b_target = self.core.get_offset_mut(self.b_ptr);
 
-        match instruction.modifier {
+        match self.modifier {
             Modifier::A => {
                 if let Some(res) = field_op(a_value_a_offset, b_value_a_offset) {
                     b_target.a_field.set_value(res);
@@ -110,7 +119,7 @@ impl<'a> Executor<'a> {
                     b_target.b_field.set_value(b_res);
                 }
 
-                if instruction.modifier == Modifier::I {
+                if self.modifier == Modifier::I {
                     if let Some(mut instruction_op) = instruction_op.into() {
                         if let Some(res) = instruction_op(self.a_value, b_target.clone()) {
                             b_target.opcode = res.opcode;
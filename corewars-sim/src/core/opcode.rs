@@ -1,11 +1,13 @@
 //! Opcode-specific logic to run during a simulation step.
 
 use std::cell::Cell;
+use std::mem;
 
 use corewars_core::load_file::{Offset, Opcode};
 
 use super::modifier;
 use super::process;
+use super::pspace;
 use super::Core;
 
 #[derive(Debug)]
@@ -17,10 +19,21 @@ pub struct Executed {
 /// TODO: docstring
 // TODO
 #[allow(clippy::too_many_lines)]
-pub fn execute(core: &mut Core, program_counter: Offset) -> Result<Executed, process::Error> {
+pub fn execute(
+    core: &mut Core,
+    warrior: &str,
+    program_counter: Offset,
+) -> Result<Executed, process::Error> {
     let instruction = core.get_offset(program_counter).clone();
     let opcode = instruction.opcode;
 
+    // The executor borrows the whole core, so P-space is moved out of it while
+    // a P-space opcode runs
+    let mut pspaces = match opcode {
+        Opcode::Ldp | Opcode::Stp => mem::take(&mut core.pspaces),
+        _ => pspace::Table::default(),
+    };
+
     // These are basically just useful constants that some opcodes need to use
     let zero = core.offset(0);
     let skip_one = core.offset(2);
@@ -146,7 +159,24 @@ pub fn execute(core: &mut Core, program_counter: Offset) -> Result<Executed, pro
         }
 
         // P-space opcodes
-        Opcode::Ldp | Opcode::Stp => unimplemented!(),
+        Opcode::Ldp | Opcode::Stp => {
+            let pspace = pspaces
+                .get_mut(warrior)
+                .expect("P-space should be allocated when a warrior is loaded");
+
+            if opcode == Opcode::Ldp {
+                executor
+                    .single_field()
+                    .run_on_fields(|a, _b| Some(pspace.load(a)));
+            } else {
+                executor.single_field().run_on_fields(|a, b| {
+                    pspace.store(b, a);
+                    None
+                });
+            }
+
+            core.pspaces = pspaces;
+        }
     }
 
     Ok(Executed {
@@ -166,6 +196,9 @@ mod tests {
 
     use test_case::test_case;
 
+    /// The name given to warriors without a `;name` by [`build_core`]
+    const WARRIOR: &str = "Warrior0";
+
     mod process {
 
         use super::*;
@@ -176,7 +209,7 @@ mod tests {
         fn execute_dat() {
             let mut core = build_core("dat #0, #0");
             let pc = core.offset(0);
-            let err = execute(&mut core, pc).unwrap_err();
+            let err = execute(&mut core, WARRIOR, pc).unwrap_err();
             assert_eq!(err, Error::ExecuteDat(pc));
         }
 
@@ -185,7 +218,7 @@ mod tests {
             let mut core = build_core("dat >1, >2");
             let pc = core.offset(0);
 
-            let err = execute(&mut core, pc).unwrap_err();
+            let err = execute(&mut core, WARRIOR, pc).unwrap_err();
 
             assert_eq!(err, Error::ExecuteDat(pc));
             assert_eq!(
@@ -207,7 +240,7 @@ mod tests {
             };
             let mut core = build_core("mov.i $0, $1");
             let pc = core.offset(0);
-            let result = execute(&mut core, pc).expect("Failed to execute");
+            let result = execute(&mut core, WARRIOR, pc).expect("Failed to execute");
             assert!(result.program_counter_offset.is_none());
 
             assert_eq!(
@@ -225,7 +258,7 @@ mod tests {
         fn execute_nop() {
             let mut core = build_core("nop #0, #0");
             let pc = core.offset(0);
-            let result = execute(&mut core, pc).unwrap();
+            let result = execute(&mut core, WARRIOR, pc).unwrap();
             assert!(result.program_counter_offset.is_none());
         }
     }
@@ -252,7 +285,7 @@ mod tests {
             ));
 
             let pc = core.offset(0);
-            let result = execute(&mut core, pc).unwrap();
+            let result = execute(&mut core, WARRIOR, pc).unwrap();
 
             assert!(result.program_counter_offset.is_none());
 
@@ -276,7 +309,7 @@ mod tests {
             );
 
             let pc = core.offset(0);
-            let result = execute(&mut core, pc).unwrap();
+            let result = execute(&mut core, WARRIOR, pc).unwrap();
 
             assert!(result.program_counter_offset.is_none());
 
@@ -306,7 +339,7 @@ mod tests {
                 ",
             );
             let pc = core.offset(0);
-            let result = execute(&mut core, pc).unwrap();
+            let result = execute(&mut core, WARRIOR, pc).unwrap();
             assert!(result.program_counter_offset.is_none());
 
             assert_eq!(
@@ -342,7 +375,7 @@ mod tests {
 
             core.set(2, divisor);
             let pc = core.offset(0);
-            let err = execute(&mut core, pc).unwrap_err();
+            let err = execute(&mut core, WARRIOR, pc).unwrap_err();
 
             assert_eq!(err, Error::DivideByZero);
             assert_eq!(core.get(2), result);
@@ -358,7 +391,7 @@ mod tests {
                 ",
             );
             let pc = core.offset(0);
-            let result = execute(&mut core, pc).unwrap();
+            let result = execute(&mut core, WARRIOR, pc).unwrap();
             assert!(result.program_counter_offset.is_none());
 
             assert_eq!(
@@ -394,7 +427,7 @@ mod tests {
 
             core.set(2, divisor);
             let pc = core.offset(0);
-            let err = execute(&mut core, pc).unwrap_err();
+            let err = execute(&mut core, WARRIOR, pc).unwrap_err();
 
             assert_eq!(err, Error::DivideByZero);
             assert_eq!(core.get(2), result);
@@ -467,7 +500,7 @@ mod tests {
             let mut core = build_core(program);
             let pc = core.offset(0);
             let expected_offset = expected_offset.map(|o| core.offset(o));
-            let result = execute(&mut core, pc).expect("Error executing opcode");
+            let result = execute(&mut core, WARRIOR, pc).expect("Error executing opcode");
 
             assert_eq!(result.program_counter_offset, expected_offset);
         }
@@ -491,7 +524,7 @@ mod tests {
         fn execute_slt_no_skip(program: &str) {
             let mut core = build_core(program);
             let pc = core.offset(0);
-            let result = execute(&mut core, pc).unwrap();
+            let result = execute(&mut core, WARRIOR, pc).unwrap();
             assert!(result.program_counter_offset.is_none());
         }
 
@@ -505,7 +538,7 @@ mod tests {
                 ",
             );
             let pc = core.offset(0);
-            let result = execute(&mut core, pc).unwrap();
+            let result = execute(&mut core, WARRIOR, pc).unwrap();
             assert_eq!(result.program_counter_offset, Some(core.offset(2)));
         }
     }
@@ -525,7 +558,7 @@ mod tests {
                 ",
             );
             let pc = core.offset(1);
-            let result = execute(&mut core, pc).unwrap();
+            let result = execute(&mut core, WARRIOR, pc).unwrap();
 
             assert_eq!(result.program_counter_offset, None);
             assert_eq!(
@@ -554,7 +587,7 @@ mod tests {
                 ",
             );
             let pc = core.offset(1);
-            let result = execute(&mut core, pc).unwrap();
+            let result = execute(&mut core, WARRIOR, pc).unwrap();
 
             assert_eq!(result.program_counter_offset, Some(core.offset(2)));
             assert_eq!(
@@ -583,7 +616,7 @@ mod tests {
                 ",
             );
             let pc = core.offset(1);
-            let result = execute(&mut core, pc).unwrap();
+            let result = execute(&mut core, WARRIOR, pc).unwrap();
 
             assert_eq!(result.program_counter_offset, None);
         }
@@ -599,7 +632,7 @@ mod tests {
                 ",
             );
             let pc = core.offset(1);
-            let result = execute(&mut core, pc).unwrap();
+            let result = execute(&mut core, WARRIOR, pc).unwrap();
 
             assert_eq!(result.program_counter_offset, Some(core.offset(2)));
         }
@@ -613,7 +646,7 @@ mod tests {
                 ",
             );
             let pc = core.offset(1);
-            let result = execute(&mut core, pc).expect("Failed to execute");
+            let result = execute(&mut core, WARRIOR, pc).expect("Failed to execute");
 
             assert_eq!(result.program_counter_offset, Some(core.offset(3)));
             assert_eq!(
@@ -636,7 +669,7 @@ mod tests {
                 ",
             );
             let pc = core.offset(1);
-            let result = execute(&mut core, pc).expect("Failed to execute");
+            let result = execute(&mut core, WARRIOR, pc).expect("Failed to execute");
 
             assert_eq!(result.program_counter_offset, Some(core.offset(3)));
             assert!(result.should_split);
@@ -663,7 +696,7 @@ mod tests {
             );
 
             let pc = core.offset(1);
-            let result = execute(&mut core, pc).unwrap();
+            let result = execute(&mut core, WARRIOR, pc).unwrap();
 
             assert_eq!(result.program_counter_offset, None);
         }
@@ -680,9 +713,61 @@ mod tests {
             );
 
             let pc = core.offset(1);
-            let result = execute(&mut core, pc).unwrap();
+            let result = execute(&mut core, WARRIOR, pc).unwrap();
 
             assert_eq!(result.program_counter_offset, Some(core.offset(2)));
         }
     }
+
+    mod pspace {
+        use super::*;
+
+        use pretty_assertions::assert_eq;
+
+        #[test]
+        fn execute_stp_then_ldp() {
+            let mut core = build_core(
+                "
+                stp #42, #3
+                ldp #3, $2
+                ldp.f $3, $2
+                dat #0, #0
+                dat #0, #0
+                ",
+            );
+
+            for i in 0..3 {
+                let pc = core.offset(i);
+                let result = execute(&mut core, WARRIOR, pc).expect("Failed to execute");
+                assert_eq!(result.program_counter_offset, None);
+            }
+
+            // The value stored in P-space, then the initial result of the last round,
+            // since `.F` only loads the B-field like `.B`
+            assert_eq!(core.get(3).b_field, Field::immediate(42));
+            assert_eq!(core.get(4).b_field, Field::immediate(7999));
+        }
+
+        #[test]
+        fn share_pspace_by_pin() {
+            let mut core = Core::new(8000).unwrap();
+
+            // Both warriors are loaded at the start of the core, but have their own processes
+            for name in ["first", "second"] {
+                let warrior = corewars_parser::parse(&format!(
+                    ";name {name}\nPIN 1\nstp #5, #1\nldp #1, $1\ndat #0, #0"
+                ))
+                .expect("Failed to parse warrior");
+
+                core.load_warrior(&warrior).expect("Failed to load warrior");
+            }
+
+            let pc = core.offset(0);
+            execute(&mut core, "first", pc).unwrap();
+            let pc = core.offset(1);
+            execute(&mut core, "second", pc).unwrap();
+
+            assert_eq!(core.get(2).b_field, Field::immediate(5));
+        }
+    }
 }
@@ -14,12 +14,13 @@ pub struct Entry {
     pub offset: Offset,
 }
 
-/// A representation of the process queue. This is effectively a simple FIFO queue.
-// TODO enforce size limits based on MAXPROCESSES
+/// A representation of the process queue. Each process has a FIFO queue of
+/// tasks, and processes take turns to execute their next task.
 #[derive(Debug)]
 pub struct Queue {
-    /// The actual offsets enqueued to be executed
-    queue: VecDeque<Entry>,
+    /// The name and tasks of each process, in the order that the processes
+    /// take turns. The process at the front executes next.
+    queue: VecDeque<(String, VecDeque<Entry>)>,
 
     /// A map of process names to the number of tasks each has in the queue.
     /// This is updated whenever instructions are added to/removed from the queue,
@@ -40,45 +41,68 @@ impl Queue {
         }
     }
 
-    /// Get the next offset for execution, removing it from the queue.
+    /// Get the next offset for execution, removing it from the queue. The
+    /// process it belongs to then waits for every other process to take a turn.
     pub fn pop(&mut self) -> Result<Entry, Error> {
-        self.queue
+        // Processes without any tasks have terminated
+        while self
+            .queue
+            .front()
+            .is_some_and(|(_, tasks)| tasks.is_empty())
+        {
+            self.queue.pop_front();
+        }
+
+        let (name, mut tasks) = self.queue.pop_front().ok_or(Error::NoRemainingProcesses)?;
+        let entry = tasks
             .pop_front()
-            .map_or(Err(Error::NoRemainingProcesses), |entry| {
-                let decremented = self.processes[&entry.name].saturating_sub(1);
-                self.processes
-                    .entry(entry.name.clone())
-                    .and_modify(|count| *count = decremented);
-
-                Ok(entry)
-            })
+            .expect("processes without tasks should be removed");
+        self.queue.push_back((name, tasks));
+
+        let decremented = self.processes[&entry.name].saturating_sub(1);
+        self.processes
+            .entry(entry.name.clone())
+            .and_modify(|count| *count = decremented);
+
+        Ok(entry)
     }
 
     /// Get the next offset for execution without modifying the queue.
     // TODO: this should probably just return Option<&ProcessEntry>
     pub fn peek(&self) -> Result<&Entry, Error> {
-        self.queue.get(0).ok_or(Error::NoRemainingProcesses)
+        self.queue
+            .iter()
+            .find_map(|(_, tasks)| tasks.front())
+            .ok_or(Error::NoRemainingProcesses)
     }
 
     /// Add an entry to the process queue. If specified, it will use the given thread ID,
     /// otherwise a new thread ID will be created based on the current number of
     /// threads active for this process name.
     pub fn push(&mut self, process_name: String, offset: Offset, thread: Option<usize>) {
-        let thread_id = thread.map_or_else(
-            || {
-                let entry = self.next_thread_id.entry(process_name.clone()).or_insert(0);
-                let id = *entry;
-                *entry += 1;
-                id
-            },
-            |id| id,
-        );
+        let thread_id = thread.unwrap_or_else(|| {
+            let entry = self.next_thread_id.entry(process_name.clone()).or_insert(0);
+            let id = *entry;
+            *entry += 1;
+            id
+        });
 
-        self.queue.push_back(Entry {
+        let entry = Entry {
             name: process_name.clone(),
             thread: thread_id,
             offset,
-        });
+        };
+
+        match self
+            .queue
+            .iter_mut()
+            .find(|(name, _)| *name == process_name)
+        {
+            Some((_, tasks)) => tasks.push_back(entry),
+            None => self
+                .queue
+                .push_back((process_name.clone(), VecDeque::from(vec![entry]))),
+        }
 
         *self.processes.entry(process_name).or_insert(0) += 1;
     }
@@ -88,6 +112,11 @@ impl Queue {
     pub fn thread_count(&self, name: &str) -> usize {
         self.processes[name]
     }
+
+    /// Whether a process with the given name was ever added to the queue
+    pub fn contains(&self, name: &str) -> bool {
+        self.processes.contains_key(name)
+    }
 }
 
 /// An process-related error occurred
@@ -216,4 +245,26 @@ mod tests {
         );
         assert!(queue.thread_count("p1") > 0);
     }
+
+    #[test]
+    fn queue_alternates_processes() {
+        let mut queue = Queue::new();
+        let offset = Offset::new(0, 8000);
+
+        queue.push("p1".into(), offset, None);
+        queue.push("p1".into(), offset + 1, None);
+        queue.push("p2".into(), offset + 10, None);
+
+        let popped: Vec<(String, usize)> = (0..3)
+            .map(|_| queue.pop().unwrap())
+            .map(|entry| (entry.name, entry.thread))
+            .collect();
+
+        assert_eq!(
+            popped,
+            vec![("p1".into(), 0), ("p2".into(), 0), ("p1".into(), 1)]
+        );
+        assert!(queue.contains("p2"));
+        assert!(!queue.contains("p3"));
+    }
 }
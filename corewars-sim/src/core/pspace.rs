@@ -0,0 +1,145 @@
+//! P-space is a small private memory for each warrior which, unlike the core,
+//! is not cleared between rounds. Warriors declaring the same `PIN` share
+//! a single P-space, so that they can cooperate.
+
+use std::collections::BTreeMap;
+
+use corewars_core::load_file::Offset;
+
+/// The P-space of a single warrior (or several, if they share a `PIN`).
+#[derive(Debug)]
+pub struct PSpace {
+    cells: Box<[Offset]>,
+}
+
+impl PSpace {
+    fn new(size: u32, core_size: u32) -> Self {
+        let mut cells = vec![Offset::new(0, core_size); size as usize].into_boxed_slice();
+
+        // Location 0 holds the result of the previous round, or -1 before the first
+        cells[0] = Offset::new(-1, core_size);
+
+        Self { cells }
+    }
+
+    /// Read the value at an index, which wraps around the size of P-space.
+    pub fn load(&self, index: Offset) -> Offset {
+        self.cells[index.value() as usize % self.cells.len()]
+    }
+
+    /// Write a value at an index, which wraps around the size of P-space.
+    pub fn store(&mut self, index: Offset, value: Offset) {
+        let len = self.cells.len();
+        self.cells[index.value() as usize % len] = value;
+    }
+}
+
+/// The P-spaces allocated to all warriors loaded into a core.
+#[derive(Debug, Default)]
+pub struct Table {
+    /// The number of cells in each P-space (`PSPACESIZE`)
+    size: u32,
+
+    /// Each distinct P-space that has been allocated
+    spaces: Vec<PSpace>,
+
+    /// The index into `spaces` for each warrior, by name
+    warriors: BTreeMap<String, usize>,
+
+    /// The index into `spaces` for each `PIN` that has been used
+    pins: BTreeMap<i32, usize>,
+}
+
+impl Table {
+    /// Create an empty table, which allocates P-spaces with `size` cells.
+    pub fn new(size: u32) -> Self {
+        Self {
+            size,
+            ..Self::default()
+        }
+    }
+
+    /// Allocate P-space for a warrior. If it has a `PIN` which another warrior
+    /// has already used, the existing P-space is shared instead. A warrior
+    /// which already has P-space from a previous round keeps it.
+    pub fn allocate(&mut self, warrior: &str, pin: Option<i32>, core_size: u32) {
+        if self.warriors.contains_key(warrior) {
+            return;
+        }
+
+        let existing = pin.and_then(|pin| self.pins.get(&pin).copied());
+
+        let index = existing.unwrap_or_else(|| {
+            self.spaces.push(PSpace::new(self.size, core_size));
+            self.spaces.len() - 1
+        });
+
+        if let Some(pin) = pin {
+            self.pins.insert(pin, index);
+        }
+        self.warriors.insert(warrior.to_owned(), index);
+    }
+
+    /// Get the P-space allocated to a warrior.
+    pub fn get_mut(&mut self, warrior: &str) -> Option<&mut PSpace> {
+        let index = *self.warriors.get(warrior)?;
+        Some(&mut self.spaces[index])
+    }
+
+    /// Store the result of a round in location 0 of a warrior's P-space.
+    pub fn set_result(&mut self, warrior: &str, result: Offset) {
+        if let Some(pspace) = self.get_mut(warrior) {
+            pspace.cells[0] = result;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn wraps_index() {
+        let mut table = Table::new(4);
+        table.allocate("imp", None, 8000);
+
+        let pspace = table.get_mut("imp").unwrap();
+        assert_eq!(pspace.load(Offset::new(0, 8000)), Offset::new(-1, 8000));
+
+        pspace.store(Offset::new(5, 8000), Offset::new(42, 8000));
+        assert_eq!(pspace.load(Offset::new(1, 8000)), Offset::new(42, 8000));
+    }
+
+    #[test]
+    fn shares_by_pin() {
+        let mut table = Table::new(16);
+        table.allocate("first", Some(7), 8000);
+        table.allocate("second", Some(7), 8000);
+        table.allocate("third", None, 8000);
+
+        let index = Offset::new(3, 8000);
+        let value = Offset::new(10, 8000);
+        table.get_mut("first").unwrap().store(index, value);
+
+        assert_eq!(table.get_mut("second").unwrap().load(index), value);
+        assert_eq!(
+            table.get_mut("third").unwrap().load(index),
+            Offset::new(0, 8000)
+        );
+    }
+
+    #[test]
+    fn keeps_pspace_between_rounds() {
+        let mut table = Table::new(4);
+        table.allocate("imp", None, 8000);
+        table.set_result("imp", Offset::new(2, 8000));
+
+        table.allocate("imp", None, 8000);
+        assert_eq!(
+            table.get_mut("imp").unwrap().load(Offset::new(0, 8000)),
+            Offset::new(2, 8000)
+        );
+    }
+}
@@ -6,4 +6,4 @@
 mod core;
 
 // Re-exports
-pub use crate::core::Core;
+pub use crate::core::{disassembly, Core};
@@ -6,7 +6,7 @@ use pretty_assertions::assert_eq;
 use test_generator::test_resources;
 
 use corewars_core::load_file::PseudoOpcode;
-use corewars_parser::Result as ParseResult;
+use corewars_parser::{FormatOptions, Result as ParseResult};
 
 #[test_resources("testdata/input/simple/*.redcode")]
 #[test_resources("testdata/input/wilkie/*.redcode")]
@@ -29,7 +29,7 @@ fn read_dir(input_file: &str) {
 
     let parsed_warrior = match corewars_parser::parse(&input) {
         ParseResult::Ok(core, _) => core,
-        ParseResult::Err(e, _) => panic!("Parse error:\n{}", e),
+        ParseResult::Err(e, _) => panic!("Parse error:\n{:?}", e),
     };
 
     let mut core = corewars_sim::Core::default();
@@ -53,3 +53,85 @@ fn read_dir(input_file: &str) {
 
     assert_eq!(expected_lines, actual_lines);
 }
+
+#[test_resources("testdata/input/simple/*.redcode")]
+#[test_resources("testdata/input/wilkie/*.redcode")]
+#[test_resources("testdata/input/wilmoo/*.redcode")]
+fn round_trip_source(input_file: &str) {
+    let current_dir = Path::new(env!("CARGO_MANIFEST_DIR")).parent().unwrap();
+    std::env::set_current_dir(current_dir).unwrap();
+
+    let input = fs::read_to_string(input_file)
+        .unwrap_or_else(|err| panic!("Unable to read file {:?}: {:?}", input_file, err));
+
+    let tree = corewars_parser::parse_tree(&input);
+    assert_eq!(tree.errors, Vec::new());
+    assert_eq!(tree.to_string(), input);
+
+    // Printing the unexpanded source should not change the assembled program
+    let source = tree.code();
+
+    assert_eq!(
+        corewars_parser::parse(&source).unwrap().program,
+        corewars_parser::parse(&input).unwrap().program,
+    );
+}
+
+#[test_resources("testdata/input/simple/*.redcode")]
+#[test_resources("testdata/input/wilkie/*.redcode")]
+#[test_resources("testdata/input/wilmoo/*.redcode")]
+fn round_trip_format(input_file: &str) {
+    let current_dir = Path::new(env!("CARGO_MANIFEST_DIR")).parent().unwrap();
+    std::env::set_current_dir(current_dir).unwrap();
+
+    let input = fs::read_to_string(input_file)
+        .unwrap_or_else(|err| panic!("Unable to read file {:?}: {:?}", input_file, err));
+
+    let options = FormatOptions::default();
+    let formatted = corewars_parser::format(&input, &options).unwrap();
+
+    // Formatting is stable, and does not change the assembled program
+    assert_eq!(
+        corewars_parser::format(&formatted, &options).unwrap(),
+        formatted
+    );
+    assert_eq!(
+        corewars_parser::parse(&formatted).unwrap().program,
+        corewars_parser::parse(&input).unwrap().program,
+    );
+}
+
+#[test_resources("testdata/input/simple/*.redcode")]
+#[test_resources("testdata/input/wilkie/*.redcode")]
+#[test_resources("testdata/input/wilmoo/*.redcode")]
+fn round_trip_load_file(input_file: &str) {
+    let current_dir = Path::new(env!("CARGO_MANIFEST_DIR")).parent().unwrap();
+    std::env::set_current_dir(current_dir).unwrap();
+
+    let input = fs::read_to_string(input_file)
+        .unwrap_or_else(|err| panic!("Unable to read file {:?}: {:?}", input_file, err));
+
+    // A dumped warrior is read back exactly as it was written
+    let warrior = corewars_parser::parse(&input).unwrap();
+    let dumped = warrior.to_string();
+    let loaded = corewars_parser::parse_load_file(&dumped).unwrap();
+
+    assert_eq!(loaded.to_string(), dumped);
+    assert_eq!(loaded.program.instructions, warrior.program.instructions);
+
+    // So are load files written by pMARS
+    let expected_out_file = PathBuf::from(input_file.replace("input", "expected_output"));
+    let expected_output: String = fs::read_to_string(expected_out_file)
+        .map(|s| normalized(s.trim().chars()).collect())
+        .unwrap_or_else(|err| panic!("Unable to read file {:?}: {:?}", input_file, err));
+
+    let loaded = corewars_parser::parse_load_file(&expected_output).unwrap();
+    assert_eq!(loaded.program.to_string(), expected_output);
+
+    // pMARS adjusts values to be within the core, which doesn't change the code
+    let core_size = corewars_sim::Core::default().len();
+    assert_eq!(
+        loaded.fingerprint(core_size),
+        warrior.fingerprint(core_size)
+    );
+}
@@ -1,20 +1,111 @@
 use std::{
+    convert::TryFrom,
     error::Error,
+    fmt::{self, Display},
     fs,
-    io::{self, Read},
-    path::PathBuf,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
 };
 
 use lazy_static::lazy_static;
+use log::LevelFilter;
+use serde_json::{json, Map, Value};
 use structopt::StructOpt;
 
+use corewars_core::load_file::{Standard, DEFAULT_CONSTANTS};
+use corewars_core::{enum_string, ResolvedWarrior};
 use corewars_parser as parser;
 use corewars_sim::Core;
 
+use config::{Config, RuleSet};
+use pmars::PmarsOptions;
+use rules::{RuleOptions, Rules};
+
+mod config;
+mod pmars;
+mod rules;
+
 lazy_static! {
     static ref IO_SENTINEL: PathBuf = PathBuf::from("-");
 }
 
+enum_string! {
+    /// How results, errors or warnings are printed: as text for people to
+    /// read, or as JSON for other programs
+    #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+    pub enum OutputFormat {
+        Human => "human",
+        Json => "json",
+    }
+}
+
+/// The exit code of the CLI, so that scripts can tell why it failed without
+/// reading its output
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ExitCode {
+    /// The command succeeded. For `run`, the warrior was still running after
+    /// the max number of cycles
+    Success = 0,
+
+    /// Any other error, such as a file which could not be read
+    Error = 1,
+
+    /// The input could not be parsed
+    ParseError = 2,
+
+    /// The warrior could not be loaded into the core
+    LoadError = 3,
+
+    /// Every process of the warrior terminated before the max number of cycles
+    Terminated = 4,
+}
+
+impl ExitCode {
+    /// The exit code for an error from running a command
+    #[must_use]
+    pub fn from_error(error: &(dyn Error + 'static)) -> Self {
+        error
+            .downcast_ref::<Failure>()
+            .map_or(Self::Error, |failure| failure.code)
+    }
+}
+
+impl Display for ExitCode {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.pad(match self {
+            Self::Success => "success",
+            Self::Error => "error",
+            Self::ParseError => "parse_error",
+            Self::LoadError => "load_error",
+            Self::Terminated => "terminated",
+        })
+    }
+}
+
+/// An error which exits the CLI with a specific [`ExitCode`](ExitCode)
+#[derive(Debug)]
+pub struct Failure {
+    pub code: ExitCode,
+    message: String,
+}
+
+impl Failure {
+    fn new(code: ExitCode, message: impl ToString) -> Self {
+        Self {
+            code,
+            message: message.to_string(),
+        }
+    }
+}
+
+impl Display for Failure {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str(&self.message)
+    }
+}
+
+impl Error for Failure {}
+
 #[derive(Debug, StructOpt)]
 #[structopt(rename_all = "kebab")]
 /// Parse, assemble, and save Redcode files
@@ -23,14 +114,108 @@ struct CliOptions {
     #[structopt(subcommand)]
     command: Command,
 
-    /// Print additional details while running
-    // TODO(#26) hook this up to a log level
-    #[structopt(long, short)]
-    verbose: bool,
+    /// Print additional details while running. Use "-vv" to also print each
+    /// step of the simulation, or "-vvv" for everything
+    #[structopt(long, short, parse(from_occurrences))]
+    verbose: u8,
+
+    /// Only print errors, not warnings
+    #[structopt(long, short, conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// How errors and warnings are printed: "human" for annotated source
+    /// snippets, or "json" for one JSON object per line. Defaults to "human"
+    #[structopt(long, possible_values = &["human", "json"])]
+    message_format: Option<OutputFormat>,
+
+    /// How results are printed: "human" for text, or "json" for a single JSON
+    /// object on stdout, which includes any errors and warnings. Defaults to
+    /// "human"
+    #[structopt(long, possible_values = &["human", "json"])]
+    format: Option<OutputFormat>,
+
+    /// The config file to use, instead of the first "corewars.toml" found in
+    /// the current directory or its ancestors
+    #[structopt(long, value_name = "FILE", parse(from_os_str))]
+    config: Option<PathBuf>,
 
-    /// Input file; use "-" to read from stdin
-    #[structopt(parse(from_os_str))]
-    input_file: PathBuf,
+    /// Read the input as a pre-assembled "load file", like the output of
+    /// `dump`, instead of assembling it
+    #[structopt(long)]
+    load_file: bool,
+
+    /// Override a predefined constant such as CORESIZE, e.g. "-D CORESIZE=55440"
+    #[structopt(
+        long = "define",
+        short = "D",
+        value_name = "NAME=VALUE",
+        number_of_values = 1,
+        parse(try_from_str = parse_constant)
+    )]
+    constants: Vec<(String, u32)>,
+
+    /// The Redcode standard to assemble for, overriding any ";redcode-88" or
+    /// ";redcode-94" comment in the input. Defaults to "94" if neither is given
+    #[structopt(long, value_name = "STANDARD", possible_values = &["88", "94"])]
+    redcode: Option<Standard>,
+}
+
+// The warrior files given to a subcommand. This isn't a doc comment, since
+// structopt would use it as the description of every subcommand.
+#[derive(Debug, StructOpt)]
+struct Inputs {
+    /// Input files; use "-" to read from stdin
+    #[structopt(parse(from_os_str), required = true)]
+    input_files: Vec<PathBuf>,
+}
+
+/// The source of a warrior, read from a file or stdin
+struct Input {
+    file_name: String,
+    source: String,
+}
+
+impl Inputs {
+    /// Read every input file, in the order they were given. Files which
+    /// aren't found are looked for in the search paths of `config`.
+    fn read(&self, config: &Config) -> Result<Vec<Input>, Box<dyn Error>> {
+        let stdin_count = self
+            .input_files
+            .iter()
+            .filter(|path| **path == *IO_SENTINEL)
+            .count();
+        if stdin_count > 1 {
+            return Err(r#"stdin ("-") can only be read once"#.into());
+        }
+
+        self.input_files
+            .iter()
+            .map(|path| {
+                let path = config.find_input(path);
+                let (file_name, source) = if path == *IO_SENTINEL {
+                    let mut source = String::new();
+                    io::stdin().read_to_string(&mut source)?;
+                    (String::from("<stdin>"), source)
+                } else {
+                    let source = fs::read_to_string(&path)
+                        .map_err(|err| format!("could not read {}: {err}", path.display()))?;
+                    (path.display().to_string(), source)
+                };
+
+                log::debug!("Read {} bytes from {file_name}", source.len());
+                Ok(Input { file_name, source })
+            })
+            .collect()
+    }
+
+    /// Output can only be saved to a file for a single input, since the
+    /// output for each input would overwrite the last
+    fn check_output_file(&self, output_file: &Path) -> Result<(), Box<dyn Error>> {
+        if self.input_files.len() > 1 && *output_file != *IO_SENTINEL {
+            return Err("--output-file can only be used with a single input file".into());
+        }
+        Ok(())
+    }
 }
 
 #[derive(Debug, StructOpt)]
@@ -38,6 +223,9 @@ enum Command {
     /// Save/print a program in "load file" format
     #[structopt(name = "dump")]
     Dump {
+        #[structopt(flatten)]
+        inputs: Inputs,
+
         /// Output file; defaults to stdout ("-")
         #[structopt(long, short, parse(from_os_str), default_value = IO_SENTINEL.to_str().unwrap())]
         output_file: PathBuf,
@@ -48,80 +236,863 @@ enum Command {
         no_expand: bool,
     },
 
-    /// Run a warrior to completion
+    /// Format a Redcode source file, aligning statements into columns and
+    /// keeping comments
+    #[structopt(name = "fmt")]
+    Fmt {
+        #[structopt(flatten)]
+        inputs: Inputs,
+
+        /// Output file; defaults to stdout ("-")
+        #[structopt(long, short, parse(from_os_str), default_value = IO_SENTINEL.to_str().unwrap())]
+        output_file: PathBuf,
+
+        /// Add the default modifier to instructions which don't have one.
+        /// ICWS '88 warriors are left without modifiers, since '88 doesn't
+        /// allow them
+        #[structopt(long)]
+        explicit_modifiers: bool,
+
+        /// Don't write any output, but fail if the input is not formatted
+        #[structopt(long)]
+        check: bool,
+    },
+
+    /// Check a warrior for common mistakes, such as unused labels or
+    /// unreachable code
+    #[structopt(name = "lint")]
+    Lint {
+        #[structopt(flatten)]
+        inputs: Inputs,
+    },
+
+    /// Run a warrior to completion, or battle several warriors in one core
     #[structopt(name = "run")]
     Run {
-        /// The max number of cycles to run. Defaults to
-        #[structopt(long, short)]
-        max_cycles: Option<usize>,
+        #[structopt(flatten)]
+        inputs: Inputs,
+
+        #[structopt(flatten)]
+        rules: RuleOptions,
+    },
+
+    /// Battle warriors against every warrior in a benchmark, and score them
+    #[structopt(name = "bench")]
+    Bench {
+        #[structopt(flatten)]
+        inputs: Inputs,
+
+        /// The name of a benchmark from the config file, or a directory of
+        /// warriors. Its rules are used unless "--rules" is given
+        #[structopt(long, short, value_name = "BENCHMARK")]
+        suite: String,
+
+        #[structopt(flatten)]
+        rules: RuleOptions,
+    },
+
+    /// Battle warriors like pMARS, with its options and output format
+    #[structopt(name = "pmars-compat")]
+    PmarsCompat {
+        #[structopt(flatten)]
+        inputs: Inputs,
+
+        #[structopt(flatten)]
+        options: PmarsOptions,
     },
 }
 
-pub fn run() -> Result<(), Box<dyn Error>> {
+/// Run the CLI, and report its result or error in the selected format
+#[must_use]
+pub fn run() -> ExitCode {
     let cli_options = CliOptions::from_args();
+    init_logging(cli_options.verbose, cli_options.quiet);
 
-    let mut input = String::new();
-
-    if cli_options.input_file == *IO_SENTINEL {
-        io::stdin().read_to_string(&mut input)?;
-    } else {
-        input = fs::read_to_string(cli_options.input_file)?;
-    }
+    let mut report = Report::new(
+        cli_options.format.unwrap_or(OutputFormat::Human),
+        cli_options.message_format.unwrap_or(OutputFormat::Human),
+    );
+    report.quiet = cli_options.quiet;
 
-    let parsed_core = match parser::parse(input.as_str()) {
-        parser::Result::Ok(warrior, warnings) => {
-            print_warnings(&warnings);
-            Ok(warrior)
+    let result = Config::find(cli_options.config.as_deref()).and_then(|config| {
+        // Options take precedence over the config file
+        if let Some(format) = cli_options.format.or(config.output.format) {
+            report.format = format;
         }
-        parser::Result::Err(err, warnings) => {
-            print_warnings(&warnings);
-            Err(err)
+        if let Some(format) = cli_options.message_format.or(config.output.message_format) {
+            report.message_format = format;
         }
-    }?;
+
+        execute(cli_options, &config, &mut report)
+    });
+    report.finish(&result)
+}
+
+fn execute(
+    cli_options: CliOptions,
+    config: &Config,
+    report: &mut Report,
+) -> Result<ExitCode, Box<dyn Error>> {
+    // The rules for running warriors come from a rule set or preset, then
+    // constants defined with `-D`, then options for individual rules, so
+    // later ones take precedence
+    let (rule_set, rule_constants, fixed_position) = match &cli_options.command {
+        Command::Run { rules, .. } => (
+            rules.name().map(str::to_owned),
+            rules.constants(),
+            rules.fixed_position,
+        ),
+        Command::Bench { rules, suite, .. } => {
+            let name = rules.name().map(str::to_owned);
+            (
+                name.or_else(|| config.benchmark(suite).rules),
+                rules.constants(),
+                rules.fixed_position,
+            )
+        }
+        Command::PmarsCompat { options, .. } => (None, options.constants(), options.fixed_position),
+        _ => (None, Vec::new(), None),
+    };
+    let rule_set = match rule_set {
+        Some(name) => config.rule_set(&name)?,
+        None => RuleSet::default(),
+    };
+
+    let mut settings = rules::settings(&rule_set, &cli_options.constants, rule_constants)?;
+    let fixed_position = fixed_position.or(rule_set.fixed_position);
+    if let Some(standard) = cli_options.redcode {
+        settings = settings.with_standard(standard);
+    }
+    let core_size = settings.constant("CORESIZE").unwrap_or_default();
+    // Warriors may check how many opponents they have, unless the number was
+    // given explicitly
+    let warriors_defined = cli_options
+        .constants
+        .iter()
+        .any(|(name, _)| name == "WARRIORS");
 
     match cli_options.command {
         Command::Dump {
+            inputs,
             output_file,
             no_expand,
         } => {
+            inputs.check_output_file(&output_file)?;
+            let inputs = inputs.read(config)?;
+            let mut files = Vec::new();
+
             if no_expand {
-                unimplemented!()
-            }
+                // Labels don't need to resolve when they are printed as written
+                let trees = report.check_parsed_inputs(&inputs, |source| {
+                    parser::parse_tree(source).into_result()
+                })?;
 
-            if output_file == *IO_SENTINEL {
-                println!("{parsed_core}");
+                for (input, tree) in inputs.iter().zip(trees) {
+                    let text = format!("{}\n", tree.code());
+                    report.output(&output_file, &text)?;
+                    files.push(json!({ "file": input.file_name, "source": text }));
+                }
             } else {
-                fs::write(output_file, format!("{parsed_core}\n"))?;
+                let warriors = parse_warriors(&inputs, cli_options.load_file, &settings, report)?;
+
+                for (input, warrior) in inputs.iter().zip(warriors) {
+                    let text = format!("{warrior}\n");
+                    report.output(&output_file, &text)?;
+                    files.push(json!({
+                        "file": input.file_name,
+                        "load_file": text,
+                        "warrior": serde_json::to_value(&warrior)?,
+                        "fingerprint": format!("{:016x}", warrior.fingerprint(core_size)),
+                    }));
+                }
+            }
+
+            report.result("files", Value::Array(files));
+        }
+        Command::Fmt {
+            inputs,
+            output_file,
+            explicit_modifiers,
+            check,
+        } => {
+            inputs.check_output_file(&output_file)?;
+            let inputs = inputs.read(config)?;
+
+            let mut options = parser::FormatOptions::default();
+            if explicit_modifiers {
+                options = options.with_explicit_modifiers();
+            }
+            if let Some(standard) = cli_options.redcode {
+                options = options.with_standard(standard);
+            }
+
+            let formatted =
+                report.check_parsed_inputs(&inputs, |source| parser::format(source, &options))?;
+
+            let mut files = Vec::new();
+            let mut unformatted = Vec::new();
+
+            for (input, formatted) in inputs.iter().zip(formatted) {
+                if check {
+                    let is_formatted = formatted == input.source;
+                    if !is_formatted {
+                        unformatted.push(input.file_name.as_str());
+                    }
+                    files.push(json!({ "file": input.file_name, "formatted": is_formatted }));
+                } else {
+                    report.output(&output_file, &formatted)?;
+                    files.push(json!({ "file": input.file_name, "formatted": formatted }));
+                }
+            }
+
+            report.result("files", Value::Array(files));
+
+            match unformatted.as_slice() {
+                [] => {}
+                [file_name] => return Err(format!("{file_name} is not formatted").into()),
+                file_names => {
+                    return Err(format!("{} are not formatted", file_names.join(", ")).into())
+                }
+            }
+        }
+        Command::Lint { inputs } => {
+            let inputs = inputs.read(config)?;
+            report.check_parsed_inputs(&inputs, |source| parser::lint(source, settings.clone()))?;
+        }
+        Command::Run { inputs, .. } => {
+            let inputs = inputs.read(config)?;
+
+            if !warriors_defined {
+                settings = settings.with_constant("WARRIORS", u32::try_from(inputs.len())?)?;
+            }
+
+            let warriors = parse_warriors(&inputs, cli_options.load_file, &settings, report)?;
+            let rules = Rules::new(&settings, fixed_position);
+            return battle(&inputs, &warriors, &rules, report);
+        }
+        Command::Bench { inputs, suite, .. } => {
+            let benchmark = config.benchmark(&suite);
+            let opponents = Inputs {
+                input_files: warrior_files(&benchmark.path)?,
             };
+
+            let inputs = inputs.read(config)?;
+            let opponents = opponents.read(config)?;
+
+            // Each warrior battles one opponent at a time
+            if !warriors_defined {
+                settings = settings.with_constant("WARRIORS", 2)?;
+            }
+
+            let warriors = parse_warriors(&inputs, cli_options.load_file, &settings, report)?;
+            let opponent_warriors =
+                parse_warriors(&opponents, cli_options.load_file, &settings, report)?;
+
+            let rules = Rules::new(&settings, fixed_position);
+            return bench(
+                (&inputs, &warriors),
+                (&opponents, &opponent_warriors),
+                &rules,
+                report,
+            );
+        }
+        Command::PmarsCompat { inputs, options } => {
+            if let Some(standard) = options.standard() {
+                settings = settings.with_standard(standard);
+            }
+
+            let inputs = inputs.read(config)?;
+            if !warriors_defined {
+                settings = settings.with_constant("WARRIORS", u32::try_from(inputs.len())?)?;
+            }
+
+            let warriors = parse_warriors(&inputs, cli_options.load_file, &settings, report)?;
+            let rules = Rules::new(&settings, fixed_position);
+            return pmars(&inputs, &warriors, &rules, options.brief, report);
         }
-        Command::Run { max_cycles } => {
-            let mut core = Core::default();
-            core.load_warrior(&parsed_core)?;
-
-            match core.run(max_cycles) {
-                Ok(_) => println!(
-                    "Warrior stopped after {}max of {} cycles",
-                    if max_cycles.is_some() {
-                        "specified "
-                    } else {
-                        ""
-                    },
-                    core.steps_taken()
+    };
+
+    Ok(ExitCode::Success)
+}
+
+/// Assemble every input, or read them as load files
+fn parse_warriors(
+    inputs: &[Input],
+    load_file: bool,
+    settings: &parser::Settings,
+    report: &mut Report,
+) -> Result<Vec<ResolvedWarrior>, Box<dyn Error>> {
+    report.check_parsed_inputs(inputs, |source| {
+        if load_file {
+            parser::parse_load_file(source)
+        } else {
+            parser::parse_with_settings(source, settings.clone())
+        }
+    })
+}
+
+/// How many rounds a warrior won, tied and lost
+#[derive(Clone, Debug, Default)]
+struct Score {
+    wins: u32,
+    ties: u32,
+    losses: u32,
+
+    /// Points scored like pMARS: each round, the warriors which survive share
+    /// (N² - 1) points between them, for N warriors. So with two warriors, a
+    /// win scores 3 and a tie scores 1.
+    points: usize,
+}
+
+impl Display for Score {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            formatter,
+            "{} wins, {} ties, {} losses",
+            self.wins, self.ties, self.losses
+        )
+    }
+}
+
+/// The warrior files in a directory, in order of their names
+fn warrior_files(dir: &Path) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let entries =
+        fs::read_dir(dir).map_err(|err| format!("could not read {}: {err}", dir.display()))?;
+
+    let mut files = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        let extension = path.extension().and_then(|extension| extension.to_str());
+        if path.is_file() && matches!(extension, Some("red" | "redcode")) {
+            files.push(path);
+        }
+    }
+
+    if files.is_empty() {
+        return Err(format!("no warriors found in {}", dir.display()).into());
+    }
+
+    files.sort();
+    Ok(files)
+}
+
+/// The result of running warriors for every round
+struct Fight {
+    /// The core at the end of the last round
+    core: Core,
+    /// The score of each warrior, in the order they were loaded
+    scores: Vec<Score>,
+    /// Why a single warrior stopped executing in the last round, if it did
+    reason: Option<String>,
+}
+
+/// Run warriors in a core until one is left, or every warrior has run for
+/// the max number of cycles, for each round. A warrior which survives a
+/// round alone wins it, and warriors which survive together tie.
+fn fight(warriors: &[(&str, &ResolvedWarrior)], rules: &Rules) -> Result<Fight, Failure> {
+    let load_error = |err: &dyn Display| Failure::new(ExitCode::LoadError, err);
+
+    rules
+        .check(warriors.len())
+        .map_err(|err| load_error(&err))?;
+    for (file_name, warrior) in warriors {
+        if warrior.len() > rules.max_length {
+            let message = format!(
+                "{file_name} has {} instructions, more than MAXLENGTH ({})",
+                warrior.len(),
+                rules.max_length
+            );
+            return Err(load_error(&message));
+        }
+    }
+
+    let mut core = Core::new(rules.core_size)
+        .and_then(|core| core.with_pspace_size(rules.pspace_size))
+        .and_then(|core| core.with_max_processes(rules.max_processes))
+        .map_err(|err| load_error(&err))?;
+
+    let is_battle = warriors.len() > 1;
+    let round_points = warriors.len().pow(2) - 1;
+    let mut scores = vec![Score::default(); warriors.len()];
+    let mut reason = None;
+
+    for round in 0..rules.rounds {
+        if round > 0 {
+            core.next_round();
+        }
+
+        for ((_, warrior), position) in warriors.iter().zip(rules.positions(warriors.len())) {
+            log::debug!("Loading warrior at {position} for round {}", round + 1);
+            core.load_warrior_at(warrior, position)
+                .map_err(|err| load_error(&err))?;
+        }
+
+        reason = core
+            .run(rules.max_cycles as usize)
+            .err()
+            .map(|err| err.to_string());
+
+        let alive = core.warriors_alive();
+        for (score, name) in scores.iter_mut().zip(core.warriors()) {
+            match (core.is_alive(name), alive) {
+                (false, _) => score.losses += 1,
+                (true, 1) if is_battle => score.wins += 1,
+                (true, _) => score.ties += 1,
+            }
+
+            if core.is_alive(name) {
+                score.points += round_points / alive;
+            }
+        }
+
+        log::info!("Core after round {}:\n{core}", round + 1);
+    }
+
+    Ok(Fight {
+        core,
+        scores,
+        reason,
+    })
+}
+
+/// Run warriors, and report the result of the last round and their scores
+fn battle(
+    inputs: &[Input],
+    warriors: &[ResolvedWarrior],
+    rules: &Rules,
+    report: &mut Report,
+) -> Result<ExitCode, Box<dyn Error>> {
+    let named: Vec<(&str, &ResolvedWarrior)> = inputs
+        .iter()
+        .map(|input| input.file_name.as_str())
+        .zip(warriors)
+        .collect();
+    let Fight {
+        core,
+        scores,
+        reason,
+    } = fight(&named, rules)?;
+
+    // Apart from the scores, results are for the last round
+    let is_battle = warriors.len() > 1;
+    let steps = core.steps_taken();
+    let alive: Vec<&str> = core
+        .warriors()
+        .iter()
+        .filter(|name| core.is_alive(name))
+        .map(String::as_str)
+        .collect();
+
+    let warriors_json = inputs
+        .iter()
+        .zip(core.warriors())
+        .zip(&scores)
+        .map(|((input, name), score)| {
+            json!({
+                "file": input.file_name,
+                "name": name,
+                "alive": core.is_alive(name),
+                "wins": score.wins,
+                "ties": score.ties,
+                "losses": score.losses,
+            })
+        })
+        .collect();
+
+    let outcome = match (is_battle, alive.as_slice()) {
+        (true, [_]) => "won",
+        (true, _) => "tie",
+        (false, _) if reason.is_none() => "survived",
+        (false, _) => "terminated",
+    };
+
+    report.result("rounds", json!(rules.rounds));
+    report.result("cycles", json!(steps));
+    report.result("outcome", json!(outcome));
+    report.result("reason", json!(reason));
+    report.result("warriors", Value::Array(warriors_json));
+    if is_battle {
+        let winner = match alive.as_slice() {
+            [winner] => Some(winner),
+            _ => None,
+        };
+        report.result("winner", json!(winner));
+    }
+
+    if report.format == OutputFormat::Human {
+        if rules.rounds > 1 {
+            println!("Results after {} rounds:", rules.rounds);
+            for (name, score) in core.warriors().iter().zip(&scores) {
+                println!("{name}: {score}");
+            }
+        } else {
+            match (is_battle, &reason, alive.as_slice()) {
+                (true, _, [winner]) => println!("{winner} won after {steps} steps"),
+                (true, _, alive) => println!(
+                    "Battle tied after {steps} steps, between {}",
+                    alive.join(", ")
                 ),
-                Err(err) => println!("Warrior failed after {} steps: {err}", core.steps_taken()),
+                (false, None, _) => println!("Warrior stopped after max of {steps} cycles"),
+                (false, Some(reason), _) => {
+                    println!("Warrior failed after {steps} steps: {reason}");
+                }
+            }
+        }
+    }
+
+    if !is_battle && scores[0].losses > 0 {
+        return Ok(ExitCode::Terminated);
+    }
+
+    Ok(ExitCode::Success)
+}
+
+/// Battle each warrior against each opponent in turn, and report how each
+/// warrior scored
+fn bench(
+    (inputs, warriors): (&[Input], &[ResolvedWarrior]),
+    (opponents, opponent_warriors): (&[Input], &[ResolvedWarrior]),
+    rules: &Rules,
+    report: &mut Report,
+) -> Result<ExitCode, Box<dyn Error>> {
+    let mut results = Vec::new();
+
+    for (input, warrior) in inputs.iter().zip(warriors) {
+        let mut total = 0.0;
+        let mut opponents_json = Vec::new();
+
+        for (opponent, opponent_warrior) in opponents.iter().zip(opponent_warriors) {
+            let named = [
+                (input.file_name.as_str(), warrior),
+                (opponent.file_name.as_str(), opponent_warrior),
+            ];
+            // Scaled so that winning every round scores 300
+            let score = fight(&named, rules)?.scores.swap_remove(0);
+            total += score.points as f64 * 100.0 / f64::from(rules.rounds);
+
+            if report.format == OutputFormat::Human {
+                println!("{} vs {}: {score}", input.file_name, opponent.file_name);
+            }
+            opponents_json.push(json!({
+                "file": opponent.file_name,
+                "wins": score.wins,
+                "ties": score.ties,
+                "losses": score.losses,
+            }));
+        }
+
+        let score = total / opponents.len() as f64;
+        if report.format == OutputFormat::Human {
+            println!(
+                "{}: score {score:.1} against {} warriors",
+                input.file_name,
+                opponents.len()
+            );
+        }
+        results.push(json!({
+            "file": input.file_name,
+            "score": score,
+            "opponents": opponents_json,
+        }));
+    }
+
+    report.result("rounds", json!(rules.rounds));
+    report.result("benchmarks", Value::Array(results));
+    Ok(ExitCode::Success)
+}
+
+/// Battle warriors, and report their scores in the same format as pMARS
+fn pmars(
+    inputs: &[Input],
+    warriors: &[ResolvedWarrior],
+    rules: &Rules,
+    brief: bool,
+    report: &mut Report,
+) -> Result<ExitCode, Box<dyn Error>> {
+    let named: Vec<(&str, &ResolvedWarrior)> = inputs
+        .iter()
+        .map(|input| input.file_name.as_str())
+        .zip(warriors)
+        .collect();
+    let Fight { scores, .. } = fight(&named, rules)?;
+
+    // pMARS prints the wins of each warrior, then the number of rounds which
+    // no warrior won. For two warriors, that's the first one's wins, losses
+    // and ties.
+    let mut results: Vec<u32> = scores.iter().map(|score| score.wins).collect();
+    results.push(rules.rounds - results.iter().sum::<u32>());
+
+    // pMARS's names for warriors without a name or author
+    let names = |warrior: &ResolvedWarrior| {
+        let metadata = &warrior.metadata;
+        (
+            metadata.name.as_deref().unwrap_or("Unknown").to_owned(),
+            metadata.author.as_deref().unwrap_or("Anonymous").to_owned(),
+        )
+    };
+
+    let mut warriors_json = Vec::new();
+    for ((input, warrior), score) in inputs.iter().zip(warriors).zip(&scores) {
+        let (name, author) = names(warrior);
+
+        if report.format == OutputFormat::Human && !brief {
+            println!(
+                "Program \"{name}\" (length {}) by \"{author}\"\n\n{warrior}\n",
+                warrior.len()
+            );
+        }
+
+        warriors_json.push(json!({
+            "file": input.file_name,
+            "name": name,
+            "author": author,
+            "score": score.points,
+            "wins": score.wins,
+            "ties": score.ties,
+            "losses": score.losses,
+        }));
+    }
+
+    if report.format == OutputFormat::Human {
+        for (warrior, score) in warriors.iter().zip(&scores) {
+            let (name, author) = names(warrior);
+            println!("{name} by {author} scores {}", score.points);
+        }
+
+        let results: Vec<String> = results.iter().map(ToString::to_string).collect();
+        println!("Results: {}", results.join(" "));
+    }
+
+    report.result("rounds", json!(rules.rounds));
+    report.result("warriors", Value::Array(warriors_json));
+    report.result("results", json!(results));
+    Ok(ExitCode::Success)
+}
+
+/// The results of a command. With human-readable output, these are printed
+/// as they are found, otherwise they are printed as one JSON object at the end.
+struct Report {
+    format: OutputFormat,
+    message_format: OutputFormat,
+    /// Whether human-readable warnings are hidden
+    quiet: bool,
+    warnings: Vec<Value>,
+    errors: Vec<Value>,
+    results: Map<String, Value>,
+}
+
+impl Report {
+    fn new(format: OutputFormat, message_format: OutputFormat) -> Self {
+        Self {
+            format,
+            message_format,
+            quiet: false,
+            warnings: Vec::new(),
+            errors: Vec::new(),
+            results: Map::new(),
+        }
+    }
+
+    /// Add a result to the JSON output
+    fn result(&mut self, key: &str, value: Value) {
+        self.results.insert(key.to_owned(), value);
+    }
+
+    /// Save or print some output text. With JSON output, the text is only
+    /// printed as part of the JSON object, so the caller should add it there.
+    fn output(&self, output_file: &Path, text: &str) -> io::Result<()> {
+        if *output_file != *IO_SENTINEL {
+            fs::write(output_file, text)?;
+        } else if self.format == OutputFormat::Human {
+            print!("{text}");
+        }
+
+        Ok(())
+    }
+
+    /// Parse every input with `parse`, and report the warnings and errors
+    /// of each. Inputs after one with errors are still checked, but the first
+    /// error is returned.
+    fn check_parsed_inputs<T>(
+        &mut self,
+        inputs: &[Input],
+        parse: impl Fn(&str) -> parser::Result<T>,
+    ) -> Result<Vec<T>, Box<dyn Error>> {
+        let mut parsed = Vec::new();
+        let mut first_error = None;
+
+        for input in inputs {
+            match self.check_parsed(parse(&input.source), &input.source, &input.file_name) {
+                Ok(value) => parsed.push(value),
+                Err(err) => {
+                    first_error.get_or_insert(err);
+                }
+            }
+        }
+
+        first_error.map_or(Ok(parsed), Err)
+    }
+
+    /// Report the warnings and errors from parsing, and return the parsed
+    /// value if there were no errors.
+    fn check_parsed<T>(
+        &mut self,
+        result: parser::Result<T>,
+        source: &str,
+        file_name: &str,
+    ) -> Result<T, Box<dyn Error>> {
+        for warning in result.warnings() {
+            self.diagnostic(&parser::Diagnostic::from(warning), source, file_name);
+        }
+
+        match result {
+            parser::Result::Ok(value, _) => Ok(value),
+            parser::Result::Err(errors, _) => {
+                for error in &errors {
+                    self.diagnostic(&parser::Diagnostic::from(error), source, file_name);
+                }
+
+                let reason = match errors.len() {
+                    1 => String::from("previous error"),
+                    count => format!("{count} previous errors"),
+                };
+                let message = format!("could not parse {file_name} due to {reason}");
+                Err(Failure::new(ExitCode::ParseError, message).into())
+            }
+        }
+    }
+
+    fn diagnostic(&mut self, diagnostic: &parser::Diagnostic, source: &str, file_name: &str) {
+        match self.format {
+            OutputFormat::Human => {
+                if !(self.quiet && diagnostic.severity == parser::Severity::Warning) {
+                    print_diagnostic(diagnostic, source, file_name, self.message_format);
+                }
             }
+            OutputFormat::Json => {
+                let json = diagnostic_json(diagnostic, source, file_name);
+                match diagnostic.severity {
+                    parser::Severity::Error => self.errors.push(json),
+                    parser::Severity::Warning => self.warnings.push(json),
+                }
+            }
+        }
+    }
+
+    /// Print the error, or the JSON output if it was requested, and return
+    /// the exit code. A closed stdout (e.g. when piped to `head`) is not an
+    /// error, since whoever closed it does not want the rest of the output.
+    fn finish(self, result: &Result<ExitCode, Box<dyn Error>>) -> ExitCode {
+        let (status, message) = match result {
+            Ok(code) => (*code, None),
+            Err(err) => (ExitCode::from_error(err.as_ref()), Some(err.to_string())),
+        };
+
+        if self.format != OutputFormat::Json {
+            if let Some(message) = message {
+                eprintln!("Error: {message}");
+            }
+            return status;
+        }
 
-            if cli_options.verbose {
-                println!("Core after execution:\n{core}");
+        let mut output = Map::new();
+        output.insert("status".into(), json!(status.to_string()));
+        output.insert("message".into(), json!(message));
+        output.insert("warnings".into(), Value::Array(self.warnings));
+        output.insert("errors".into(), Value::Array(self.errors));
+        output.extend(self.results);
+
+        let mut stdout = io::stdout().lock();
+        match writeln!(stdout, "{}", Value::Object(output)).and_then(|()| stdout.flush()) {
+            Err(err) if err.kind() != io::ErrorKind::BrokenPipe => {
+                eprintln!("Error: {err}");
+                ExitCode::Error
             }
+            _ => status,
         }
+    }
+}
+
+/// Print log messages from the CLI and libraries to stderr. The level is set
+/// by `-v` or `-q`, but can be overridden with the `RUST_LOG` environment
+/// variable, e.g. `RUST_LOG=corewars_parser=debug`.
+fn init_logging(verbose: u8, quiet: bool) {
+    let level = match (quiet, verbose) {
+        (true, _) => LevelFilter::Error,
+        (false, 0) => LevelFilter::Warn,
+        (false, 1) => LevelFilter::Info,
+        (false, 2) => LevelFilter::Debug,
+        (false, _) => LevelFilter::Trace,
     };
 
-    Ok(())
+    env_logger::Builder::new()
+        .filter_level(level)
+        .parse_default_env()
+        .init();
 }
 
-fn print_warnings(warnings: &[parser::Warning]) {
-    for warning in warnings.iter() {
-        eprintln!("Warning: {warning}");
+/// Parse a `NAME=VALUE` definition of a predefined constant.
+fn parse_constant(definition: &str) -> Result<(String, u32), String> {
+    let (name, value) = definition
+        .split_once('=')
+        .ok_or_else(|| format!("expected NAME=VALUE, found {definition:?}"))?;
+
+    if !DEFAULT_CONSTANTS.contains_key(name) {
+        let mut names: Vec<&str> = DEFAULT_CONSTANTS.keys().map(String::as_str).collect();
+        names.sort_unstable();
+        return Err(format!(
+            "unknown constant {name:?}, expected one of: {}",
+            names.join(", ")
+        ));
     }
+
+    let value = value
+        .parse()
+        .map_err(|err| format!("invalid value for {name}: {err}"))?;
+
+    // Check the value now, so that it is reported like any other invalid option
+    parser::Settings::default()
+        .with_constant(name, value)
+        .map_err(|err| {
+            let help = err.help().unwrap_or_default();
+            format!("{err}; {help}")
+        })?;
+
+    Ok((name.to_owned(), value))
+}
+
+fn print_diagnostic(
+    diagnostic: &parser::Diagnostic,
+    source: &str,
+    file_name: &str,
+    format: OutputFormat,
+) {
+    match format {
+        OutputFormat::Human => eprintln!("{}", diagnostic.render(source, file_name)),
+        OutputFormat::Json => eprintln!("{}", diagnostic_json(diagnostic, source, file_name)),
+    }
+}
+
+fn diagnostic_json(diagnostic: &parser::Diagnostic, source: &str, file_name: &str) -> Value {
+    // Lines and columns are one-based, to match the human-readable output
+    let span_json = |span: &parser::Span| {
+        json!({
+            "file": file_name,
+            "line": span.line + 1,
+            "column": span.start + 1,
+            "end_column": span.end + 1,
+        })
+    };
+
+    json!({
+        "severity": diagnostic.severity.to_string(),
+        "message": diagnostic.message,
+        "span": diagnostic.span.as_ref().map(span_json),
+        "expansion": diagnostic.expansion.as_ref().map(|expansion| json!({
+            "label": expansion.label,
+            "span": span_json(&expansion.span),
+        })),
+        "help": diagnostic.help,
+        "rendered": diagnostic.render(source, file_name),
+    })
 }
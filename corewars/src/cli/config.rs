@@ -0,0 +1,283 @@
+//! A project's `corewars.toml`, which keeps settings that would otherwise be
+//! repeated on every command line, such as the rules of a tournament.
+//!
+//! ```toml
+//! # Directories to look for input files in, if they aren't found
+//! search-paths = ["warriors"]
+//!
+//! [output]
+//! format = "json"
+//! message-format = "human"
+//!
+//! # Used with `corewars run --rules tournament`
+//! [rules.tournament]
+//! preset = "94nop"
+//! rounds = 100
+//!
+//! # Used with `corewars bench --suite nano`
+//! [benchmarks.nano]
+//! path = "bench/nano"
+//! rules = "nano"
+//! ```
+
+use std::collections::BTreeMap;
+use std::env;
+use std::error::Error;
+use std::fmt::Display;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use serde::{Deserialize, Deserializer};
+
+use super::rules::Preset;
+use super::{OutputFormat, IO_SENTINEL};
+
+/// The name of the config file, which is looked for in the current directory
+/// and each of its ancestors
+pub const FILE_NAME: &str = "corewars.toml";
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config {
+    /// The directory of the config file. Relative paths in the config are
+    /// relative to this, rather than the current directory.
+    #[serde(skip)]
+    root: PathBuf,
+
+    /// Directories to look for input files in, in order, if they aren't found
+    search_paths: Vec<PathBuf>,
+
+    pub output: Output,
+
+    /// Named rule sets, which can be used like presets
+    rules: BTreeMap<String, RuleSet>,
+
+    /// Named directories of warriors to benchmark against
+    benchmarks: BTreeMap<String, Benchmark>,
+}
+
+/// Defaults for how results are printed, which options override
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Output {
+    #[serde(deserialize_with = "from_str")]
+    pub format: Option<OutputFormat>,
+
+    #[serde(deserialize_with = "from_str")]
+    pub message_format: Option<OutputFormat>,
+}
+
+/// The rules of a battle, based on an optional preset. Each rule which isn't
+/// given comes from the preset, or is the default.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct RuleSet {
+    #[serde(deserialize_with = "from_str")]
+    pub preset: Option<Preset>,
+    pub core_size: Option<u32>,
+    pub max_cycles: Option<u32>,
+    pub max_processes: Option<u32>,
+    pub max_length: Option<u32>,
+    pub min_distance: Option<u32>,
+    pub rounds: Option<u32>,
+    pub fixed_position: Option<u32>,
+}
+
+impl RuleSet {
+    /// The constants for these rules: the preset, then any rules which were
+    /// given, so that later ones take precedence.
+    #[must_use]
+    pub fn constants(&self) -> Vec<(String, u32)> {
+        let rules = [
+            ("CORESIZE", self.core_size),
+            ("MAXCYCLES", self.max_cycles),
+            ("MAXPROCESSES", self.max_processes),
+            ("MAXLENGTH", self.max_length),
+            ("MINDISTANCE", self.min_distance),
+            ("ROUNDS", self.rounds),
+        ];
+
+        self.preset
+            .into_iter()
+            .flat_map(Preset::constants)
+            .map(|(name, value)| (name, Some(value)))
+            .chain(rules)
+            .filter_map(|(name, value)| Some((name.to_owned(), value?)))
+            .collect()
+    }
+}
+
+/// A directory of warriors to benchmark against
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Benchmark {
+    pub path: PathBuf,
+
+    /// The name of a rule set or preset to benchmark with
+    #[serde(default)]
+    pub rules: Option<String>,
+}
+
+impl Config {
+    /// Read the config file at `path`, or else the first one found in the
+    /// current directory or its ancestors. Without a config file, the
+    /// default config is used.
+    pub fn find(path: Option<&Path>) -> Result<Self, Box<dyn Error>> {
+        let path = match path {
+            Some(path) => path.to_owned(),
+            None => {
+                let found = env::current_dir()?
+                    .ancestors()
+                    .map(|dir| dir.join(FILE_NAME))
+                    .find(|path| path.is_file());
+
+                match found {
+                    Some(path) => path,
+                    None => return Ok(Self::default()),
+                }
+            }
+        };
+
+        let text = fs::read_to_string(&path)
+            .map_err(|err| format!("could not read {}: {err}", path.display()))?;
+        let mut config: Self = toml::from_str(&text)
+            .map_err(|err| format!("invalid config file {}: {err}", path.display()))?;
+
+        log::debug!("Read config from {}", path.display());
+        config.root = path.parent().map(Path::to_owned).unwrap_or_default();
+        Ok(config)
+    }
+
+    /// Find an input file. A relative path which doesn't exist is looked for
+    /// in each of the search paths, or else returned as it is.
+    #[must_use]
+    pub fn find_input(&self, path: &Path) -> PathBuf {
+        if *path == *IO_SENTINEL || path.is_absolute() || path.exists() {
+            return path.to_owned();
+        }
+
+        self.search_paths
+            .iter()
+            .map(|dir| self.root.join(dir).join(path))
+            .find(|path| path.is_file())
+            .unwrap_or_else(|| path.to_owned())
+    }
+
+    /// The rule set with the given name, or else the preset with that name
+    pub fn rule_set(&self, name: &str) -> Result<RuleSet, String> {
+        if let Some(rule_set) = self.rules.get(name) {
+            return Ok(rule_set.clone());
+        }
+
+        let preset = Preset::from_str(name).map_err(|_| {
+            let mut names: Vec<String> = Preset::iter_values().map(ToString::to_string).collect();
+            names.extend(self.rules.keys().cloned());
+            format!(
+                "unknown rules {name:?}, expected one of: {}",
+                names.join(", ")
+            )
+        })?;
+
+        Ok(RuleSet {
+            preset: Some(preset),
+            ..RuleSet::default()
+        })
+    }
+
+    /// The benchmark with the given name, or else a benchmark of the
+    /// directory at that path
+    #[must_use]
+    pub fn benchmark(&self, name: &str) -> Benchmark {
+        match self.benchmarks.get(name) {
+            Some(benchmark) => Benchmark {
+                path: self.root.join(&benchmark.path),
+                rules: benchmark.rules.clone(),
+            },
+            None => Benchmark {
+                path: PathBuf::from(name),
+                rules: None,
+            },
+        }
+    }
+}
+
+/// Deserialize an optional value from a string, such as an `enum_string!`
+fn from_str<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+    T::Err: Display,
+{
+    let text = String::deserialize(deserializer)?;
+    text.parse().map(Some).map_err(serde::de::Error::custom)
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn config(text: &str) -> Config {
+        let mut config: Config = toml::from_str(text).unwrap();
+        config.root = PathBuf::from("project");
+        config
+    }
+
+    #[test]
+    fn reads_config() {
+        let config = config(
+            r#"
+            search-paths = ["warriors"]
+
+            [output]
+            format = "json"
+
+            [rules.tournament]
+            preset = "tiny"
+            rounds = 10
+
+            [benchmarks.nano]
+            path = "bench/nano"
+            rules = "nano"
+            "#,
+        );
+
+        assert_eq!(config.output.format, Some(OutputFormat::Json));
+        assert_eq!(config.output.message_format, None);
+
+        let constants = config.rule_set("tournament").unwrap().constants();
+        assert_eq!(constants.first(), Some(&("CORESIZE".to_owned(), 800)));
+        assert_eq!(constants.last(), Some(&("ROUNDS".to_owned(), 10)));
+
+        let benchmark = config.benchmark("nano");
+        assert_eq!(benchmark.path, Path::new("project/bench/nano"));
+        assert_eq!(benchmark.rules.as_deref(), Some("nano"));
+        assert_eq!(config.benchmark("other").path, Path::new("other"));
+    }
+
+    #[test]
+    fn finds_presets() {
+        let config = config("[rules.custom]\ncore-size = 100\n");
+
+        assert_eq!(config.rule_set("nano").unwrap().preset, Some(Preset::Nano));
+        assert_eq!(
+            config.rule_set("huge").unwrap_err(),
+            r#"unknown rules "huge", expected one of: 94nop, tiny, nano, custom"#
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_fields() {
+        let error = toml::from_str::<Config>("[rules.custom]\ncoresize = 100\n").unwrap_err();
+        assert!(
+            error.to_string().contains("unknown field `coresize`"),
+            "{}",
+            error
+        );
+
+        let error = toml::from_str::<Config>("[output]\nformat = \"xml\"\n").unwrap_err();
+        assert!(error.to_string().contains("xml"), "{}", error);
+    }
+}
@@ -0,0 +1,100 @@
+//! Options which match those of [pMARS](http://www.koth.org/pmars/), so that
+//! existing scripts can run `corewars pmars-compat` in place of `pmars`.
+
+use structopt::StructOpt;
+
+use corewars_core::load_file::Standard;
+
+// The options of `pmars-compat`, with the same names and meanings as pMARS.
+// This isn't a doc comment, since structopt would use it as the description
+// of the subcommand.
+#[derive(Debug, StructOpt)]
+pub struct PmarsOptions {
+    /// Rounds to play [default: 1]
+    #[structopt(short = "r", value_name = "ROUNDS")]
+    rounds: Option<u32>,
+
+    /// Size of core [default: 8000]
+    #[structopt(short = "s", value_name = "SIZE")]
+    core_size: Option<u32>,
+
+    /// Cycles until tie [default: 80000]
+    #[structopt(short = "c", value_name = "CYCLES")]
+    max_cycles: Option<u32>,
+
+    /// Max. processes [default: 8000]
+    #[structopt(short = "p", value_name = "PROCESSES")]
+    max_processes: Option<u32>,
+
+    /// Max. warrior length [default: 100]
+    #[structopt(short = "l", value_name = "LENGTH")]
+    max_length: Option<u32>,
+
+    /// Min. warriors distance [default: 100]
+    #[structopt(short = "d", value_name = "DISTANCE")]
+    min_distance: Option<u32>,
+
+    /// Size of P-space [default: 1/16th of the core size]
+    #[structopt(short = "S", value_name = "SIZE")]
+    pspace_size: Option<u32>,
+
+    /// Fixed position of warrior #2
+    #[structopt(short = "F", value_name = "POSITION")]
+    pub fixed_position: Option<u32>,
+
+    /// Brief mode (no source listings)
+    #[structopt(short = "b")]
+    pub brief: bool,
+
+    /// Enforce ICWS'88 rules
+    #[structopt(short = "8")]
+    icws_88: bool,
+}
+
+impl PmarsOptions {
+    /// The constants for the options which were given
+    #[must_use]
+    pub fn constants(&self) -> Vec<(String, u32)> {
+        let options = [
+            ("CORESIZE", self.core_size),
+            ("MAXCYCLES", self.max_cycles),
+            ("MAXPROCESSES", self.max_processes),
+            ("MAXLENGTH", self.max_length),
+            ("MINDISTANCE", self.min_distance),
+            ("PSPACESIZE", self.pspace_size),
+            ("ROUNDS", self.rounds),
+        ];
+
+        options
+            .iter()
+            .filter_map(|&(name, value)| Some((name.to_owned(), value?)))
+            .collect()
+    }
+
+    /// The Redcode standard to assemble for, if one was given
+    #[must_use]
+    pub fn standard(&self) -> Option<Standard> {
+        self.icws_88.then_some(Standard::Icws88)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn reads_pmars_options() {
+        let options =
+            PmarsOptions::from_iter(&["pmars", "-r", "100", "-s", "800", "-b", "-F", "400"]);
+
+        assert_eq!(
+            options.constants(),
+            vec![("CORESIZE".to_owned(), 800), ("ROUNDS".to_owned(), 100)]
+        );
+        assert_eq!(options.fixed_position, Some(400));
+        assert!(options.brief);
+        assert_eq!(options.standard(), None);
+    }
+}
@@ -0,0 +1,340 @@
+//! The rules of a battle, such as the size of the core and how far apart
+//! warriors are loaded. Rules can be given as a preset for one of the
+//! standard hills, and then changed by individual options.
+
+use std::convert::TryFrom;
+
+use structopt::StructOpt;
+
+use corewars_core::enum_string;
+use corewars_parser as parser;
+
+use super::config::RuleSet;
+
+/// How many random positions are tried for each warrior, before falling back
+/// to spacing the warriors evenly
+const MAX_PLACEMENT_TRIES: usize = 1000;
+
+enum_string! {
+    #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+    pub enum Preset {
+        Nop94 => "94nop",
+        Tiny => "tiny",
+        Nano => "nano",
+    }
+}
+
+impl Preset {
+    /// The constants which define the rules of this hill on KOTH.org
+    #[must_use]
+    pub fn constants(self) -> [(&'static str, u32); 6] {
+        let (core_size, max_cycles, max_length, rounds) = match self {
+            Self::Nop94 => (8000, 80_000, 100, 250),
+            Self::Tiny => (800, 8000, 20, 250),
+            Self::Nano => (80, 800, 5, 250),
+        };
+
+        [
+            ("CORESIZE", core_size),
+            ("MAXPROCESSES", core_size),
+            ("MAXCYCLES", max_cycles),
+            ("MAXLENGTH", max_length),
+            ("MINDISTANCE", max_length),
+            ("ROUNDS", rounds),
+        ]
+    }
+}
+
+// Options for the rules of `run` and `bench`. This isn't a doc comment, since
+// structopt would use it as the description of the subcommand.
+#[derive(Debug, StructOpt)]
+pub struct RuleOptions {
+    /// Use the rules of a standard hill ("94nop", "tiny" or "nano"), or a
+    /// rule set from the config file. Any other options, and constants given
+    /// with "-D", override these rules
+    #[structopt(long, value_name = "NAME")]
+    rules: Option<String>,
+
+    /// The number of instructions in the core (CORESIZE)
+    #[structopt(long)]
+    core_size: Option<u32>,
+
+    /// The max number of cycles each warrior runs for (MAXCYCLES)
+    #[structopt(long, short)]
+    max_cycles: Option<u32>,
+
+    /// The max number of processes each warrior may have (MAXPROCESSES)
+    #[structopt(long)]
+    max_processes: Option<u32>,
+
+    /// The max number of instructions in each warrior (MAXLENGTH)
+    #[structopt(long)]
+    max_length: Option<u32>,
+
+    /// The min distance between the start of each warrior (MINDISTANCE)
+    #[structopt(long)]
+    min_distance: Option<u32>,
+
+    /// The number of rounds to run (ROUNDS)
+    #[structopt(long)]
+    rounds: Option<u32>,
+
+    /// Load the second warrior this far after the first, instead of at a
+    /// random position
+    #[structopt(long, value_name = "POSITION")]
+    pub fixed_position: Option<u32>,
+}
+
+impl RuleOptions {
+    /// The name of the rules given with `--rules`
+    #[must_use]
+    pub fn name(&self) -> Option<&str> {
+        self.rules.as_deref()
+    }
+
+    /// The constants for the options which were given
+    #[must_use]
+    pub fn constants(&self) -> Vec<(String, u32)> {
+        let options = [
+            ("CORESIZE", self.core_size),
+            ("MAXCYCLES", self.max_cycles),
+            ("MAXPROCESSES", self.max_processes),
+            ("MAXLENGTH", self.max_length),
+            ("MINDISTANCE", self.min_distance),
+            ("ROUNDS", self.rounds),
+        ];
+
+        options
+            .iter()
+            .filter_map(|&(name, value)| Some((name.to_owned(), value?)))
+            .collect()
+    }
+}
+
+/// The settings for the rules of a rule set or preset, then constants defined
+/// with `-D`, then `options` for individual rules, so later ones take
+/// precedence. Fails if any constant has a value which can't be used.
+pub fn settings(
+    rule_set: &RuleSet,
+    defined: &[(String, u32)],
+    options: Vec<(String, u32)>,
+) -> Result<parser::Settings, parser::Error> {
+    let mut constants = rule_set.constants();
+    constants.extend(defined.iter().cloned());
+    constants.extend(options);
+
+    constants
+        .into_iter()
+        .try_fold(parser::Settings::default(), |settings, (name, value)| {
+            settings.with_constant(name, value)
+        })
+}
+
+/// The rules of a battle, once presets and overrides have been applied
+#[derive(Debug)]
+pub struct Rules {
+    pub core_size: u32,
+    pub pspace_size: u32,
+    pub max_cycles: u32,
+    pub max_processes: u32,
+    pub max_length: u32,
+    pub min_distance: u32,
+    pub rounds: u32,
+    pub fixed_position: Option<u32>,
+}
+
+impl Rules {
+    #[must_use]
+    pub fn new(settings: &parser::Settings, fixed_position: Option<u32>) -> Self {
+        let constant = |name| settings.constant(name).unwrap_or_default();
+
+        Self {
+            core_size: constant("CORESIZE"),
+            pspace_size: constant("PSPACESIZE"),
+            max_cycles: constant("MAXCYCLES"),
+            max_processes: constant("MAXPROCESSES"),
+            max_length: constant("MAXLENGTH"),
+            min_distance: constant("MINDISTANCE"),
+            rounds: constant("ROUNDS"),
+            fixed_position,
+        }
+    }
+
+    /// Check that `count` warriors can battle with these rules.
+    pub fn check(&self, count: usize) -> Result<(), String> {
+        if self.rounds == 0 {
+            return Err("ROUNDS must be at least 1".into());
+        }
+
+        if self.min_distance < self.max_length {
+            return Err(format!(
+                "MINDISTANCE ({}) must be at least MAXLENGTH ({}), so warriors can't overlap",
+                self.min_distance, self.max_length
+            ));
+        }
+
+        if count < 2 {
+            return Ok(());
+        }
+
+        let space = u64::from(self.min_distance) * count as u64;
+        if space > u64::from(self.core_size) {
+            return Err(format!(
+                "{count} warriors can't be loaded {} apart in a core of size {}",
+                self.min_distance, self.core_size
+            ));
+        }
+
+        if let Some(position) = self.fixed_position {
+            let max_position = self.core_size - self.min_distance;
+            if position < self.min_distance || position > max_position {
+                return Err(format!(
+                    "the fixed position must be between {} and {max_position}",
+                    self.min_distance
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Choose where to load `count` warriors for a round. The first warrior
+    /// is loaded at 0, the second at the fixed position if there is one, and
+    /// the rest at random positions at least `min_distance` from each other.
+    #[must_use]
+    pub fn positions(&self, count: usize) -> Vec<u32> {
+        let mut positions = vec![0];
+        positions.extend(self.fixed_position.filter(|_| count > 1));
+
+        while positions.len() < count {
+            let position = (0..MAX_PLACEMENT_TRIES)
+                .map(|_| fastrand::u32(0..self.core_size))
+                .find(|&position| {
+                    positions
+                        .iter()
+                        .all(|&other| self.distance(position, other) >= self.min_distance)
+                });
+
+            match position {
+                Some(position) => positions.push(position),
+                // A core which is nearly full may not have room left for a
+                // random position, but an even spacing always fits
+                None => return self.evenly_spaced(count),
+            }
+        }
+
+        positions
+    }
+
+    /// The distance between two positions, in either direction around the core
+    fn distance(&self, a: u32, b: u32) -> u32 {
+        let distance = a.abs_diff(b);
+        distance.min(self.core_size - distance)
+    }
+
+    fn evenly_spaced(&self, count: usize) -> Vec<u32> {
+        let core_size = u64::from(self.core_size);
+        let count = count as u64;
+
+        (0..count)
+            .map(|i| {
+                let position = core_size * i / count;
+                u32::try_from(position).expect("position should be within the core")
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+
+    use super::super::config::Config;
+    use super::*;
+
+    fn rules(core_size: u32, min_distance: u32, fixed_position: Option<u32>) -> Rules {
+        Rules {
+            core_size,
+            pspace_size: 1,
+            max_cycles: 1,
+            max_processes: 1,
+            max_length: min_distance,
+            min_distance,
+            rounds: 1,
+            fixed_position,
+        }
+    }
+
+    fn read_rules(args: &[&str], defined: &[(String, u32)]) -> Rules {
+        let options = RuleOptions::from_iter(args);
+        let rule_set = Config::default()
+            .rule_set(options.name().unwrap_or("94nop"))
+            .unwrap();
+        let settings = settings(&rule_set, defined, options.constants()).unwrap();
+
+        Rules::new(&settings, options.fixed_position)
+    }
+
+    #[test]
+    fn reads_rules() {
+        let rules = read_rules(&["run", "--rules", "nano", "--fixed-position", "40"], &[]);
+
+        assert_eq!(rules.core_size, 80);
+        assert_eq!(rules.pspace_size, 5);
+        assert_eq!(rules.max_cycles, 800);
+        assert_eq!(rules.max_processes, 80);
+        assert_eq!(rules.max_length, 5);
+        assert_eq!(rules.min_distance, 5);
+        assert_eq!(rules.rounds, 250);
+        assert_eq!(rules.fixed_position, Some(40));
+    }
+
+    #[test]
+    fn overrides_preset() {
+        let args = [
+            "run",
+            "--rules",
+            "nano",
+            "--rounds",
+            "3",
+            "--max-length",
+            "10",
+        ];
+        let defined = [("CORESIZE".to_owned(), 160), ("ROUNDS".to_owned(), 5)];
+        let rules = read_rules(&args, &defined);
+
+        // Options take precedence over `-D`, which takes precedence over the preset
+        assert_eq!(rules.core_size, 160);
+        assert_eq!(rules.max_cycles, 800);
+        assert_eq!(rules.max_length, 10);
+        assert_eq!(rules.min_distance, 5);
+        assert_eq!(rules.rounds, 3);
+    }
+
+    #[test]
+    fn places_warriors_apart() {
+        let nano = rules(80, 5, None);
+
+        for _ in 0..100 {
+            let positions = nano.positions(16);
+            assert_eq!(positions.len(), 16);
+
+            for (i, &a) in positions.iter().enumerate() {
+                for &b in &positions[i + 1..] {
+                    assert!(nano.distance(a, b) >= 5, "{:?}", positions);
+                }
+            }
+        }
+
+        assert_eq!(nano.positions(1), vec![0]);
+        assert_eq!(rules(80, 5, Some(40)).positions(2), vec![0, 40]);
+    }
+
+    #[test]
+    fn checks_rules() {
+        assert_eq!(rules(80, 5, None).check(16), Ok(()));
+        assert!(rules(80, 5, None).check(17).is_err());
+        assert!(rules(80, 5, Some(4)).check(2).is_err());
+        assert_eq!(rules(80, 5, Some(4)).check(1), Ok(()));
+    }
+}
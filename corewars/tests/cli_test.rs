@@ -2,6 +2,7 @@ use std::fs;
 use std::process::Command;
 
 use assert_cmd::prelude::*;
+use assert_fs::prelude::*;
 use lazy_static::lazy_static;
 use normalize_line_endings::normalized;
 use predicates::prelude::*;
@@ -48,8 +49,8 @@ fn dump_stdout() {
     let cmd = Command::cargo_bin(assert_cmd::crate_name!())
         .unwrap()
         .current_dir(env!("CARGO_MANIFEST_DIR"))
-        .arg("../testdata/input/simple/basic.redcode")
         .arg("dump")
+        .arg("../testdata/input/simple/basic.redcode")
         .arg("--output-file")
         .arg("-")
         .assert()
@@ -68,8 +69,8 @@ fn dump_file() {
     Command::cargo_bin(assert_cmd::crate_name!())
         .unwrap()
         .current_dir(env!("CARGO_MANIFEST_DIR"))
-        .arg("../testdata/input/simple/basic.redcode")
         .arg("dump")
+        .arg("../testdata/input/simple/basic.redcode")
         .current_dir(env!("CARGO_MANIFEST_DIR"))
         .arg("--output-file")
         .arg(out_file.path())
@@ -81,3 +82,828 @@ fn dump_file() {
 
     assert_eq!(file_contents, &**EXPECTED_OUT);
 }
+
+#[test]
+fn dump_no_expand() {
+    assert_cmd::Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .arg("dump")
+        .arg("-")
+        .arg("--no-expand")
+        .write_stdin(";name imp\nstep equ 1 ; how far\nstart mov.i 0, step\nend start\n")
+        .assert()
+        .success()
+        .stdout(
+            ";name imp\n\
+            step    EQU     1\n\
+            start   MOV.I   0,      step\n        \
+            END     start\n",
+        );
+}
+
+#[test]
+fn dump_load_file() {
+    assert_cmd::Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .arg("--load-file")
+        .arg("dump")
+        .arg("-")
+        .write_stdin(";name imp\nORG     0\nMOV.I   $0,     $1\n")
+        .assert()
+        .success()
+        .stdout(";name imp\nORG     0\nMOV.I   $0,     $1\n");
+
+    assert_cmd::Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .arg("--load-file")
+        .arg("dump")
+        .arg("-")
+        .write_stdin("start MOV 0, 1\n")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "expected an opcode, found `start`",
+        ));
+}
+
+#[test]
+fn lint() {
+    assert_cmd::Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .arg("lint")
+        .arg("-")
+        .write_stdin("unused mov 0, 1\n       dat 0\n")
+        .assert()
+        .success()
+        .stderr(
+            predicate::str::contains(r#"label "unused" is never used"#)
+                .and(predicate::str::contains("`DAT` is executed")),
+        );
+}
+
+#[test]
+fn fmt() {
+    let input = "start mov 0,1 ;copy\n  jmp start\n";
+    let formatted = "start   MOV     0,      1               ;copy\n        JMP     start\n";
+
+    assert_cmd::Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .arg("fmt")
+        .arg("-")
+        .write_stdin(input)
+        .assert()
+        .success()
+        .stdout(formatted);
+
+    assert_cmd::Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .arg("fmt")
+        .arg("-")
+        .arg("--check")
+        .write_stdin(input)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("<stdin> is not formatted"));
+
+    assert_cmd::Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .arg("fmt")
+        .arg("-")
+        .arg("--check")
+        .write_stdin(formatted)
+        .assert()
+        .success();
+}
+
+#[test]
+fn fmt_explicit_modifiers_88() {
+    let input = ";redcode-88\nmov 0, 1\n";
+    let formatted = ";redcode-88\n        MOV     0,      1\n";
+
+    assert_cmd::Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .arg("fmt")
+        .arg("-")
+        .arg("--explicit-modifiers")
+        .write_stdin(input)
+        .assert()
+        .success()
+        .stdout(formatted);
+
+    assert_cmd::Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .arg("dump")
+        .arg("-")
+        .write_stdin(formatted)
+        .assert()
+        .success();
+
+    assert_cmd::Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .arg("--redcode")
+        .arg("88")
+        .arg("fmt")
+        .arg("-")
+        .arg("--explicit-modifiers")
+        .write_stdin("jmp 0\n")
+        .assert()
+        .success()
+        .stdout("        JMP     0\n");
+}
+
+#[test]
+fn parse_error_diagnostic() {
+    assert_cmd::Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .arg("dump")
+        .arg("-")
+        .write_stdin("mov 0, 1\n  add #1 ; missing B-field\n")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "error: expected additional arguments for ADD opcode\n --> <stdin>:2:3\n",
+        ))
+        .stderr(predicate::str::contains(
+            "2 |   add #1 ; missing B-field\n  |   ^^^^^^\n",
+        ));
+}
+
+#[test]
+fn parse_error_multiple() {
+    assert_cmd::Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .arg("dump")
+        .arg("-")
+        .write_stdin("jmp foo\nmov 0, 1 bar\nadd #1\n")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(" --> <stdin>:1:5\n"))
+        .stderr(predicate::str::contains(" --> <stdin>:2:10\n"))
+        .stderr(predicate::str::contains(" --> <stdin>:3:1\n"))
+        .stderr(predicate::str::contains(
+            "could not parse <stdin> due to 3 previous errors",
+        ));
+}
+
+#[test]
+fn define_constant() {
+    assert_cmd::Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .arg("-D")
+        .arg("CORESIZE=55440")
+        .arg("dump")
+        .arg("-")
+        .write_stdin("mov 0, CORESIZE-1\n")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("MOV.I   $0,     $55439"));
+}
+
+#[test]
+fn define_unknown_constant() {
+    assert_cmd::Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .arg("-D")
+        .arg("CORSIZE=55440")
+        .arg("dump")
+        .arg("-")
+        .write_stdin("dat 0\n")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(r#"unknown constant "CORSIZE""#));
+}
+
+#[test]
+fn define_invalid_constant() {
+    for (definition, expected) in [
+        (
+            "CORESIZE=3000000000",
+            "invalid value 3000000000 for constant CORESIZE",
+        ),
+        ("CORESIZE=0", "invalid value 0 for constant CORESIZE"),
+    ] {
+        assert_cmd::Command::cargo_bin(assert_cmd::crate_name!())
+            .unwrap()
+            .arg("-D")
+            .arg(definition)
+            .arg("dump")
+            .arg("-")
+            .write_stdin("dat 0\n")
+            .assert()
+            .code(1)
+            .stderr(predicate::str::contains(expected));
+    }
+
+    assert_cmd::Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .arg("run")
+        .arg("--core-size")
+        .arg("0")
+        .arg("-")
+        .write_stdin("dat 0\n")
+        .assert()
+        .code(1)
+        .stderr(predicate::str::contains(
+            "invalid value 0 for constant CORESIZE",
+        ));
+}
+
+#[test]
+fn dump_pin() {
+    assert_cmd::Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .arg("dump")
+        .arg("-")
+        .write_stdin("pin 2 * 21\nldp.ab #0, $1\n")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "ORG     0\nPIN     42\nLDP.AB  #0,     $1",
+        ));
+}
+
+#[test]
+fn pin_without_argument() {
+    assert_cmd::Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .arg("dump")
+        .arg("-")
+        .write_stdin("pin\nmov 0, 1\n")
+        .assert()
+        .code(2)
+        .stdout("")
+        .stderr(predicate::str::contains("`PIN` must be given an argument"))
+        .stderr(predicate::str::contains("<stdin>:1:1"));
+}
+
+#[test]
+fn redcode_standard() {
+    let input = ";redcode-88\nmov.ab 0, 1\n";
+
+    assert_cmd::Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .arg("dump")
+        .arg("-")
+        .write_stdin(input)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "error: `.ab` is not part of the ICWS '88 standard",
+        ));
+
+    assert_cmd::Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .arg("--redcode")
+        .arg("94")
+        .arg("dump")
+        .arg("-")
+        .write_stdin(input)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("MOV.AB  $0,     $1"));
+}
+
+#[test]
+fn assertion_failed() {
+    assert_cmd::Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .arg("-D")
+        .arg("CORESIZE=55440")
+        .arg("dump")
+        .arg("-")
+        .write_stdin(";assert CORESIZE == 8000\ndat 0\n")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "error: assertion `CORESIZE == 8000` failed",
+        ))
+        .stderr(predicate::str::contains("1:9"));
+}
+
+#[test]
+fn parse_error_json() {
+    let cmd = assert_cmd::Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .arg("--message-format")
+        .arg("json")
+        .arg("dump")
+        .arg("-")
+        .write_stdin("jmp foo\n")
+        .assert()
+        .failure();
+
+    let stderr = String::from_utf8(cmd.get_output().stderr.clone()).unwrap();
+    let message: serde_json::Value = serde_json::from_str(stderr.lines().next().unwrap())
+        .expect("First line of output should be a JSON diagnostic");
+
+    assert_eq!(message["severity"], "error");
+    assert_eq!(message["message"], r#"no such label "foo""#);
+    assert_eq!(message["span"]["line"], 1);
+    assert_eq!(message["span"]["column"], 5);
+    assert_eq!(message["span"]["end_column"], 8);
+}
+
+#[test]
+fn dump_json() {
+    let cmd = assert_cmd::Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .arg("--format")
+        .arg("json")
+        .arg("dump")
+        .arg("-")
+        .write_stdin(";name imp\nunused mov 0, 1\n")
+        .assert()
+        .success();
+
+    let output: serde_json::Value = serde_json::from_slice(&cmd.get_output().stdout).unwrap();
+
+    assert_eq!(output["status"], "success");
+
+    let file = &output["files"][0];
+    assert_eq!(file["file"], "<stdin>");
+    assert_eq!(
+        file["load_file"],
+        ";name imp\nORG     0\nMOV.I   $0,     $1\n"
+    );
+    assert_eq!(file["warrior"]["metadata"]["name"], "imp");
+    assert_eq!(
+        file["warrior"]["program"]["instructions"][0]["opcode"],
+        "MOV"
+    );
+    assert!(file["fingerprint"].is_string());
+}
+
+#[test]
+fn parse_error_exit_code() {
+    let cmd = assert_cmd::Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .arg("--format")
+        .arg("json")
+        .arg("run")
+        .arg("-")
+        .write_stdin("jmp foo\n")
+        .assert()
+        .code(2);
+
+    let output: serde_json::Value = serde_json::from_slice(&cmd.get_output().stdout).unwrap();
+
+    assert_eq!(output["status"], "parse_error");
+    assert_eq!(output["errors"][0]["message"], r#"no such label "foo""#);
+    assert_eq!(output["errors"][0]["span"]["column"], 5);
+}
+
+#[test]
+fn error_json() {
+    let cmd = assert_cmd::Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .arg("--format")
+        .arg("json")
+        .arg("dump")
+        .arg("does_not_exist.red")
+        .assert()
+        .code(1)
+        .stderr("");
+
+    let output: serde_json::Value = serde_json::from_slice(&cmd.get_output().stdout).unwrap();
+
+    assert_eq!(output["status"], "error");
+    assert!(output["message"].is_string());
+}
+
+#[test]
+fn closed_stdout() {
+    let mut child = Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .arg("--format")
+        .arg("json")
+        .arg("dump")
+        .arg("-")
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    // Close stdout before the output is written, like `| head` does
+    drop(child.stdout.take());
+    std::io::Write::write_all(&mut child.stdin.take().unwrap(), b"mov 0, 1\n").unwrap();
+
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "");
+}
+
+#[test]
+fn run_json() {
+    let cmd = assert_cmd::Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .arg("--format")
+        .arg("json")
+        .arg("run")
+        .arg("-")
+        .arg("--max-cycles")
+        .arg("10")
+        .write_stdin("mov 0, 1\n")
+        .assert()
+        .success();
+
+    let output: serde_json::Value = serde_json::from_slice(&cmd.get_output().stdout).unwrap();
+
+    assert_eq!(output["status"], "success");
+    assert_eq!(output["outcome"], "survived");
+    assert_eq!(output["cycles"], 10);
+    assert_eq!(output["reason"], serde_json::Value::Null);
+}
+
+#[test]
+fn run_exit_codes() {
+    assert_cmd::Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .arg("run")
+        .arg("-")
+        .write_stdin("dat 0\n")
+        .assert()
+        .code(4)
+        .stdout(predicate::str::contains("Warrior failed after 1 steps"));
+
+    assert_cmd::Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .arg("-D")
+        .arg("CORESIZE=1")
+        .arg("run")
+        .arg("-")
+        .write_stdin("mov 0, 1\nmov 0, 1\n")
+        .assert()
+        .code(3)
+        .stderr(predicate::str::contains("too many instructions"));
+}
+
+#[test]
+fn verbosity() {
+    assert_cmd::Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .arg("run")
+        .arg("-")
+        .arg("--max-cycles")
+        .arg("2")
+        .write_stdin("mov 0, 1\n")
+        .assert()
+        .success()
+        .stderr("");
+
+    assert_cmd::Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .arg("-vv")
+        .arg("run")
+        .arg("-")
+        .arg("--max-cycles")
+        .arg("2")
+        .write_stdin("mov 0, 1\n")
+        .assert()
+        .success()
+        .stderr(predicate::str::contains(
+            "Step     1 (t 0): 00001 MOV.I   $0,     $1",
+        ));
+
+    assert_cmd::Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .arg("-q")
+        .arg("lint")
+        .arg("-")
+        .write_stdin("unused mov 0, 1\n")
+        .assert()
+        .success()
+        .stderr("");
+}
+
+#[test]
+fn multiple_files() {
+    let dir = assert_fs::TempDir::new().unwrap();
+    let imp = dir.child("imp.red");
+    imp.write_str(";name Imp\nmov 0, 1\n").unwrap();
+    let broken = dir.child("broken.red");
+    broken.write_str("jmp foo\n").unwrap();
+
+    assert_cmd::Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .arg("dump")
+        .arg(imp.path())
+        .arg("-")
+        .write_stdin(";name Dwarf\ndat 0\n")
+        .assert()
+        .success()
+        .stdout(";name Imp\nORG     0\nMOV.I   $0,     $1\n;name Dwarf\nORG     0\nDAT.F   #0,     $0\n");
+
+    assert_cmd::Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .arg("lint")
+        .arg(broken.path())
+        .arg("-")
+        .write_stdin("jmp bar\n")
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("broken.red:1:5"))
+        .stderr(predicate::str::contains("<stdin>:1:5"));
+
+    assert_cmd::Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .arg("dump")
+        .arg("--output-file")
+        .arg(dir.child("out.red").path())
+        .arg(imp.path())
+        .arg(imp.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "--output-file can only be used with a single input file",
+        ));
+}
+
+#[test]
+fn run_battle() {
+    let dir = assert_fs::TempDir::new().unwrap();
+    let imp = dir.child("imp.red");
+    imp.write_str(";name Imp\nmov 0, 1\n").unwrap();
+
+    assert_cmd::Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .arg("run")
+        .arg(imp.path())
+        .arg("-")
+        .write_stdin(";name Bomb\ndat 0\n")
+        .assert()
+        .success()
+        .stdout("Imp won after 2 steps\n");
+
+    let cmd = assert_cmd::Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .arg("--format")
+        .arg("json")
+        .arg("run")
+        .arg("--max-cycles")
+        .arg("10")
+        .arg(imp.path())
+        .arg("-")
+        .write_stdin(";assert WARRIORS == 2\njmp 0\n")
+        .assert()
+        .success();
+
+    let output: serde_json::Value = serde_json::from_slice(&cmd.get_output().stdout).unwrap();
+
+    assert_eq!(output["outcome"], "tie");
+    assert_eq!(output["winner"], serde_json::Value::Null);
+    assert_eq!(output["cycles"], 20);
+    assert_eq!(output["warriors"][0]["name"], "Imp");
+    assert_eq!(output["warriors"][1]["file"], "<stdin>");
+    assert_eq!(output["warriors"][1]["name"], "Warrior1");
+    assert_eq!(output["warriors"][1]["alive"], true);
+
+    assert_cmd::Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .arg("run")
+        .arg("--max-length")
+        .arg("2")
+        .arg(imp.path())
+        .arg("-")
+        .write_stdin("mov 0, 1\nmov 0, 1\nmov 0, 1\n")
+        .assert()
+        .code(3)
+        .stderr(predicate::str::contains(
+            "<stdin> has 3 instructions, more than MAXLENGTH (2)",
+        ));
+}
+
+#[test]
+fn run_rules() {
+    let dir = assert_fs::TempDir::new().unwrap();
+    let imp = dir.child("imp.red");
+    imp.write_str(";name Imp\n;assert CORESIZE == 80\nmov 0, 1\n")
+        .unwrap();
+
+    assert_cmd::Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .arg("run")
+        .arg("--rules")
+        .arg("nano")
+        .arg("--rounds")
+        .arg("3")
+        .arg("--fixed-position")
+        .arg("40")
+        .arg(imp.path())
+        .arg("-")
+        .write_stdin(";name Bomb\ndat 0\n")
+        .assert()
+        .success()
+        .stdout(
+            "Results after 3 rounds:\n\
+            Imp: 3 wins, 0 ties, 0 losses\n\
+            Bomb: 0 wins, 0 ties, 3 losses\n",
+        );
+
+    let cmd = assert_cmd::Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .arg("-D")
+        .arg("ROUNDS=2")
+        .arg("--format")
+        .arg("json")
+        .arg("run")
+        .arg("--rules")
+        .arg("nano")
+        .arg("--max-cycles")
+        .arg("10")
+        .arg(imp.path())
+        .assert()
+        .success();
+
+    let output: serde_json::Value = serde_json::from_slice(&cmd.get_output().stdout).unwrap();
+
+    assert_eq!(output["rounds"], 2);
+    assert_eq!(output["cycles"], 10);
+    assert_eq!(output["warriors"][0]["ties"], 2);
+
+    assert_cmd::Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .arg("run")
+        .arg("--rules")
+        .arg("nano")
+        .arg("--fixed-position")
+        .arg("78")
+        .arg(imp.path())
+        .arg(imp.path())
+        .assert()
+        .code(3)
+        .stderr(predicate::str::contains(
+            "the fixed position must be between 5 and 75",
+        ));
+}
+
+#[test]
+fn config_file() {
+    let dir = assert_fs::TempDir::new().unwrap();
+    dir.child("warriors/imp.red")
+        .write_str(";name Imp\n;assert CORESIZE == 80\nmov 0, 1\n")
+        .unwrap();
+    dir.child("bench/bomb.red").write_str("dat 0\n").unwrap();
+    dir.child("bench/wait.redcode")
+        .write_str("jmp 0\n")
+        .unwrap();
+    dir.child("bench/README.md")
+        .write_str("Not a warrior\n")
+        .unwrap();
+    dir.child("corewars.toml")
+        .write_str(
+            "search-paths = [\"warriors\"]\n\
+            \n\
+            [output]\n\
+            format = \"json\"\n\
+            \n\
+            [rules.quick]\n\
+            preset = \"nano\"\n\
+            rounds = 2\n\
+            \n\
+            [benchmarks.small]\n\
+            path = \"bench\"\n\
+            rules = \"quick\"\n",
+        )
+        .unwrap();
+
+    let cmd = assert_cmd::Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .current_dir(dir.path())
+        .arg("run")
+        .arg("--rules")
+        .arg("quick")
+        .arg("imp.red")
+        .assert()
+        .success();
+
+    let output: serde_json::Value = serde_json::from_slice(&cmd.get_output().stdout).unwrap();
+
+    assert_eq!(output["rounds"], 2);
+    assert_eq!(output["warriors"][0]["name"], "Imp");
+
+    assert_cmd::Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .current_dir(dir.path())
+        .arg("--format")
+        .arg("human")
+        .arg("run")
+        .arg("--rules")
+        .arg("quick")
+        .arg("--rounds")
+        .arg("3")
+        .arg("imp.red")
+        .assert()
+        .success()
+        .stdout("Results after 3 rounds:\nImp: 0 wins, 3 ties, 0 losses\n");
+
+    let cmd = assert_cmd::Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .current_dir(dir.path())
+        .arg("bench")
+        .arg("--suite")
+        .arg("small")
+        .arg("imp.red")
+        .assert()
+        .success();
+
+    let output: serde_json::Value = serde_json::from_slice(&cmd.get_output().stdout).unwrap();
+    let benchmark = &output["benchmarks"][0];
+
+    assert_eq!(output["rounds"], 2);
+    assert_eq!(benchmark["score"], 200.0);
+    assert_eq!(benchmark["opponents"].as_array().unwrap().len(), 2);
+    assert_eq!(benchmark["opponents"][0]["wins"], 2);
+    assert_eq!(benchmark["opponents"][1]["ties"], 2);
+
+    assert_cmd::Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .current_dir(dir.path())
+        .arg("run")
+        .arg("--rules")
+        .arg("huge")
+        .arg("imp.red")
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains(
+            r#"unknown rules \"huge\", expected one of: 94nop, tiny, nano, quick"#,
+        ));
+
+    let bad_config = dir.child("bad.toml");
+    bad_config
+        .write_str("[rules.quick]\ncoresize = 80\n")
+        .unwrap();
+
+    assert_cmd::Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .arg("--config")
+        .arg(bad_config.path())
+        .arg("lint")
+        .arg("-")
+        .write_stdin("dat 0\n")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("invalid config file"))
+        .stderr(predicate::str::contains("unknown field `coresize`"));
+}
+
+#[test]
+fn pmars_compat() {
+    let dir = assert_fs::TempDir::new().unwrap();
+    let imp = dir.child("imp.red");
+    imp.write_str(";name Imp\n;author A. K. Dewdney\nmov 0, 1\n")
+        .unwrap();
+
+    assert_cmd::Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .arg("pmars-compat")
+        .arg("-r")
+        .arg("5")
+        .arg("-s")
+        .arg("800")
+        .arg("-b")
+        .arg(imp.path())
+        .arg("-")
+        .write_stdin("dat 0\n")
+        .assert()
+        .success()
+        .stdout(
+            "Imp by A. K. Dewdney scores 15\n\
+            Unknown by Anonymous scores 0\n\
+            Results: 5 0 0\n",
+        );
+
+    assert_cmd::Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .arg("pmars-compat")
+        .arg(imp.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with(
+            "Program \"Imp\" (length 1) by \"A. K. Dewdney\"\n",
+        ))
+        .stdout(predicate::str::ends_with(
+            "Imp by A. K. Dewdney scores 0\nResults: 0 1\n",
+        ));
+
+    let cmd = assert_cmd::Command::cargo_bin(assert_cmd::crate_name!())
+        .unwrap()
+        .arg("--format")
+        .arg("json")
+        .arg("pmars-compat")
+        .arg("-r")
+        .arg("2")
+        .arg("-c")
+        .arg("10")
+        .arg(imp.path())
+        .arg("-")
+        .write_stdin("jmp 0\n")
+        .assert()
+        .success();
+
+    let output: serde_json::Value = serde_json::from_slice(&cmd.get_output().stdout).unwrap();
+
+    assert_eq!(output["results"], serde_json::json!([0, 0, 2]));
+    assert_eq!(output["warriors"][0]["score"], 2);
+    assert_eq!(output["warriors"][1]["name"], "Unknown");
+}